@@ -0,0 +1,1008 @@
+/*
+    Turns the command line into Arguments.
+
+    Options can appear anywhere between the positional arguments, like in GNU grep.
+    Short flags can be combined (-inw), and options that take a number accept it attached (-A3) or separate (-A 3).
+    Long options take their value either after "=" (--context=3) or as the next argument (--context 3).
+    Everything after "--" is treated as a positional argument, even if it starts with a dash.
+    The options of the config file (see config.rs) are parsed first, so the command line overrides them.
+*/
+
+use crate::{color::ColorChoice, config::Config, filetype, glob::Glob, Arguments, STDIN_PATH};
+use std::{env, fmt, fs};
+
+// Used when the IGNORE environment variable is not set.
+const DEFAULT_IGNORE: &str = ".git/,target/";
+
+pub const USAGE: &str = "Usage: grep [OPTIONS] <pattern> [path...]
+       grep [OPTIONS] -e <pattern>... [path...]
+       grep [OPTIONS] -f <file> [path...]
+
+Searches each path for lines containing the pattern. Directories are searched recursively.
+If no path is given or a path is \"-\", the standard input is searched.
+
+Options:
+  -e, --regexp <pattern>        Use <pattern> for matching, can be given multiple times
+  -f, --file <file>             Read patterns from <file>, one per line
+  -i, --ignore-case             Ignore case distinctions (or set CASE_INSENSITIVE)
+  -E, --regex                   Interpret <pattern> as a regular expression (or set REGEX)
+  -v, --invert-match            Select lines that do not match
+  -w, --word-regexp             Only match whole words
+  -x, --line-regexp             Only match whole lines
+  -n, --line-number             Prefix each line with its line number
+  -c, --count                   Only print the number of selected lines per file
+  -l, --files-with-matches      Only print the names of files with selected lines
+  -L, --files-without-match     Only print the names of files without selected lines
+  -q, --quiet, --silent         Print nothing, exit with 0 as soon as a line is selected
+  -m, --max-count <N>           Stop reading a file after N selected lines
+  -A, --after-context <N>       Print N lines after each match
+  -B, --before-context <N>      Print N lines before each match
+  -C, --context <N>             Print N lines before and after each match
+  -z, --search-zip              Decompress gzip compressed files while searching them
+  -j, --threads <N>             Search N files at the same time (default: one per CPU)
+  -g, --glob <glob>             Only search the files in directories that match <glob>,
+                                or skip the files and directories that match it if it starts with \"!\"
+  -t, --type <type>             Only search files of <type> in directories, can be given multiple times
+  -T, --type-not <type>         Skip files of <type> in directories
+      --color[=<when>]          Highlight matches: auto (default), always or never
+      --fuzzy <k>               Match substrings with at most k typos (edit distance), best matches first
+      --json                    Print the results as JSON Lines (begin, match, context, end and summary records)
+      --replace <text>          Print selected lines with every match replaced by <text>,
+                                which can refer to capture groups as $1 or ${1}
+      --in-place[=<suffix>]     Write the replacements back into the files instead of printing them,
+                                keeping a copy of each changed file with <suffix> appended to its name
+      --interactive             Browse the results in the terminal, refine the pattern while typing
+                                and print the lines picked with Tab and Enter
+      --no-<flag>               Undo a flag, for example --no-line-number or --no-json,
+                                or unset --replace, --fuzzy or --max-count with --no-replace and so on
+      --case-sensitive          Same as --no-ignore-case
+      --no-config               Ignore the config file
+      --help                    Print this help
+
+Directories are searched without the entries listed in IGNORE (comma separated globs, default: .git/,target/).
+Globs support *, ?, [a-z], [!a-z] and ** for any number of directories, like .gitignore files.
+File types: c, cpp, css, go, html, java, js, json, make, md, markdown, py, rust, sh, toml, ts, txt, yaml.
+
+Defaults can be kept in a config file, GREP_CONFIG_PATH or $XDG_CONFIG_HOME/grep/config (~/.config/grep/config).
+It holds one option per line (like --line-number or -C2), \"ignore = <globs>\" and \"colors = <GREP_COLORS>\".
+The environment variables override the file, and the command line overrides both.
+
+Exit status: 0 if a line was selected, 1 if none was, otherwise the code of the first error:
+2 invalid arguments, 3 path does not exist, 4 path is not a file, 5 reading or writing failed,
+6 invalid regular expression, 7 file is not valid UTF-8.";
+
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    HelpRequested,
+    MissingPattern,
+    UnknownOption(String),
+    MissingValue(String),
+    InvalidValue { option: String, value: String },
+    UnexpectedArgument(String),
+    PatternFile { path: String, message: String },
+    ConflictingOptions(String, String),
+    RequiredOption { option: String, required: String },
+    Config { path: String, message: String },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::HelpRequested => write!(f, "Help was requested"),
+            ParseError::MissingPattern => write!(f, "No argument was provided for the pattern"),
+            ParseError::UnknownOption(option) => write!(f, "Unknown option {option}"),
+            ParseError::MissingValue(option) => write!(f, "Option {option} requires a value"),
+            ParseError::InvalidValue { option, value } => {
+                write!(f, "Invalid value \"{value}\" for option {option}")
+            }
+            ParseError::UnexpectedArgument(argument) => {
+                write!(f, "Unexpected argument \"{argument}\"")
+            }
+            ParseError::PatternFile { path, message } => {
+                write!(f, "Could not read patterns from {path}: {message}")
+            }
+            ParseError::ConflictingOptions(first, second) => {
+                write!(f, "Options {first} and {second} can not be used together")
+            }
+            ParseError::RequiredOption { option, required } => {
+                write!(f, "Option {option} requires {required}")
+            }
+            ParseError::Config { path, message } => {
+                write!(f, "Error in config file {path}: {message}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+// Expects the arguments without the program name.
+pub fn parse_arguments(args: Vec<String>) -> Result<Arguments, ParseError> {
+    // The config has to be known before parsing, so --no-config is looked for up front, like ripgrep does.
+    let no_config = args
+        .iter()
+        .take_while(|argument| *argument != "--")
+        .any(|argument| argument == "--no-config");
+    let config = match no_config {
+        true => Config::default(),
+        false => Config::load()?,
+    };
+
+    parse_arguments_with_config(args, &config)
+}
+
+pub fn parse_arguments_with_config(
+    args: Vec<String>,
+    config: &Config,
+) -> Result<Arguments, ParseError> {
+    let mut arguments = Arguments::default();
+    for option in &config.options {
+        apply_config_option(&mut arguments, option).map_err(|error| ParseError::Config {
+            path: config.path.display().to_string(),
+            message: error.to_string(),
+        })?;
+    }
+    // The environment variables override the config file, and flags on the command line override both.
+    arguments.case_insensitive |= env::var("CASE_INSENSITIVE").is_ok();
+    arguments.regex |= env::var("REGEX").is_ok();
+
+    let mut positionals = Vec::new();
+    // Set by -e and -f, even if the pattern file turns out to be empty.
+    let mut patterns_given = false;
+    // The options set on the command line, by the name check_conflicts reports them with.
+    let mut given = Vec::new();
+    let mut iterator = args.into_iter();
+
+    while let Some(argument) = iterator.next() {
+        if argument == "--" {
+            positionals.extend(iterator.by_ref());
+            break;
+        }
+
+        let options = if let Some(long) = argument.strip_prefix("--") {
+            let name = long.split('=').next().unwrap_or(long);
+            given.push(conflict_name(name));
+            parse_long_option(&mut arguments, long, &mut iterator)?
+        } else if argument.len() > 1 && argument.starts_with('-') {
+            let options = parse_short_options(&mut arguments, &argument[1..], &mut iterator)?;
+            for option in &options {
+                if let Some((long, _)) = LONG_OPTIONS.iter().find(|(_, short)| short == option) {
+                    given.push(conflict_name(long));
+                }
+            }
+            options
+        } else {
+            positionals.push(argument);
+            continue;
+        };
+        patterns_given |= options.contains(&'e') || options.contains(&'f');
+    }
+
+    check_conflicts(&mut arguments, &given)?;
+    if arguments.in_place && arguments.replace.is_none() {
+        return Err(ParseError::RequiredOption {
+            option: "--in-place".to_string(),
+            required: "--replace".to_string(),
+        });
+    }
+
+    let mut positionals = positionals.into_iter();
+    if !patterns_given {
+        arguments.patterns = vec![positionals.next().ok_or(ParseError::MissingPattern)?];
+    }
+    arguments.paths = positionals.collect();
+    if arguments.paths.is_empty() {
+        arguments.paths.push(STDIN_PATH.to_string());
+    }
+
+    let ignore = env::var("IGNORE").ok().or(config.ignore.clone());
+    arguments.ignore = Glob::parse_list(ignore.as_deref().unwrap_or(DEFAULT_IGNORE));
+    arguments.colors = env::var("GREP_COLORS")
+        .ok()
+        .or(config.colors.clone())
+        .unwrap_or_default();
+
+    Ok(arguments)
+}
+
+/*
+    Every option of the config file is a single argument, so a value can not be taken from the next line.
+    Patterns only come from the command line, a pattern in the config would hide the one searched for.
+*/
+fn apply_config_option(arguments: &mut Arguments, option: &str) -> Result<(), ParseError> {
+    let mut rest = std::iter::empty();
+    let options = match option.strip_prefix("--") {
+        Some(long) => parse_long_option(arguments, long, &mut rest),
+        None => parse_short_options(arguments, &option[1..], &mut rest),
+    };
+
+    match options {
+        Ok(options) if options.contains(&'e') || options.contains(&'f') => {
+            Err(ParseError::UnexpectedArgument(option.to_string()))
+        }
+        Err(ParseError::HelpRequested) => Err(ParseError::UnexpectedArgument(option.to_string())),
+        result => result.map(|_| ()),
+    }
+}
+
+/*
+    --json prints every selected line as it is, which contradicts only printing counts, names or replacements,
+    or printing nothing at all. -l and -L list the opposite files.
+    --interactive prints the lines picked in the browser, so it only goes along with options that select lines.
+    --in-place prints nothing at all, inverted lines have no matches that could be replaced,
+    and compressed files would be written back decompressed.
+    --fuzzy compares literal text, and its ranking reorders lines, which would tear context lines apart.
+
+    When only one option of a conflicting pair was given on the command line, the other one comes from the config
+    file or the environment and is dropped, so -c on the command line overrides --json in the config.
+*/
+fn check_conflicts(arguments: &mut Arguments, given: &[String]) -> Result<(), ParseError> {
+    while let Some(((_, first), (_, second))) = conflicts(arguments)
+        .into_iter()
+        .find(|((first, _), (second, _))| *first && *second)
+    {
+        let overridden = match (
+            given.iter().any(|name| name == first),
+            given.iter().any(|name| name == second),
+        ) {
+            (true, false) => second,
+            (false, true) => first,
+            _ => {
+                return Err(ParseError::ConflictingOptions(
+                    first.to_string(),
+                    second.to_string(),
+                ))
+            }
+        };
+
+        match overridden {
+            "--context" => {
+                arguments.before_context = 0;
+                arguments.after_context = 0;
+            }
+            _ => {
+                negate_long_option(arguments, &overridden[2..], overridden.to_string())?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+type Conflict = ((bool, &'static str), (bool, &'static str));
+
+fn conflicts(arguments: &Arguments) -> [Conflict; 22] {
+    let json = (arguments.json, "--json");
+    let in_place = (arguments.in_place, "--in-place");
+    let fuzzy = (arguments.fuzzy.is_some(), "--fuzzy");
+    let replace = (arguments.replace.is_some(), "--replace");
+    let count = (arguments.count, "--count");
+    let names = (arguments.files_with_matches, "--files-with-matches");
+    let without = (arguments.files_without_match, "--files-without-match");
+    let quiet = (arguments.quiet, "--quiet");
+    let interactive = (arguments.interactive, "--interactive");
+    let context = (
+        arguments.before_context > 0 || arguments.after_context > 0,
+        "--context",
+    );
+
+    [
+        (json, count),
+        (json, names),
+        (json, without),
+        (json, quiet),
+        (json, replace),
+        (names, without),
+        (interactive, json),
+        (interactive, count),
+        (interactive, names),
+        (interactive, without),
+        (interactive, quiet),
+        (interactive, replace),
+        (in_place, count),
+        (in_place, names),
+        (in_place, without),
+        (in_place, quiet),
+        (in_place, (arguments.invert, "--invert-match")),
+        (in_place, (arguments.decompress, "--search-zip")),
+        (fuzzy, (arguments.regex, "--regex")),
+        (fuzzy, (arguments.word, "--word-regexp")),
+        (fuzzy, (arguments.line, "--line-regexp")),
+        (fuzzy, context),
+    ]
+}
+
+// The name of a long option in check_conflicts: -q is --quiet even when given as --silent, -A and -B are --context.
+fn conflict_name(name: &str) -> String {
+    match LONG_OPTIONS.iter().find(|(long, _)| *long == name) {
+        Some((_, 'A' | 'B' | 'C')) => "--context".to_string(),
+        Some((_, short)) => {
+            let (long, _) = LONG_OPTIONS
+                .iter()
+                .find(|(_, other)| other == short)
+                .unwrap();
+            format!("--{long}")
+        }
+        None => format!("--{name}"),
+    }
+}
+
+// Long options are translated to their short form, so both share the same code.
+const LONG_OPTIONS: &[(&str, char)] = &[
+    ("ignore-case", 'i'),
+    ("regex", 'E'),
+    ("regexp", 'e'),
+    ("file", 'f'),
+    ("invert-match", 'v'),
+    ("word-regexp", 'w'),
+    ("line-regexp", 'x'),
+    ("line-number", 'n'),
+    ("count", 'c'),
+    ("files-with-matches", 'l'),
+    ("files-without-match", 'L'),
+    ("quiet", 'q'),
+    ("silent", 'q'),
+    ("max-count", 'm'),
+    ("after-context", 'A'),
+    ("before-context", 'B'),
+    ("context", 'C'),
+    ("threads", 'j'),
+    ("search-zip", 'z'),
+    ("glob", 'g'),
+    ("type", 't'),
+    ("type-not", 'T'),
+];
+
+fn takes_value(option: char) -> bool {
+    matches!(
+        option,
+        'e' | 'f' | 'm' | 'A' | 'B' | 'C' | 'j' | 'g' | 't' | 'T'
+    )
+}
+
+// Returns the options that were set, in their short form.
+fn parse_long_option(
+    arguments: &mut Arguments,
+    long: &str,
+    rest: &mut impl Iterator<Item = String>,
+) -> Result<Vec<char>, ParseError> {
+    let (name, value) = match long.split_once('=') {
+        Some((name, value)) => (name, Some(value.to_string())),
+        None => (long, None),
+    };
+    let option = format!("--{name}");
+
+    if name == "help" {
+        return Err(ParseError::HelpRequested);
+    }
+    // Already handled by parse_arguments, before the config was read.
+    if name == "no-config" && value.is_none() {
+        return Ok(Vec::new());
+    }
+    // Like in GNU grep, the value of --color is optional and has to be attached with "=".
+    if name == "color" || name == "colour" {
+        arguments.color = match value {
+            Some(value) => value
+                .parse::<ColorChoice>()
+                .map_err(|_| ParseError::InvalidValue { option, value })?,
+            None => ColorChoice::Auto,
+        };
+        return Ok(Vec::new());
+    }
+    /*
+        Every flag has a --no- form that undoes it, so the command line can turn off what the config file turned on.
+        Of the options with a value, only those that are off by default can be unset.
+    */
+    if let Some(negated) = name.strip_prefix("no-") {
+        if value.is_some() {
+            return Err(ParseError::UnexpectedArgument(format!("{option}=")));
+        }
+        return negate_long_option(arguments, negated, option);
+    }
+    if name == "case-sensitive" && value.is_none() {
+        arguments.case_insensitive = false;
+        return Ok(Vec::new());
+    }
+    if name == "json" || name == "interactive" {
+        if value.is_some() {
+            return Err(ParseError::UnexpectedArgument(format!("{option}=")));
+        }
+        match name {
+            "json" => arguments.json = true,
+            _ => arguments.interactive = true,
+        }
+        return Ok(Vec::new());
+    }
+    if name == "fuzzy" {
+        let value = value.or_else(|| rest.next());
+        let value = value.ok_or(ParseError::MissingValue(option.clone()))?;
+        arguments.fuzzy = Some(parse_number(&option, &value)?);
+        return Ok(Vec::new());
+    }
+    if name == "replace" {
+        let value = value.or_else(|| rest.next());
+        arguments.replace = Some(value.ok_or(ParseError::MissingValue(option))?);
+        return Ok(Vec::new());
+    }
+    // Like the value of --color, the backup suffix is optional and has to be attached with "=".
+    if name == "in-place" {
+        arguments.in_place = true;
+        arguments.backup_suffix = value.filter(|suffix| !suffix.is_empty());
+        return Ok(Vec::new());
+    }
+    let short = match LONG_OPTIONS.iter().find(|(long, _)| *long == name) {
+        Some((_, short)) => *short,
+        None => return Err(ParseError::UnknownOption(option)),
+    };
+
+    if !takes_value(short) {
+        if value.is_some() {
+            return Err(ParseError::UnexpectedArgument(format!("{option}=")));
+        }
+        set_flag(arguments, short, true)?;
+        return Ok(vec![short]);
+    }
+
+    let value = match value.or_else(|| rest.next()) {
+        Some(value) => value,
+        None => return Err(ParseError::MissingValue(option)),
+    };
+    set_value(arguments, short, &option, value)?;
+
+    Ok(vec![short])
+}
+
+fn negate_long_option(
+    arguments: &mut Arguments,
+    name: &str,
+    option: String,
+) -> Result<Vec<char>, ParseError> {
+    match name {
+        "json" => arguments.json = false,
+        "interactive" => arguments.interactive = false,
+        "in-place" => {
+            arguments.in_place = false;
+            arguments.backup_suffix = None;
+        }
+        "replace" => arguments.replace = None,
+        "fuzzy" => arguments.fuzzy = None,
+        "max-count" => arguments.max_count = None,
+        _ => match LONG_OPTIONS.iter().find(|(long, _)| *long == name) {
+            Some((_, short)) if !takes_value(*short) => set_flag(arguments, *short, false)?,
+            _ => return Err(ParseError::UnknownOption(option)),
+        },
+    }
+
+    Ok(Vec::new())
+}
+
+// Returns the options that were set.
+fn parse_short_options(
+    arguments: &mut Arguments,
+    flags: &str,
+    rest: &mut impl Iterator<Item = String>,
+) -> Result<Vec<char>, ParseError> {
+    let mut options = Vec::new();
+
+    for (index, flag) in flags.char_indices() {
+        options.push(flag);
+        if !takes_value(flag) {
+            set_flag(arguments, flag, true)?;
+            continue;
+        }
+
+        // The value is either the rest of this argument (-A3) or the next argument (-A 3).
+        let option = format!("-{flag}");
+        let attached = &flags[index + flag.len_utf8()..];
+        let value = match attached.is_empty() {
+            true => rest
+                .next()
+                .ok_or(ParseError::MissingValue(option.clone()))?,
+            false => attached.to_string(),
+        };
+        set_value(arguments, flag, &option, value)?;
+        break;
+    }
+
+    Ok(options)
+}
+
+// Sets the flag, or unsets it for its --no- form.
+fn set_flag(arguments: &mut Arguments, flag: char, on: bool) -> Result<(), ParseError> {
+    match flag {
+        'i' => arguments.case_insensitive = on,
+        'E' => arguments.regex = on,
+        'v' => arguments.invert = on,
+        'w' => arguments.word = on,
+        'x' => arguments.line = on,
+        'n' => arguments.line_number = on,
+        'c' => arguments.count = on,
+        'l' => arguments.files_with_matches = on,
+        'L' => arguments.files_without_match = on,
+        'q' => arguments.quiet = on,
+        'z' => arguments.decompress = on,
+        _ => return Err(ParseError::UnknownOption(format!("-{flag}"))),
+    }
+
+    Ok(())
+}
+
+fn set_value(
+    arguments: &mut Arguments,
+    flag: char,
+    option: &str,
+    value: String,
+) -> Result<(), ParseError> {
+    match flag {
+        'e' => arguments.patterns.push(value),
+        'f' => {
+            let content = fs::read_to_string(&value).map_err(|error| ParseError::PatternFile {
+                path: value.clone(),
+                message: error.to_string(),
+            })?;
+            arguments
+                .patterns
+                .extend(content.lines().map(|line| line.to_string()));
+        }
+        'm' => arguments.max_count = Some(parse_number(option, &value)?),
+        'A' => arguments.after_context = parse_number(option, &value)?,
+        'B' => arguments.before_context = parse_number(option, &value)?,
+        'j' => arguments.threads = parse_number(option, &value)?,
+        'g' => arguments.rules.add(&value),
+        't' | 'T' => {
+            let globs = filetype::globs(&value).ok_or(ParseError::InvalidValue {
+                option: option.to_string(),
+                value,
+            })?;
+            for glob in globs {
+                match flag {
+                    't' => arguments.rules.include(glob),
+                    _ => arguments.rules.exclude(glob),
+                }
+            }
+        }
+        _ => {
+            let lines = parse_number(option, &value)?;
+            arguments.before_context = lines;
+            arguments.after_context = lines;
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_number(option: &str, value: &str) -> Result<usize, ParseError> {
+    value.parse().map_err(|_| ParseError::InvalidValue {
+        option: option.to_string(),
+        value: value.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_arguments_with_config, ParseError};
+    use crate::{color::ColorChoice, config::Config};
+
+    // Leaves out the config file of whoever runs the tests.
+    fn parse(args: &[&str]) -> Result<crate::Arguments, ParseError> {
+        parse_with_config(args, "")
+    }
+
+    fn parse_with_config(args: &[&str], config: &str) -> Result<crate::Arguments, ParseError> {
+        let config = Config {
+            path: "config".into(),
+            ..Config::parse(config).unwrap()
+        };
+        parse_arguments_with_config(args.iter().map(|arg| arg.to_string()).collect(), &config)
+    }
+
+    #[test]
+    fn test_positionals() {
+        let arguments = parse(&["pattern", "file.txt"]).unwrap();
+        assert_eq!(arguments.patterns, vec!["pattern"]);
+        assert_eq!(arguments.paths, vec!["file.txt"]);
+        assert!(!arguments.invert);
+    }
+
+    #[test]
+    fn test_path_defaults_to_stdin() {
+        let arguments = parse(&["-n", "pattern"]).unwrap();
+        assert_eq!(arguments.paths, vec!["-"]);
+    }
+
+    #[test]
+    fn test_flags_between_positionals() {
+        let arguments = parse(&["-v", "pattern", "--line-number", "file.txt", "-c"]).unwrap();
+        assert!(arguments.invert);
+        assert!(arguments.line_number);
+        assert!(arguments.count);
+        assert_eq!(arguments.patterns, vec!["pattern"]);
+        assert_eq!(arguments.paths, vec!["file.txt"]);
+    }
+
+    #[test]
+    fn test_combined_short_flags() {
+        let arguments = parse(&["-ivnwxlEz", "pattern", "file.txt"]).unwrap();
+        assert!(arguments.case_insensitive);
+        assert!(arguments.invert);
+        assert!(arguments.line_number);
+        assert!(arguments.word);
+        assert!(arguments.line);
+        assert!(arguments.files_with_matches);
+        assert!(arguments.regex);
+        assert!(arguments.decompress);
+        assert!(parse(&["--search-zip", "p"]).unwrap().decompress);
+    }
+
+    #[test]
+    fn test_context_values() {
+        let arguments = parse(&["-A", "2", "-B3", "pattern", "file.txt"]).unwrap();
+        assert_eq!(arguments.after_context, 2);
+        assert_eq!(arguments.before_context, 3);
+
+        let arguments = parse(&["-nC1", "pattern", "file.txt"]).unwrap();
+        assert!(arguments.line_number);
+        assert_eq!((arguments.before_context, arguments.after_context), (1, 1));
+
+        let arguments = parse(&["--context=4", "--after-context", "5", "p", "f"]).unwrap();
+        assert_eq!((arguments.before_context, arguments.after_context), (4, 5));
+    }
+
+    #[test]
+    fn test_threads() {
+        assert_eq!(parse(&["p"]).unwrap().threads, 0);
+        assert_eq!(parse(&["-j", "4", "p"]).unwrap().threads, 4);
+        assert_eq!(parse(&["-nj2", "p"]).unwrap().threads, 2);
+        assert_eq!(parse(&["--threads=1", "p"]).unwrap().threads, 1);
+    }
+
+    #[test]
+    fn test_double_dash_ends_options() {
+        let arguments = parse(&["-n", "--", "-v", "-file"]).unwrap();
+        assert!(arguments.line_number);
+        assert!(!arguments.invert);
+        assert_eq!(arguments.patterns, vec!["-v"]);
+        assert_eq!(arguments.paths, vec!["-file"]);
+    }
+
+    #[test]
+    fn test_multiple_paths() {
+        let arguments = parse(&["pattern", "a.txt", "b.txt", "-"]).unwrap();
+        assert_eq!(arguments.patterns, vec!["pattern"]);
+        assert_eq!(arguments.paths, vec!["a.txt", "b.txt", "-"]);
+    }
+
+    #[test]
+    fn test_multiple_patterns() {
+        let arguments = parse(&["-e", "foo", "--regexp=bar", "-ebaz", "a.txt", "b.txt"]).unwrap();
+        assert_eq!(arguments.patterns, vec!["foo", "bar", "baz"]);
+        assert_eq!(arguments.paths, vec!["a.txt", "b.txt"]);
+    }
+
+    #[test]
+    fn test_pattern_file() {
+        let path = std::env::temp_dir().join(format!("grep_patterns_{}", std::process::id()));
+        std::fs::write(&path, "foo\nbar baz\n").unwrap();
+
+        let arguments = parse(&["-e", "first", "-f", path.to_str().unwrap(), "a.txt"]).unwrap();
+        assert_eq!(arguments.patterns, vec!["first", "foo", "bar baz"]);
+        assert_eq!(arguments.paths, vec!["a.txt"]);
+
+        // An empty pattern file is allowed, and then nothing matches.
+        std::fs::write(&path, "").unwrap();
+        let arguments = parse(&["-f", path.to_str().unwrap(), "a.txt"]).unwrap();
+        assert!(arguments.patterns.is_empty());
+        assert_eq!(arguments.paths, vec!["a.txt"]);
+
+        std::fs::remove_file(path).unwrap();
+        assert!(matches!(
+            parse(&["-f", "/does/not/exist", "a.txt"]).unwrap_err(),
+            ParseError::PatternFile { .. }
+        ));
+    }
+
+    #[test]
+    fn test_color() {
+        assert_eq!(parse(&["p"]).unwrap().color, ColorChoice::Auto);
+        assert_eq!(
+            parse(&["--color=always", "p"]).unwrap().color,
+            ColorChoice::Always
+        );
+        assert_eq!(
+            parse(&["--colour=never", "p"]).unwrap().color,
+            ColorChoice::Never
+        );
+
+        // Without "=", the next argument is not taken as the value.
+        let arguments = parse(&["--color", "p"]).unwrap();
+        assert_eq!(arguments.color, ColorChoice::Auto);
+        assert_eq!(arguments.patterns, vec!["p"]);
+
+        assert!(matches!(
+            parse(&["--color=sometimes", "p"]).unwrap_err(),
+            ParseError::InvalidValue { .. }
+        ));
+    }
+
+    #[test]
+    fn test_json() {
+        assert!(!parse(&["p"]).unwrap().json);
+        assert!(parse(&["--json", "p"]).unwrap().json);
+        assert_eq!(
+            parse(&["--json=yes", "p"]).unwrap_err(),
+            ParseError::UnexpectedArgument("--json=".to_string())
+        );
+        assert_eq!(
+            parse(&["-c", "--json", "p"]).unwrap_err(),
+            ParseError::ConflictingOptions("--json".to_string(), "--count".to_string())
+        );
+        assert!(matches!(
+            parse(&["--json", "-l", "p"]).unwrap_err(),
+            ParseError::ConflictingOptions(..)
+        ));
+    }
+
+    #[test]
+    fn test_replace() {
+        let arguments = parse(&["--replace", "$1", "p"]).unwrap();
+        assert_eq!(arguments.replace.as_deref(), Some("$1"));
+        assert_eq!(arguments.patterns, vec!["p"]);
+        assert!(!arguments.in_place);
+
+        let arguments = parse(&["--replace=", "p", "--in-place"]).unwrap();
+        assert_eq!(arguments.replace.as_deref(), Some(""));
+        assert!(arguments.in_place);
+        assert_eq!(arguments.backup_suffix, None);
+
+        let arguments = parse(&["--replace=x", "--in-place=.bak", "p", "f"]).unwrap();
+        assert_eq!(arguments.backup_suffix.as_deref(), Some(".bak"));
+        assert_eq!(arguments.paths, vec!["f"]);
+    }
+
+    #[test]
+    fn test_replace_errors() {
+        assert_eq!(
+            parse(&["p", "--replace"]).unwrap_err(),
+            ParseError::MissingValue("--replace".to_string())
+        );
+        assert_eq!(
+            parse(&["--in-place", "p"]).unwrap_err(),
+            ParseError::RequiredOption {
+                option: "--in-place".to_string(),
+                required: "--replace".to_string()
+            }
+        );
+        assert_eq!(
+            parse(&["--replace=x", "--in-place", "-v", "p"]).unwrap_err(),
+            ParseError::ConflictingOptions("--in-place".to_string(), "--invert-match".to_string())
+        );
+        assert!(matches!(
+            parse(&["--replace=x", "--json", "p"]).unwrap_err(),
+            ParseError::ConflictingOptions(..)
+        ));
+    }
+
+    #[test]
+    fn test_fuzzy() {
+        assert_eq!(parse(&["p"]).unwrap().fuzzy, None);
+        assert_eq!(parse(&["--fuzzy", "2", "p"]).unwrap().fuzzy, Some(2));
+        assert_eq!(parse(&["--fuzzy=0", "p"]).unwrap().fuzzy, Some(0));
+        assert!(matches!(
+            parse(&["--fuzzy=two", "p"]).unwrap_err(),
+            ParseError::InvalidValue { .. }
+        ));
+        assert_eq!(
+            parse(&["--fuzzy=1", "-C", "2", "p"]).unwrap_err(),
+            ParseError::ConflictingOptions("--fuzzy".to_string(), "--context".to_string())
+        );
+        assert!(matches!(
+            parse(&["--fuzzy=1", "-E", "p"]).unwrap_err(),
+            ParseError::ConflictingOptions(..)
+        ));
+    }
+
+    #[test]
+    fn test_interactive() {
+        assert!(parse(&["--interactive", "p"]).unwrap().interactive);
+        assert!(!parse(&["p"]).unwrap().interactive);
+        assert_eq!(
+            parse(&["--interactive", "-c", "p"]).unwrap_err(),
+            ParseError::ConflictingOptions("--interactive".to_string(), "--count".to_string())
+        );
+        assert!(matches!(
+            parse(&["--interactive", "--replace", "x", "p"]).unwrap_err(),
+            ParseError::ConflictingOptions(..)
+        ));
+    }
+
+    #[test]
+    fn test_summary_modes() {
+        let arguments = parse(&["-qL", "-m2", "p"]).unwrap();
+        assert!(arguments.quiet);
+        assert!(arguments.files_without_match);
+        assert_eq!(arguments.max_count, Some(2));
+        assert_eq!(parse(&["p"]).unwrap().max_count, None);
+
+        let arguments = parse(&["--silent", "--files-without-match", "--max-count", "0", "p"]);
+        let arguments = arguments.unwrap();
+        assert!(arguments.quiet);
+        assert!(arguments.files_without_match);
+        assert_eq!(arguments.max_count, Some(0));
+        assert!(parse(&["--quiet", "p"]).unwrap().quiet);
+
+        assert_eq!(
+            parse(&["-l", "-L", "p"]).unwrap_err(),
+            ParseError::ConflictingOptions(
+                "--files-with-matches".to_string(),
+                "--files-without-match".to_string()
+            )
+        );
+        assert_eq!(
+            parse(&["-m", "-1", "p"]).unwrap_err(),
+            ParseError::InvalidValue {
+                option: "-m".to_string(),
+                value: "-1".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(parse(&[]).unwrap_err(), ParseError::MissingPattern);
+        assert_eq!(
+            parse(&["-Q", "p", "f"]).unwrap_err(),
+            ParseError::UnknownOption("-Q".to_string())
+        );
+        assert_eq!(
+            parse(&["--frobnicate", "p", "f"]).unwrap_err(),
+            ParseError::UnknownOption("--frobnicate".to_string())
+        );
+        assert_eq!(
+            parse(&["p", "f", "-A"]).unwrap_err(),
+            ParseError::MissingValue("-A".to_string())
+        );
+        assert_eq!(
+            parse(&["-C", "many", "p", "f"]).unwrap_err(),
+            ParseError::InvalidValue {
+                option: "-C".to_string(),
+                value: "many".to_string()
+            }
+        );
+        assert_eq!(
+            parse(&["--count=yes", "p", "f"]).unwrap_err(),
+            ParseError::UnexpectedArgument("--count=".to_string())
+        );
+        assert_eq!(
+            parse(&["p", "f", "--help"]).unwrap_err(),
+            ParseError::HelpRequested
+        );
+        assert_eq!(parse(&["--help"]).unwrap_err(), ParseError::HelpRequested);
+    }
+
+    #[test]
+    fn test_config() {
+        let config = "--line-number\n-C2\n--color=never\nignore = *.log\ncolors = ms=01;32\n";
+        let arguments = parse_with_config(&["p", "f"], config).unwrap();
+        assert!(arguments.line_number);
+        assert_eq!((arguments.before_context, arguments.after_context), (2, 2));
+        assert_eq!(arguments.color, ColorChoice::Never);
+        if std::env::var_os("IGNORE").is_none() {
+            assert_eq!(arguments.ignore.len(), 1);
+        }
+        if std::env::var_os("GREP_COLORS").is_none() {
+            assert_eq!(arguments.colors, "ms=01;32");
+        }
+
+        // The command line wins.
+        let arguments = parse_with_config(&["-A", "5", "--color=always", "p"], config).unwrap();
+        assert_eq!((arguments.before_context, arguments.after_context), (2, 5));
+        assert_eq!(arguments.color, ColorChoice::Always);
+        assert!(parse(&["--no-config", "p"]).is_ok());
+
+        // Flags of the config can be undone.
+        let config = "-inw\n--json\n";
+        let arguments =
+            parse_with_config(&["--no-line-number", "--case-sensitive", "p"], config).unwrap();
+        assert!(!arguments.line_number && !arguments.case_insensitive);
+        assert!(arguments.word && arguments.json);
+        let arguments = parse_with_config(&["--no-json", "--no-word-regexp", "p"], config).unwrap();
+        assert!(!arguments.json && !arguments.word && arguments.line_number);
+
+        let config = "--replace=x\n--in-place=.orig\n";
+        let arguments = parse_with_config(&["--no-in-place", "p"], config).unwrap();
+        assert!(!arguments.in_place);
+        assert_eq!(arguments.backup_suffix, None);
+        let arguments = parse_with_config(&["--no-replace", "--no-in-place", "p"], config).unwrap();
+        assert_eq!(arguments.replace, None);
+
+        let config = "--fuzzy=2
+-m3
+";
+        let arguments = parse_with_config(&["p"], config).unwrap();
+        assert_eq!((arguments.fuzzy, arguments.max_count), (Some(2), Some(3)));
+        let arguments = parse_with_config(&["--no-fuzzy", "--no-max-count", "p"], config).unwrap();
+        assert_eq!((arguments.fuzzy, arguments.max_count), (None, None));
+        assert_eq!(
+            parse(&["--no-max-count=3", "p"]).unwrap_err(),
+            ParseError::UnexpectedArgument("--no-max-count=".to_string())
+        );
+        assert_eq!(
+            parse(&["--no-context", "p"]).unwrap_err(),
+            ParseError::UnknownOption("--no-context".to_string())
+        );
+        assert_eq!(
+            parse(&["--no-regex=1", "p"]).unwrap_err(),
+            ParseError::UnexpectedArgument("--no-regex=".to_string())
+        );
+
+        // Options of the command line drop the config options they conflict with.
+        let arguments = parse_with_config(&["-E", "p"], "--fuzzy=1").unwrap();
+        assert!(arguments.regex && arguments.fuzzy.is_none());
+        let arguments = parse_with_config(&["-L", "p"], "-l").unwrap();
+        assert!(arguments.files_without_match && !arguments.files_with_matches);
+        let arguments = parse_with_config(&["-c", "p"], "--json").unwrap();
+        assert!(arguments.count && !arguments.json);
+        let arguments = parse_with_config(&["--fuzzy", "1", "p"], "-C2\n-w").unwrap();
+        assert_eq!((arguments.before_context, arguments.after_context), (0, 0));
+        assert!(!arguments.word);
+        let arguments = parse_with_config(&["-A1", "p"], "--fuzzy=1").unwrap();
+        assert_eq!((arguments.after_context, arguments.fuzzy), (1, None));
+        assert_eq!(
+            parse_with_config(&["-c", "--json", "p"], "--json").unwrap_err(),
+            ParseError::ConflictingOptions("--json".to_string(), "--count".to_string())
+        );
+        assert_eq!(
+            parse_with_config(&["p"], "-l\n-L").unwrap_err(),
+            ParseError::ConflictingOptions(
+                "--files-with-matches".to_string(),
+                "--files-without-match".to_string()
+            )
+        );
+
+        let error = |config| parse_with_config(&["p"], config).unwrap_err().to_string();
+        assert_eq!(
+            error("--colour=pink"),
+            "Error in config file config: Invalid value \"pink\" for option --colour"
+        );
+        assert_eq!(
+            error("-C"),
+            "Error in config file config: Option -C requires a value"
+        );
+        assert_eq!(
+            error("-epattern"),
+            "Error in config file config: Unexpected argument \"-epattern\""
+        );
+        assert_eq!(
+            error("--help"),
+            "Error in config file config: Unexpected argument \"--help\""
+        );
+    }
+
+    #[test]
+    fn test_rules() {
+        use crate::glob::Rules;
+        use std::path::Path;
+
+        let arguments =
+            parse(&["-g", "*.toml", "--type", "rust", "--glob=!target/**", "p"]).unwrap();
+        let allows = |path| arguments.rules.allows(Path::new(path), false);
+        assert!(allows("Cargo.toml") && allows("src/lib.rs"));
+        assert!(!allows("README.md") && !allows("target/build.rs"));
+
+        let arguments = parse(&["-T", "md", "--type-not", "txt", "p"]).unwrap();
+        assert!(arguments.rules.allows(Path::new("main.c"), false));
+        assert!(!arguments
+            .rules
+            .allows(Path::new("doc/notes.markdown"), false));
+        assert!(!arguments.rules.allows(Path::new("notes.txt"), false));
+        assert_eq!(parse(&["p"]).unwrap().rules, Rules::default());
+
+        assert_eq!(
+            parse(&["--type", "cobol", "p"]).unwrap_err(),
+            ParseError::InvalidValue {
+                option: "--type".to_string(),
+                value: "cobol".to_string()
+            }
+        );
+    }
+}
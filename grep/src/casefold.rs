@@ -0,0 +1,422 @@
+/*
+    Case-insensitive matching based on Unicode case folding instead of lowercasing.
+
+    Lowercasing is not the same as folding. Some characters are lowercase already, but still have to be treated
+    like another character: "ß" folds to "ss", "ς" (final sigma) folds to "σ" and "µ" (micro sign) folds to "μ".
+    Lowercasing a whole line also allocates a new String for every line and can change the byte offsets of the line.
+
+    So instead, the pattern is folded once up front, and every line is folded character by character while comparing.
+    Nothing is allocated per line, and matches are reported as byte ranges into the original line.
+
+    Folding follows the full case folding of Unicode (CaseFolding.txt, status C and F), which is lowercasing
+    plus the exceptions listed in FULL_FOLDING, and Cherokee, which folds to uppercase.
+    The table holds every character of Unicode 14 whose folding differs from char::to_lowercase,
+    test_folding_is_complete checks that nothing is missing. Turkic languages fold the dotted and dotless i differently,
+    which can be enabled with CaseFolder::new_turkic.
+*/
+
+use std::{ops::Range, str::Chars};
+
+// Characters whose full case folding differs from what char::to_lowercase returns.
+const FULL_FOLDING: &[(char, &str)] = &[
+    ('\u{00B5}', "\u{03BC}"),                 // µ micro sign → μ
+    ('\u{00DF}', "ss"),                       // ß
+    ('\u{0149}', "\u{02BC}n"),                // ŉ
+    ('\u{017F}', "s"),                        // ſ long s
+    ('\u{01F0}', "j\u{030C}"),                // ǰ
+    ('\u{0345}', "\u{03B9}"),                 // combining ypogegrammeni → ι
+    ('\u{0390}', "\u{03B9}\u{0308}\u{0301}"), // ΐ
+    ('\u{03B0}', "\u{03C5}\u{0308}\u{0301}"), // ΰ
+    ('\u{03C2}', "\u{03C3}"),                 // ς final sigma → σ
+    ('\u{03D0}', "\u{03B2}"),                 // ϐ → β
+    ('\u{03D1}', "\u{03B8}"),                 // ϑ → θ
+    ('\u{03D5}', "\u{03C6}"),                 // ϕ → φ
+    ('\u{03D6}', "\u{03C0}"),                 // ϖ → π
+    ('\u{03F0}', "\u{03BA}"),                 // ϰ → κ
+    ('\u{03F1}', "\u{03C1}"),                 // ϱ → ρ
+    ('\u{03F5}', "\u{03B5}"),                 // ϵ → ε
+    ('\u{0587}', "\u{0565}\u{0582}"),         // և
+    ('\u{1C80}', "\u{0432}"),                 // ᲀ → в
+    ('\u{1C81}', "\u{0434}"),                 // ᲁ → д
+    ('\u{1C82}', "\u{043E}"),                 // ᲂ → о
+    ('\u{1C83}', "\u{0441}"),                 // ᲃ → с
+    ('\u{1C84}', "\u{0442}"),                 // ᲄ → т
+    ('\u{1C85}', "\u{0442}"),                 // ᲅ → т
+    ('\u{1C86}', "\u{044A}"),                 // ᲆ → ъ
+    ('\u{1C87}', "\u{0463}"),                 // ᲇ → ѣ
+    ('\u{1C88}', "\u{A64B}"),                 // ᲈ → ꙋ
+    ('\u{1E96}', "h\u{0331}"),                // ẖ
+    ('\u{1E97}', "t\u{0308}"),                // ẗ
+    ('\u{1E98}', "w\u{030A}"),                // ẘ
+    ('\u{1E99}', "y\u{030A}"),                // ẙ
+    ('\u{1E9A}', "a\u{02BE}"),                // ẚ
+    ('\u{1E9B}', "\u{1E61}"),                 // ẛ → ṡ
+    ('\u{1E9E}', "ss"),                       // ẞ capital sharp s
+    ('\u{1F50}', "\u{03C5}\u{0313}"),         // ὐ
+    ('\u{1F52}', "\u{03C5}\u{0313}\u{0300}"), // ὒ
+    ('\u{1F54}', "\u{03C5}\u{0313}\u{0301}"), // ὔ
+    ('\u{1F56}', "\u{03C5}\u{0313}\u{0342}"), // ὖ
+    ('\u{1F80}', "\u{1F00}\u{03B9}"),         // ᾀ → ἀι
+    ('\u{1F81}', "\u{1F01}\u{03B9}"),         // ᾁ → ἁι
+    ('\u{1F82}', "\u{1F02}\u{03B9}"),         // ᾂ → ἂι
+    ('\u{1F83}', "\u{1F03}\u{03B9}"),         // ᾃ → ἃι
+    ('\u{1F84}', "\u{1F04}\u{03B9}"),         // ᾄ → ἄι
+    ('\u{1F85}', "\u{1F05}\u{03B9}"),         // ᾅ → ἅι
+    ('\u{1F86}', "\u{1F06}\u{03B9}"),         // ᾆ → ἆι
+    ('\u{1F87}', "\u{1F07}\u{03B9}"),         // ᾇ → ἇι
+    ('\u{1F88}', "\u{1F00}\u{03B9}"),         // ᾈ → ἀι
+    ('\u{1F89}', "\u{1F01}\u{03B9}"),         // ᾉ → ἁι
+    ('\u{1F8A}', "\u{1F02}\u{03B9}"),         // ᾊ → ἂι
+    ('\u{1F8B}', "\u{1F03}\u{03B9}"),         // ᾋ → ἃι
+    ('\u{1F8C}', "\u{1F04}\u{03B9}"),         // ᾌ → ἄι
+    ('\u{1F8D}', "\u{1F05}\u{03B9}"),         // ᾍ → ἅι
+    ('\u{1F8E}', "\u{1F06}\u{03B9}"),         // ᾎ → ἆι
+    ('\u{1F8F}', "\u{1F07}\u{03B9}"),         // ᾏ → ἇι
+    ('\u{1F90}', "\u{1F20}\u{03B9}"),         // ᾐ → ἠι
+    ('\u{1F91}', "\u{1F21}\u{03B9}"),         // ᾑ → ἡι
+    ('\u{1F92}', "\u{1F22}\u{03B9}"),         // ᾒ → ἢι
+    ('\u{1F93}', "\u{1F23}\u{03B9}"),         // ᾓ → ἣι
+    ('\u{1F94}', "\u{1F24}\u{03B9}"),         // ᾔ → ἤι
+    ('\u{1F95}', "\u{1F25}\u{03B9}"),         // ᾕ → ἥι
+    ('\u{1F96}', "\u{1F26}\u{03B9}"),         // ᾖ → ἦι
+    ('\u{1F97}', "\u{1F27}\u{03B9}"),         // ᾗ → ἧι
+    ('\u{1F98}', "\u{1F20}\u{03B9}"),         // ᾘ → ἠι
+    ('\u{1F99}', "\u{1F21}\u{03B9}"),         // ᾙ → ἡι
+    ('\u{1F9A}', "\u{1F22}\u{03B9}"),         // ᾚ → ἢι
+    ('\u{1F9B}', "\u{1F23}\u{03B9}"),         // ᾛ → ἣι
+    ('\u{1F9C}', "\u{1F24}\u{03B9}"),         // ᾜ → ἤι
+    ('\u{1F9D}', "\u{1F25}\u{03B9}"),         // ᾝ → ἥι
+    ('\u{1F9E}', "\u{1F26}\u{03B9}"),         // ᾞ → ἦι
+    ('\u{1F9F}', "\u{1F27}\u{03B9}"),         // ᾟ → ἧι
+    ('\u{1FA0}', "\u{1F60}\u{03B9}"),         // ᾠ → ὠι
+    ('\u{1FA1}', "\u{1F61}\u{03B9}"),         // ᾡ → ὡι
+    ('\u{1FA2}', "\u{1F62}\u{03B9}"),         // ᾢ → ὢι
+    ('\u{1FA3}', "\u{1F63}\u{03B9}"),         // ᾣ → ὣι
+    ('\u{1FA4}', "\u{1F64}\u{03B9}"),         // ᾤ → ὤι
+    ('\u{1FA5}', "\u{1F65}\u{03B9}"),         // ᾥ → ὥι
+    ('\u{1FA6}', "\u{1F66}\u{03B9}"),         // ᾦ → ὦι
+    ('\u{1FA7}', "\u{1F67}\u{03B9}"),         // ᾧ → ὧι
+    ('\u{1FA8}', "\u{1F60}\u{03B9}"),         // ᾨ → ὠι
+    ('\u{1FA9}', "\u{1F61}\u{03B9}"),         // ᾩ → ὡι
+    ('\u{1FAA}', "\u{1F62}\u{03B9}"),         // ᾪ → ὢι
+    ('\u{1FAB}', "\u{1F63}\u{03B9}"),         // ᾫ → ὣι
+    ('\u{1FAC}', "\u{1F64}\u{03B9}"),         // ᾬ → ὤι
+    ('\u{1FAD}', "\u{1F65}\u{03B9}"),         // ᾭ → ὥι
+    ('\u{1FAE}', "\u{1F66}\u{03B9}"),         // ᾮ → ὦι
+    ('\u{1FAF}', "\u{1F67}\u{03B9}"),         // ᾯ → ὧι
+    ('\u{1FB2}', "\u{1F70}\u{03B9}"),         // ᾲ → ὰι
+    ('\u{1FB3}', "\u{03B1}\u{03B9}"),         // ᾳ → αι
+    ('\u{1FB4}', "\u{03AC}\u{03B9}"),         // ᾴ → άι
+    ('\u{1FB6}', "\u{03B1}\u{0342}"),         // ᾶ
+    ('\u{1FB7}', "\u{03B1}\u{0342}\u{03B9}"), // ᾷ → ᾶι
+    ('\u{1FBC}', "\u{03B1}\u{03B9}"),         // ᾼ → αι
+    ('\u{1FBE}', "\u{03B9}"),                 // ι prosgegrammeni → ι
+    ('\u{1FC2}', "\u{1F74}\u{03B9}"),         // ῂ → ὴι
+    ('\u{1FC3}', "\u{03B7}\u{03B9}"),         // ῃ → ηι
+    ('\u{1FC4}', "\u{03AE}\u{03B9}"),         // ῄ → ήι
+    ('\u{1FC6}', "\u{03B7}\u{0342}"),         // ῆ
+    ('\u{1FC7}', "\u{03B7}\u{0342}\u{03B9}"), // ῇ → ῆι
+    ('\u{1FCC}', "\u{03B7}\u{03B9}"),         // ῌ → ηι
+    ('\u{1FD2}', "\u{03B9}\u{0308}\u{0300}"), // ῒ
+    ('\u{1FD3}', "\u{03B9}\u{0308}\u{0301}"), // ΐ
+    ('\u{1FD6}', "\u{03B9}\u{0342}"),         // ῖ
+    ('\u{1FD7}', "\u{03B9}\u{0308}\u{0342}"), // ῗ
+    ('\u{1FE2}', "\u{03C5}\u{0308}\u{0300}"), // ῢ
+    ('\u{1FE3}', "\u{03C5}\u{0308}\u{0301}"), // ΰ
+    ('\u{1FE4}', "\u{03C1}\u{0313}"),         // ῤ
+    ('\u{1FE6}', "\u{03C5}\u{0342}"),         // ῦ
+    ('\u{1FE7}', "\u{03C5}\u{0308}\u{0342}"), // ῧ
+    ('\u{1FF2}', "\u{1F7C}\u{03B9}"),         // ῲ → ὼι
+    ('\u{1FF3}', "\u{03C9}\u{03B9}"),         // ῳ → ωι
+    ('\u{1FF4}', "\u{03CE}\u{03B9}"),         // ῴ → ώι
+    ('\u{1FF6}', "\u{03C9}\u{0342}"),         // ῶ
+    ('\u{1FF7}', "\u{03C9}\u{0342}\u{03B9}"), // ῷ → ῶι
+    ('\u{1FFC}', "\u{03C9}\u{03B9}"),         // ῼ → ωι
+    ('\u{FB00}', "ff"),                       // ﬀ
+    ('\u{FB01}', "fi"),                       // ﬁ
+    ('\u{FB02}', "fl"),                       // ﬂ
+    ('\u{FB03}', "ffi"),                      // ﬃ
+    ('\u{FB04}', "ffl"),                      // ﬄ
+    ('\u{FB05}', "st"),                       // ﬅ
+    ('\u{FB06}', "st"),                       // ﬆ
+    ('\u{FB13}', "\u{0574}\u{0576}"),         // ﬓ → մն
+    ('\u{FB14}', "\u{0574}\u{0565}"),         // ﬔ → մե
+    ('\u{FB15}', "\u{0574}\u{056B}"),         // ﬕ → մի
+    ('\u{FB16}', "\u{057E}\u{0576}"),         // ﬖ → վն
+    ('\u{FB17}', "\u{0574}\u{056D}"),         // ﬗ → մխ
+];
+
+// The folding of a single character, which can be up to three characters long.
+pub enum Folded {
+    Lowercase(std::char::ToLowercase),
+    Special(Chars<'static>),
+    Single(Option<char>),
+}
+
+impl Iterator for Folded {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        match self {
+            Folded::Lowercase(chars) => chars.next(),
+            Folded::Special(chars) => chars.next(),
+            Folded::Single(c) => c.take(),
+        }
+    }
+}
+
+pub fn fold(c: char, turkic: bool) -> Folded {
+    if turkic {
+        match c {
+            'I' => return Folded::Single(Some('\u{0131}')), // I → ı
+            '\u{0130}' => return Folded::Single(Some('i')), // İ → i
+            _ => {}
+        }
+    }
+
+    // ASCII is by far the most common case, so it skips the table lookup.
+    if c.is_ascii() {
+        return Folded::Single(Some(c.to_ascii_lowercase()));
+    }
+
+    // Cherokee is the only script that folds to uppercase, as its lowercase letters were added later.
+    match c {
+        '\u{13A0}'..='\u{13F5}' => return Folded::Single(Some(c)),
+        '\u{13F8}'..='\u{13FD}' => return Folded::Single(char::from_u32(c as u32 - 8)),
+        '\u{AB70}'..='\u{ABBF}' => {
+            return Folded::Single(char::from_u32(c as u32 - 0xAB70 + 0x13A0))
+        }
+        _ => {}
+    }
+
+    match FULL_FOLDING.binary_search_by_key(&c, |(from, _)| *from) {
+        Ok(index) => Folded::Special(FULL_FOLDING[index].1.chars()),
+        Err(_) => Folded::Lowercase(c.to_lowercase()),
+    }
+}
+
+// Two characters are equal ignoring case if they fold to the same characters.
+pub fn chars_equal(a: char, b: char, turkic: bool) -> bool {
+    a == b || fold(a, turkic).eq(fold(b, turkic))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaseFolder {
+    folded: Vec<char>,
+    turkic: bool,
+}
+
+impl CaseFolder {
+    pub fn new(pattern: &str) -> CaseFolder {
+        CaseFolder::build(pattern, false)
+    }
+
+    pub fn new_turkic(pattern: &str) -> CaseFolder {
+        CaseFolder::build(pattern, true)
+    }
+
+    fn build(pattern: &str, turkic: bool) -> CaseFolder {
+        CaseFolder {
+            folded: pattern.chars().flat_map(|c| fold(c, turkic)).collect(),
+            turkic,
+        }
+    }
+
+    pub fn is_match(&self, line: &str) -> bool {
+        self.find_at(line, 0).is_some()
+    }
+
+    // The byte ranges of all non-overlapping matches, from left to right.
+    pub fn find_all(&self, line: &str) -> Vec<Range<usize>> {
+        let mut ranges = Vec::new();
+        let mut start = 0;
+
+        while let Some(range) = self.find_at(line, start) {
+            start = range.end;
+            ranges.push(range);
+        }
+
+        ranges
+    }
+
+    // An empty pattern never matches, as there is nothing to highlight or replace.
+    pub fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        let first = *self.folded.first()?;
+
+        for (offset, c) in line[start..].char_indices() {
+            let position = start + offset;
+
+            // Most positions can be ruled out by the first folded character alone.
+            if fold(c, self.turkic).next() != Some(first) {
+                continue;
+            }
+            if let Some(end) = self.match_at(line, position) {
+                return Some(position..end);
+            }
+        }
+
+        None
+    }
+
+    /*
+        Returns where the match ends if the pattern matches the line at start.
+        A match has to end on a character boundary of the line,
+        so "s" does not match the first half of the "ss" that "ß" folds to.
+    */
+    fn match_at(&self, line: &str, start: usize) -> Option<usize> {
+        let mut pattern = self.folded.iter();
+
+        for (offset, c) in line[start..].char_indices() {
+            if pattern.len() == 0 {
+                return Some(start + offset);
+            }
+            for folded in fold(c, self.turkic) {
+                if pattern.next() != Some(&folded) {
+                    return None;
+                }
+            }
+        }
+
+        match pattern.len() {
+            0 => Some(line.len()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{chars_equal, fold, CaseFolder, FULL_FOLDING};
+
+    fn find(pattern: &str, line: &str) -> Vec<(usize, usize)> {
+        CaseFolder::new(pattern)
+            .find_all(line)
+            .into_iter()
+            .map(|range| (range.start, range.end))
+            .collect()
+    }
+
+    #[test]
+    fn test_table_is_sorted() {
+        // fold() relies on a binary search.
+        assert!(FULL_FOLDING.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+
+    #[test]
+    fn test_ascii() {
+        assert_eq!(
+            find("hello", "Hello HELLO hello"),
+            vec![(0, 5), (6, 11), (12, 17)]
+        );
+        assert!(find("hello", "help").is_empty());
+        assert!(find("", "anything").is_empty());
+    }
+
+    #[test]
+    fn test_german_sharp_s() {
+        assert_eq!(find("STRASSE", "Straße"), vec![(0, 7)]);
+        assert_eq!(find("straße", "STRASSE"), vec![(0, 7)]);
+        assert_eq!(find("ẞ", "Fuß"), vec![(2, 4)]);
+        // Only half of the "ss" that ß folds to is not a match.
+        assert!(find("s", "ß").is_empty());
+    }
+
+    #[test]
+    fn test_greek_sigma() {
+        // Final sigma, small sigma and capital sigma are the same letter.
+        assert_eq!(find("ΟΔΟΣ", "οδος"), vec![(0, 8)]);
+        assert_eq!(find("οδοσ", "ΟΔΟΣ οδος"), vec![(0, 8), (9, 17)]);
+    }
+
+    #[test]
+    fn test_cyrillic() {
+        let hello = "Здравствуйте";
+        assert_eq!(find("здравствуйте", hello), vec![(0, hello.len())]);
+        assert_eq!(find("ВСТВ", hello), vec![(8, 16)]);
+    }
+
+    #[test]
+    fn test_devanagari() {
+        // Devanagari has no case, so folding must leave it alone and still find it.
+        let namaste = "नमस्ते";
+        assert_eq!(find("नमस्ते", namaste), vec![(0, namaste.len())]);
+        assert_eq!(find("स्", namaste), vec![(6, 12)]);
+        assert!(find("नमः", namaste).is_empty());
+    }
+
+    #[test]
+    fn test_ligatures_and_signs() {
+        assert_eq!(find("office", "the OFFICE"), vec![(4, 10)]);
+        assert_eq!(find("office", "the ofﬁce"), vec![(4, 11)]);
+        assert_eq!(find("μm", "5 µm"), vec![(2, 5)]);
+        assert_eq!(find("k", "\u{212A}elvin"), vec![(0, 3)]);
+    }
+
+    #[test]
+    fn test_greek_iota_subscript() {
+        assert_eq!(fold('ᾳ', false).collect::<String>(), "αι");
+        assert_eq!(find("ΑΙ", "ᾳ ᾼ"), vec![(0, 3), (4, 7)]);
+        assert_eq!(find("ᾠδή", "ὨΙΔΉ"), vec![(0, 9)]);
+    }
+
+    #[test]
+    fn test_armenian_and_cherokee() {
+        assert_eq!(find("ՄՆ", "ﬓ"), vec![(0, 3)]);
+        assert_eq!(find("ᏣᎳᎩ", "ꮳꮃꭹ"), vec![(0, 9)]);
+        assert_eq!(fold('ꭰ', false).collect::<String>(), "Ꭰ");
+        assert!(chars_equal('Ᏸ', 'ᏸ', false));
+    }
+
+    /*
+        Without a copy of CaseFolding.txt, completeness is checked through what the folding has to satisfy:
+        a character folds like its uppercase form, and folding a folded character changes nothing.
+        A character missing from the table breaks the first rule, e.g. ᾳ would not match its uppercase "ΑΙ".
+        The dotless ı is the only exception, it has no folding of its own although its uppercase is I.
+    */
+    #[test]
+    fn test_folding_is_complete() {
+        let fold_all = |text: &mut dyn Iterator<Item = char>| -> String {
+            text.flat_map(|c| fold(c, false)).collect()
+        };
+
+        for c in (0..=0x10FFFF).filter_map(char::from_u32) {
+            let folded: String = fold(c, false).collect();
+            assert_eq!(fold_all(&mut folded.chars()), folded, "{c:?}");
+            if c != 'ı' {
+                assert_eq!(fold_all(&mut c.to_uppercase()), folded, "{c:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_turkish_i() {
+        // By default, the dotless ı is its own letter and I folds to i.
+        assert!(find("ı", "I").is_empty());
+        assert_eq!(find("i", "I"), vec![(0, 1)]);
+        // İ folds to i followed by a combining dot.
+        assert_eq!(find("i\u{0307}", "İ"), vec![(0, 2)]);
+
+        let turkic = CaseFolder::new_turkic("ılık");
+        assert!(turkic.is_match("ILIK"));
+        assert!(!turkic.is_match("ilik"));
+        assert!(CaseFolder::new_turkic("istanbul").is_match("İSTANBUL"));
+    }
+
+    #[test]
+    fn test_ranges_point_into_the_original_line() {
+        let line = "Grüße aus STRASSBURG";
+        for (start, end) in find("strass", line) {
+            assert_eq!(&line[start..end], "STRASS");
+        }
+        assert_eq!(find("grüsse", line), vec![(0, 7)]);
+    }
+
+    #[test]
+    fn test_chars_equal() {
+        assert!(chars_equal('ẞ', 'ß', false));
+        assert!(chars_equal('Σ', 'ς', false));
+        assert!(!chars_equal('I', 'ı', false));
+        assert!(chars_equal('I', 'ı', true));
+        assert_eq!(fold('Ж', false).collect::<String>(), "ж");
+    }
+}
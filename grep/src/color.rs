@@ -0,0 +1,202 @@
+/*
+    Highlights matches and decorates output with ANSI escape codes.
+
+    Colors are configured like in GNU grep, through a GREP_COLORS variable with colon separated "key=SGR" entries,
+    for example "ms=01;31:fn=35:ln=32". SGR (Select Graphic Rendition) is the parameter list of the escape code,
+    e.g. "01;31" for bold red. An empty value turns the decoration off.
+
+    Supported keys:
+     - ms: matched text in selected lines
+     - mc: matched text in context lines
+     - sl: whole selected lines
+     - cx: whole context lines
+     - fn: file names
+     - ln: line numbers
+     - se: separators (':', '-' and "--")
+     - mt: sets both ms and mc
+*/
+
+use std::{fmt, ops::Range, str::FromStr};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum ColorChoice {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    // Auto only colors output that goes to a terminal, so files and pipes do not get escape codes.
+    pub fn should_color(&self, is_terminal: bool) -> bool {
+        match self {
+            ColorChoice::Auto => is_terminal,
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<ColorChoice, String> {
+        match value {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(format!("{value} is not one of auto, always or never")),
+        }
+    }
+}
+
+impl fmt::Display for ColorChoice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ColorChoice::Auto => write!(f, "auto"),
+            ColorChoice::Always => write!(f, "always"),
+            ColorChoice::Never => write!(f, "never"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Colors {
+    pub selected_match: String,
+    pub context_match: String,
+    pub selected_line: String,
+    pub context_line: String,
+    pub file_name: String,
+    pub line_number: String,
+    pub separator: String,
+}
+
+// The same defaults GNU grep uses.
+impl Default for Colors {
+    fn default() -> Colors {
+        Colors {
+            selected_match: "01;31".to_string(),
+            context_match: "01;31".to_string(),
+            selected_line: String::new(),
+            context_line: String::new(),
+            file_name: "35".to_string(),
+            line_number: "32".to_string(),
+            separator: "36".to_string(),
+        }
+    }
+}
+
+impl Colors {
+    // Starts from the defaults and overrides every known key. Unknown keys and malformed entries are ignored.
+    pub fn parse(spec: &str) -> Colors {
+        let mut colors = Colors::default();
+
+        for entry in spec.split(':') {
+            let (key, value) = match entry.split_once('=') {
+                Some((key, value)) if is_sgr(value) => (key, value.to_string()),
+                _ => continue,
+            };
+
+            match key {
+                "ms" => colors.selected_match = value,
+                "mc" => colors.context_match = value,
+                "mt" => {
+                    colors.selected_match = value.clone();
+                    colors.context_match = value;
+                }
+                "sl" => colors.selected_line = value,
+                "cx" => colors.context_line = value,
+                "fn" => colors.file_name = value,
+                "ln" => colors.line_number = value,
+                "se" => colors.separator = value,
+                _ => {}
+            }
+        }
+
+        colors
+    }
+}
+
+// Only digits and semicolons are allowed, so a config value can never inject other escape sequences.
+fn is_sgr(value: &str) -> bool {
+    value.chars().all(|c| c.is_ascii_digit() || c == ';')
+}
+
+// Wraps the text in the escape codes for the given SGR. "\x1b[K" clears to the end of the line, like GNU grep does.
+pub fn paint(sgr: &str, text: &str) -> String {
+    match sgr.is_empty() {
+        true => text.to_string(),
+        false => format!("\x1b[{sgr}m\x1b[K{text}\x1b[m\x1b[K"),
+    }
+}
+
+/*
+    Paints the given byte ranges of the line with match_sgr and the rest with line_sgr.
+    The ranges have to be sorted, must not overlap and must lie on character boundaries.
+*/
+pub fn highlight(line: &str, ranges: &[Range<usize>], match_sgr: &str, line_sgr: &str) -> String {
+    let mut highlighted = String::with_capacity(line.len());
+    let mut position = 0;
+
+    for range in ranges {
+        highlighted.push_str(&paint(line_sgr, &line[position..range.start]));
+        highlighted.push_str(&paint(match_sgr, &line[range.clone()]));
+        position = range.end;
+    }
+    highlighted.push_str(&paint(line_sgr, &line[position..]));
+
+    highlighted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{highlight, paint, ColorChoice, Colors};
+
+    #[test]
+    fn test_color_choice() {
+        assert_eq!("always".parse::<ColorChoice>(), Ok(ColorChoice::Always));
+        assert!("sometimes".parse::<ColorChoice>().is_err());
+
+        assert!(ColorChoice::Auto.should_color(true));
+        assert!(!ColorChoice::Auto.should_color(false));
+        assert!(ColorChoice::Always.should_color(false));
+        assert!(!ColorChoice::Never.should_color(true));
+    }
+
+    #[test]
+    fn test_paint() {
+        assert_eq!(paint("32", "12"), "\x1b[32m\x1b[K12\x1b[m\x1b[K");
+        assert_eq!(paint("", "12"), "12");
+    }
+
+    #[test]
+    fn test_highlight() {
+        let line = "one two one";
+        let highlighted = highlight(line, &[0..3, 8..11], "1", "");
+        assert_eq!(
+            highlighted,
+            "\x1b[1m\x1b[Kone\x1b[m\x1b[K two \x1b[1m\x1b[Kone\x1b[m\x1b[K"
+        );
+        assert_eq!(highlight(line, &[], "1", ""), line);
+    }
+
+    #[test]
+    fn test_parse_grep_colors() {
+        let colors = Colors::parse("ms=04;32:fn=:ln=33:xx=1:se");
+        assert_eq!(colors.selected_match, "04;32");
+        assert_eq!(colors.context_match, "01;31");
+        assert_eq!(colors.file_name, "");
+        assert_eq!(colors.line_number, "33");
+        assert_eq!(colors.separator, "36");
+
+        let colors = Colors::parse("mt=7");
+        assert_eq!(colors.selected_match, "7");
+        assert_eq!(colors.context_match, "7");
+    }
+
+    #[test]
+    fn test_parse_rejects_escape_injection() {
+        let colors = Colors::parse("ms=1m\x1b[2J");
+        assert_eq!(colors.selected_match, "01;31");
+    }
+}
@@ -0,0 +1,162 @@
+/*
+    Persistent defaults, read from a config file before the command line.
+
+    The file is GREP_CONFIG_PATH if that is set, otherwise $XDG_CONFIG_HOME/grep/config,
+    falling back to ~/.config/grep/config. Without a file nothing changes, and --no-config ignores it.
+
+    Every line is empty, a comment starting with '#', an option as it would be written on the command line,
+    or a "key = value" setting:
+
+        # Number lines, and only color when asked to.
+        --line-number
+        --color=never
+        -C2
+        ignore = .git/,target/,*.min.js
+        colors = ms=01;32:fn=35
+
+    A line is a single argument, so values are attached to their option (--context=2 or -C2).
+    Patterns and paths can not be set, they always come from the command line.
+    ignore replaces the default of the IGNORE variable, colors the one of GREP_COLORS (see color.rs).
+
+    From lowest to highest precedence: the file, the environment variables, the command line.
+    A flag set in the file is undone on the command line with its --no- form, like --no-line-number,
+    and so are --replace, --fuzzy and --max-count (--no-replace, --no-fuzzy, --no-max-count).
+*/
+
+use crate::args::ParseError;
+use std::{env, fs, io, path::PathBuf};
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Config {
+    pub path: PathBuf, // Where the config was read from, for error messages
+    pub options: Vec<String>,
+    pub ignore: Option<String>, // Comma separated globs
+    pub colors: Option<String>, // In the format of GREP_COLORS
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let mut config = Config::default();
+
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            // Checked first, as options like --context=2 contain a '=' as well.
+            if line.starts_with('-') {
+                config.options.push(line.to_string());
+                continue;
+            }
+
+            let number = index + 1;
+            let (key, value) = line.split_once('=').ok_or(format!(
+                "line {number}: expected an option or a setting, found \"{line}\""
+            ))?;
+            let value = Some(value.trim().to_string());
+            match key.trim() {
+                "ignore" => config.ignore = value,
+                "colors" => config.colors = value,
+                key => return Err(format!("line {number}: unknown setting \"{key}\"")),
+            }
+        }
+
+        Ok(config)
+    }
+
+    /*
+        Reads the file at the default location, see config_path.
+        Only a file set explicitly through GREP_CONFIG_PATH has to exist, otherwise no file means no defaults.
+    */
+    pub fn load() -> Result<Config, ParseError> {
+        let explicit = env::var_os("GREP_CONFIG_PATH").is_some_and(|path| !path.is_empty());
+        let path = match config_path(|name| env::var_os(name).map(PathBuf::from)) {
+            Some(path) => path,
+            None => return Ok(Config::default()),
+        };
+        let error = |message: String| ParseError::Config {
+            path: path.display().to_string(),
+            message,
+        };
+
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !explicit => {
+                return Ok(Config::default())
+            }
+            Err(e) => return Err(error(e.to_string())),
+        };
+        let config = Config::parse(&text).map_err(error)?;
+
+        Ok(Config { path, ..config })
+    }
+}
+
+// Takes the lookup of environment variables as a parameter, so tests do not depend on the real environment.
+pub fn config_path(var: impl Fn(&str) -> Option<PathBuf>) -> Option<PathBuf> {
+    // Like in the XDG specification, empty variables count as unset.
+    let var = |name| var(name).filter(|value: &PathBuf| !value.as_os_str().is_empty());
+
+    if let Some(path) = var("GREP_CONFIG_PATH") {
+        return Some(path);
+    }
+    let config_home =
+        var("XDG_CONFIG_HOME").or_else(|| var("HOME").map(|home| home.join(".config")))?;
+    Some(config_home.join("grep").join("config"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{config_path, Config};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_parse() {
+        let config = Config::parse(
+            "# Defaults\n\n  --line-number\n-C2\n--color=never\nignore = target/, *.log\ncolors=ms=01;32:fn=35\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config.options,
+            vec!["--line-number", "-C2", "--color=never"]
+        );
+        assert_eq!(config.ignore.as_deref(), Some("target/, *.log"));
+        assert_eq!(config.colors.as_deref(), Some("ms=01;32:fn=35"));
+
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+        assert_eq!(
+            Config::parse("-n\npattern").unwrap_err(),
+            "line 2: expected an option or a setting, found \"pattern\""
+        );
+        assert_eq!(
+            Config::parse("colours = ms=1").unwrap_err(),
+            "line 1: unknown setting \"colours\""
+        );
+    }
+
+    #[test]
+    fn test_config_path() {
+        let lookup = |variables: &'static [(&str, &str)]| {
+            config_path(move |name| {
+                variables
+                    .iter()
+                    .find(|(variable, _)| *variable == name)
+                    .map(|(_, value)| PathBuf::from(value))
+            })
+        };
+
+        assert_eq!(
+            lookup(&[("GREP_CONFIG_PATH", "/etc/grep.conf"), ("HOME", "/home/a")]),
+            Some(PathBuf::from("/etc/grep.conf"))
+        );
+        assert_eq!(
+            lookup(&[("XDG_CONFIG_HOME", "/home/a/.cfg"), ("HOME", "/home/a")]),
+            Some(PathBuf::from("/home/a/.cfg/grep/config"))
+        );
+        assert_eq!(
+            lookup(&[("XDG_CONFIG_HOME", ""), ("HOME", "/home/a")]),
+            Some(PathBuf::from("/home/a/.config/grep/config"))
+        );
+        assert_eq!(lookup(&[]), None);
+    }
+}
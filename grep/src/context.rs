@@ -0,0 +1,118 @@
+/*
+    Groups matching lines together with the lines around them, like GNU grep does for -A, -B and -C.
+
+    Every match opens a window of `before` lines above and `after` lines below it.
+    Windows that overlap or touch each other are merged into one group,
+    so each line is part of at most one group and groups never share lines.
+    When printing, consecutive groups are separated by a "--" line.
+*/
+
+use crate::search::Search;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContextLine<'a> {
+    pub index: usize, // Zero based, add one to get the line number
+    pub text: &'a str,
+    pub is_match: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContextGroup<'a> {
+    pub lines: Vec<ContextLine<'a>>,
+}
+
+impl<'a> ContextGroup<'a> {
+    pub fn matches(&self) -> impl Iterator<Item = &ContextLine<'a>> {
+        self.lines.iter().filter(|line| line.is_match)
+    }
+}
+
+/*
+    The lines come from search::Search, which the command line uses as well, so both agree on the windows.
+    A gap between two yielded lines starts a new group. The texts are sliced from the content again,
+    which works as the search reports the byte offset of every line.
+*/
+pub fn group_with_context<'a>(
+    content: &'a str,
+    before: usize,
+    after: usize,
+    is_match: impl Fn(&str) -> bool,
+) -> Vec<ContextGroup<'a>> {
+    let mut groups: Vec<ContextGroup> = Vec::new();
+
+    for line in Search::new(content.as_bytes(), before, after, is_match) {
+        // Reading from a slice never fails.
+        let line = line.unwrap();
+        let context_line = ContextLine {
+            index: line.index,
+            text: &content[line.offset..line.offset + line.text.len()],
+            is_match: line.is_match,
+        };
+
+        match groups.last_mut() {
+            Some(group) if !line.after_gap => group.lines.push(context_line),
+            _ => groups.push(ContextGroup {
+                lines: vec![context_line],
+            }),
+        }
+    }
+
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::group_with_context;
+
+    const CONTENT: &str = "one\ntwo\nthree match\nfour\nfive\nsix\nseven\neight match\nnine\nten\n";
+
+    fn indices(content: &str, before: usize, after: usize) -> Vec<Vec<usize>> {
+        group_with_context(content, before, after, |line| line.contains("match"))
+            .iter()
+            .map(|group| group.lines.iter().map(|line| line.index).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_without_context() {
+        assert_eq!(indices(CONTENT, 0, 0), vec![vec![2], vec![7]]);
+    }
+
+    #[test]
+    fn test_before_and_after() {
+        assert_eq!(indices(CONTENT, 1, 0), vec![vec![1, 2], vec![6, 7]]);
+        assert_eq!(indices(CONTENT, 0, 1), vec![vec![2, 3], vec![7, 8]]);
+    }
+
+    #[test]
+    fn test_windows_are_clipped_at_the_edges() {
+        assert_eq!(indices("match\nx\nmatch", 5, 5), vec![vec![0, 1, 2]]);
+    }
+
+    #[test]
+    fn test_overlapping_and_touching_windows_are_merged() {
+        assert_eq!(
+            indices(CONTENT, 2, 2),
+            vec![vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9]]
+        );
+        // Windows [0, 4) and [4, 9) touch, so no separator is needed between them.
+        assert_eq!(
+            indices(CONTENT, 3, 1),
+            vec![vec![0, 1, 2, 3, 4, 5, 6, 7, 8]]
+        );
+        assert_eq!(indices(CONTENT, 1, 1), vec![vec![1, 2, 3], vec![6, 7, 8]]);
+    }
+
+    #[test]
+    fn test_lines_know_whether_they_matched() {
+        let groups = group_with_context(CONTENT, 1, 1, |line| line.contains("match"));
+        let matches: Vec<&str> = groups[0].matches().map(|line| line.text).collect();
+        assert_eq!(matches, vec!["three match"]);
+        assert!(!groups[0].lines[0].is_match);
+
+        // The texts point into the content, without their line endings.
+        let groups = group_with_context("a\r\nb match\r\nc", 1, 1, |line| line.contains("match"));
+        let texts: Vec<&str> = groups[0].lines.iter().map(|line| line.text).collect();
+        assert_eq!(texts, vec!["a", "b match", "c"]);
+    }
+}
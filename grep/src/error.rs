@@ -0,0 +1,152 @@
+/*
+    Everything that can keep a search from running, or an input from being searched.
+
+    Every kind of error has its own exit code, so scripts can tell them apart without parsing the message.
+    0 and 1 are taken by the grep convention for "a line was selected" and "no line was selected":
+     - 2: missing or invalid arguments, like GNU grep
+     - 3: a path does not exist
+     - 4: a path is not a file (or cannot be treated as one, like the standard input with --in-place)
+     - 5: reading or writing failed
+     - 6: a pattern is not a valid regular expression
+     - 7: a file is not valid UTF-8 where text is required
+*/
+
+use crate::{args::ParseError, regex::RegexError};
+use std::{error::Error, fmt, io, path::PathBuf, str::Utf8Error};
+
+#[derive(Debug)]
+pub enum GrepError {
+    Arguments(ParseError),
+    NotFound(PathBuf),
+    NotAFile(PathBuf),
+    // The operation says what failed, e.g. "reading notes.txt" or "writing output".
+    Io {
+        operation: String,
+        source: io::Error,
+    },
+    InvalidPattern(RegexError),
+    InvalidUtf8 {
+        path: PathBuf,
+        source: Utf8Error,
+    },
+}
+
+impl GrepError {
+    pub fn reading(name: &str, source: io::Error) -> GrepError {
+        GrepError::Io {
+            operation: format!("reading {name}"),
+            source,
+        }
+    }
+
+    pub fn writing(name: &str, source: io::Error) -> GrepError {
+        GrepError::Io {
+            operation: format!("writing {name}"),
+            source,
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            GrepError::Arguments(_) => 2,
+            GrepError::NotFound(_) => 3,
+            GrepError::NotAFile(_) => 4,
+            GrepError::Io { .. } => 5,
+            GrepError::InvalidPattern(_) => 6,
+            GrepError::InvalidUtf8 { .. } => 7,
+        }
+    }
+}
+
+impl fmt::Display for GrepError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GrepError::Arguments(error) => write!(f, "{error}"),
+            GrepError::NotFound(path) => write!(f, "{} does not exist", path.display()),
+            GrepError::NotAFile(path) => write!(f, "{} is not a file", path.display()),
+            GrepError::Io { operation, source } => write!(f, "Error while {operation}: {source}"),
+            GrepError::InvalidPattern(error) => write!(f, "Invalid pattern: {error}"),
+            GrepError::InvalidUtf8 { path, source } => {
+                write!(f, "{} is not valid UTF-8: {source}", path.display())
+            }
+        }
+    }
+}
+
+impl Error for GrepError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            GrepError::Arguments(error) => Some(error),
+            GrepError::Io { source, .. } => Some(source),
+            GrepError::InvalidPattern(error) => Some(error),
+            GrepError::InvalidUtf8 { source, .. } => Some(source),
+            GrepError::NotFound(_) | GrepError::NotAFile(_) => None,
+        }
+    }
+}
+
+impl From<ParseError> for GrepError {
+    fn from(error: ParseError) -> GrepError {
+        GrepError::Arguments(error)
+    }
+}
+
+impl From<RegexError> for GrepError {
+    fn from(error: RegexError) -> GrepError {
+        GrepError::InvalidPattern(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GrepError;
+    use crate::{args::ParseError, regex::Regex};
+    use std::{error::Error, io, path::PathBuf};
+
+    fn examples() -> Vec<GrepError> {
+        vec![
+            ParseError::MissingPattern.into(),
+            GrepError::NotFound(PathBuf::from("absent.txt")),
+            GrepError::NotAFile(PathBuf::from("src")),
+            GrepError::reading("notes.txt", io::Error::other("disk on fire")),
+            Regex::new("(").unwrap_err().into(),
+            GrepError::InvalidUtf8 {
+                path: PathBuf::from("image.png"),
+                source: String::from_utf8(vec![0xff]).unwrap_err().utf8_error(),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_exit_codes_are_distinct() {
+        let codes: Vec<i32> = examples().iter().map(GrepError::exit_code).collect();
+        assert_eq!(codes, vec![2, 3, 4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn test_display() {
+        let messages: Vec<String> = examples().iter().map(ToString::to_string).collect();
+        assert_eq!(messages[0], "No argument was provided for the pattern");
+        assert_eq!(messages[1], "absent.txt does not exist");
+        assert_eq!(messages[2], "src is not a file");
+        assert_eq!(messages[3], "Error while reading notes.txt: disk on fire");
+        assert!(messages[4].starts_with("Invalid pattern: "));
+        assert!(messages[5].starts_with("image.png is not valid UTF-8: "));
+        assert_eq!(
+            GrepError::writing("output", io::ErrorKind::BrokenPipe.into()).to_string(),
+            "Error while writing output: broken pipe"
+        );
+    }
+
+    #[test]
+    fn test_source() {
+        let sources: Vec<bool> = examples()
+            .iter()
+            .map(|error| error.source().is_some())
+            .collect();
+        assert_eq!(sources, vec![true, false, false, true, true, true]);
+
+        let error = GrepError::reading("notes.txt", io::Error::other("disk on fire"));
+        assert_eq!(error.source().unwrap().to_string(), "disk on fire");
+    }
+}
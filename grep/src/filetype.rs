@@ -0,0 +1,66 @@
+/*
+    The file types known to --type and --type-not, each with the globs of its file names.
+    The names follow ripgrep, so "--type rust" means the same in both.
+*/
+
+use crate::glob::Glob;
+
+pub const FILE_TYPES: &[(&str, &[&str])] = &[
+    ("c", &["*.c", "*.h"]),
+    ("cpp", &["*.cpp", "*.cc", "*.cxx", "*.hpp", "*.hh", "*.hxx"]),
+    ("css", &["*.css", "*.scss"]),
+    ("go", &["*.go"]),
+    ("html", &["*.html", "*.htm"]),
+    ("java", &["*.java"]),
+    ("js", &["*.js", "*.mjs", "*.cjs", "*.jsx"]),
+    ("json", &["*.json"]),
+    ("make", &["Makefile", "makefile", "GNUmakefile", "*.mk"]),
+    ("md", &["*.md", "*.markdown"]),
+    ("markdown", &["*.md", "*.markdown"]),
+    ("py", &["*.py", "*.pyi"]),
+    ("rust", &["*.rs"]),
+    ("sh", &["*.sh", "*.bash", "*.zsh"]),
+    ("toml", &["*.toml", "Cargo.lock"]),
+    ("ts", &["*.ts", "*.tsx"]),
+    ("txt", &["*.txt"]),
+    ("yaml", &["*.yaml", "*.yml"]),
+];
+
+// None for types we do not know.
+pub fn globs(name: &str) -> Option<Vec<Glob>> {
+    FILE_TYPES
+        .iter()
+        .find(|(type_name, _)| *type_name == name)
+        .map(|(_, globs)| globs.iter().map(|glob| Glob::new(glob)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{globs, FILE_TYPES};
+    use crate::args::USAGE;
+    use std::path::Path;
+
+    #[test]
+    fn test_globs() {
+        let rust = globs("rust").unwrap();
+        assert!(rust
+            .iter()
+            .any(|glob| glob.matches(Path::new("src/lib.rs"), false)));
+        assert!(!rust
+            .iter()
+            .any(|glob| glob.matches(Path::new("README.md"), false)));
+
+        let make = globs("make").unwrap();
+        assert!(make
+            .iter()
+            .any(|glob| glob.matches(Path::new("sub/Makefile"), false)));
+        assert!(globs("cobol").is_none());
+    }
+
+    #[test]
+    fn test_usage_lists_every_type() {
+        for (name, _) in FILE_TYPES {
+            assert!(USAGE.contains(&format!(" {name},")) || USAGE.contains(&format!(" {name}.")));
+        }
+    }
+}
@@ -0,0 +1,356 @@
+/*
+    Approximate matching: a line matches if it contains a substring within a Levenshtein distance of k of the pattern,
+    i.e. the substring can be turned into the pattern with at most k insertions, deletions or substitutions of characters.
+
+    The textbook solution fills a table with one row per pattern character and one column per text character,
+    which takes O(m * n) steps for a pattern of length m and a text of length n.
+    Myers' bit-parallel algorithm ("A fast bit-vector algorithm for approximate string matching based on
+    dynamic programming", 1999) stores a whole column of that table in two machine words instead:
+    as neighbouring cells differ by at most one, it is enough to store whether each cell is one more (Pv)
+    or one less (Mv) than the cell above. A new column is then computed with a handful of bit operations,
+    so the search takes O(n) steps for patterns of up to 64 characters. Longer patterns fall back to the table.
+
+    Matching works on characters, not bytes, so "ü" is one edit away from "u" and not two.
+    Ignoring case maps every character to its first lowercase character, which keeps the alignment of characters intact.
+*/
+
+use std::{collections::HashMap, ops::Range};
+
+// The longest pattern whose column fits into a u64.
+const WORD_BITS: usize = 64;
+
+#[derive(Debug, Clone)]
+struct Pattern {
+    chars: Vec<char>,
+    // For every character, the positions in the pattern where it appears, as a bit mask. Only used up to WORD_BITS.
+    ascii: [u64; 128],
+    other: HashMap<char, u64>,
+}
+
+impl Pattern {
+    fn new(chars: Vec<char>) -> Pattern {
+        let mut ascii = [0; 128];
+        let mut other = HashMap::new();
+
+        if chars.len() <= WORD_BITS {
+            for (index, &c) in chars.iter().enumerate() {
+                let bit = 1 << index;
+                match c.is_ascii() {
+                    true => ascii[c as usize] |= bit,
+                    false => *other.entry(c).or_insert(0) |= bit,
+                }
+            }
+        }
+
+        Pattern {
+            chars,
+            ascii,
+            other,
+        }
+    }
+
+    fn positions(&self, c: char) -> u64 {
+        match c.is_ascii() {
+            true => self.ascii[c as usize],
+            false => self.other.get(&c).copied().unwrap_or(0),
+        }
+    }
+
+    /*
+        Calls visit for every character of the text with the smallest distance of a substring ending at that character,
+        until visit returns false. The text is a sequence of (position, character) pairs, the position is passed on.
+    */
+    fn scan(
+        &self,
+        text: impl Iterator<Item = (usize, char)>,
+        visit: impl FnMut(usize, usize) -> bool,
+    ) {
+        match self.chars.len() <= WORD_BITS {
+            true => self.scan_bit_parallel(text, visit),
+            false => self.scan_table(text, visit),
+        }
+    }
+
+    fn scan_bit_parallel(
+        &self,
+        text: impl Iterator<Item = (usize, char)>,
+        mut visit: impl FnMut(usize, usize) -> bool,
+    ) {
+        let length = self.chars.len();
+        let last = 1 << (length - 1);
+        // Initially, every cell of the column is one more than the cell above (distance i in row i).
+        let mut plus: u64 = !0;
+        let mut minus: u64 = 0;
+        let mut distance = length;
+
+        for (position, c) in text {
+            let equal = self.positions(c);
+            let vertical = equal | minus;
+            let horizontal = ((equal & plus).wrapping_add(plus) ^ plus) | equal;
+            let mut horizontal_plus = minus | !(horizontal | plus);
+            let mut horizontal_minus = plus & horizontal;
+
+            // The last row holds the distance of the whole pattern.
+            if horizontal_plus & last != 0 {
+                distance += 1;
+            } else if horizontal_minus & last != 0 {
+                distance -= 1;
+            }
+
+            // The first row stays 0, as a match can start anywhere in the text.
+            horizontal_plus <<= 1;
+            horizontal_minus <<= 1;
+            plus = horizontal_minus | !(vertical | horizontal_plus);
+            minus = horizontal_plus & vertical;
+
+            if !visit(position, distance) {
+                return;
+            }
+        }
+    }
+
+    // Sellers' algorithm: the same table, one column at a time.
+    fn scan_table(
+        &self,
+        text: impl Iterator<Item = (usize, char)>,
+        mut visit: impl FnMut(usize, usize) -> bool,
+    ) {
+        let mut column: Vec<usize> = (0..=self.chars.len()).collect();
+
+        for (position, c) in text {
+            let mut diagonal = column[0];
+            for (row, &expected) in self.chars.iter().enumerate() {
+                let substituted = diagonal + usize::from(expected != c);
+                diagonal = column[row + 1];
+                column[row + 1] = substituted.min(column[row + 1] + 1).min(column[row] + 1);
+            }
+
+            if !visit(position, column[self.chars.len()]) {
+                return;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FuzzyMatcher {
+    forward: Pattern,
+    // The reversed pattern finds where a match starts, by scanning backwards from where it ends.
+    backward: Pattern,
+    max_distance: usize,
+    case_insensitive: bool,
+}
+
+impl FuzzyMatcher {
+    pub fn new(pattern: &str, max_distance: usize, case_insensitive: bool) -> FuzzyMatcher {
+        let chars: Vec<char> = pattern
+            .chars()
+            .map(|c| normalize(c, case_insensitive))
+            .collect();
+        let reversed = chars.iter().rev().copied().collect();
+
+        FuzzyMatcher {
+            forward: Pattern::new(chars),
+            backward: Pattern::new(reversed),
+            max_distance,
+            case_insensitive,
+        }
+    }
+
+    // An empty pattern never matches, like in the other matchers.
+    pub fn is_match(&self, line: &str) -> bool {
+        if self.forward.chars.is_empty() {
+            return false;
+        }
+
+        let mut found = false;
+        self.forward.scan(self.forward_text(line), |_, distance| {
+            found = distance <= self.max_distance;
+            !found
+        });
+        found
+    }
+
+    // The smallest distance of any substring of the line to the pattern, if it is at most the maximum distance.
+    pub fn distance(&self, line: &str) -> Option<usize> {
+        self.find(line).map(|(distance, _)| distance)
+    }
+
+    /*
+        The best match in the line: the one with the smallest distance, and of those the one that ends first.
+        Of all substrings ending there with that distance, the shortest is returned.
+    */
+    pub fn find(&self, line: &str) -> Option<(usize, Range<usize>)> {
+        if self.forward.chars.is_empty() {
+            return None;
+        }
+
+        let mut best: Option<(usize, usize)> = None;
+        self.forward.scan(self.forward_text(line), |end, distance| {
+            if best.is_none_or(|(smallest, _)| distance < smallest) {
+                best = Some((distance, end));
+            }
+            distance > 0
+        });
+        let (distance, end) = best.filter(|(distance, _)| *distance <= self.max_distance)?;
+
+        // A distance as large as the pattern is already reached by the empty substring at the end.
+        let mut start = end;
+        if distance < self.forward.chars.len() {
+            let text = line[..end]
+                .char_indices()
+                .rev()
+                .map(|(position, c)| (position, normalize(c, self.case_insensitive)));
+            self.backward.scan(text, |position, found| {
+                start = position;
+                found != distance
+            });
+        }
+
+        Some((distance, start..end))
+    }
+
+    // The positions after every character, so a match ending at a character covers it.
+    fn forward_text<'t>(&self, line: &'t str) -> impl Iterator<Item = (usize, char)> + 't {
+        let case_insensitive = self.case_insensitive;
+        line.char_indices()
+            .map(move |(position, c)| (position + c.len_utf8(), normalize(c, case_insensitive)))
+    }
+}
+
+fn normalize(c: char, case_insensitive: bool) -> char {
+    match case_insensitive {
+        true => c.to_lowercase().next().unwrap_or(c),
+        false => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FuzzyMatcher;
+
+    // The distance of the best substring, computed with the full table for comparison.
+    fn naive_distance(pattern: &str, line: &str) -> usize {
+        let pattern: Vec<char> = pattern.chars().collect();
+        let line: Vec<char> = line.chars().collect();
+        let mut best = pattern.len();
+
+        for start in 0..=line.len() {
+            let mut previous: Vec<usize> = (0..=pattern.len()).collect();
+            for (length, &c) in line[start..].iter().enumerate() {
+                let mut current = vec![length + 1];
+                for row in 0..pattern.len() {
+                    let substituted = previous[row] + usize::from(pattern[row] != c);
+                    current.push(substituted.min(previous[row + 1] + 1).min(current[row] + 1));
+                }
+                best = best.min(current[pattern.len()]);
+                previous = current;
+            }
+        }
+
+        best
+    }
+
+    #[test]
+    fn test_exact_and_approximate() {
+        let matcher = FuzzyMatcher::new("connection", 2, false);
+        assert_eq!(matcher.find("lost conection to db"), Some((1, 5..14)));
+        assert_eq!(matcher.distance("connection reset"), Some(0));
+        assert_eq!(matcher.distance("conecsion"), Some(2));
+        assert_eq!(matcher.distance("collection"), Some(2));
+        assert_eq!(matcher.distance("nothing alike"), None);
+        assert!(matcher.is_match("a connecton"));
+        assert!(!matcher.is_match("con"));
+    }
+
+    #[test]
+    fn test_match_ranges() {
+        let matcher = FuzzyMatcher::new("timeout", 1, false);
+        let line = "request timout after 5s";
+        let (distance, range) = matcher.find(line).unwrap();
+        assert_eq!(distance, 1);
+        assert_eq!(&line[range], "timout");
+
+        // Ranges are byte offsets, even though distances count characters.
+        let matcher = FuzzyMatcher::new("grüße", 1, false);
+        let line = "viele grüse";
+        let (distance, range) = matcher.find(line).unwrap();
+        assert_eq!(distance, 1);
+        assert_eq!(range, 6..12);
+    }
+
+    #[test]
+    fn test_case_insensitive() {
+        assert_eq!(FuzzyMatcher::new("Error", 0, false).distance("ERROR"), None);
+        assert_eq!(
+            FuzzyMatcher::new("Error", 0, true).distance("ERROR"),
+            Some(0)
+        );
+        assert_eq!(
+            FuzzyMatcher::new("ÄRGER", 1, true).distance("ärgrr"),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn test_empty_pattern_and_short_lines() {
+        assert!(!FuzzyMatcher::new("", 3, false).is_match("anything"));
+        // With as many edits as characters, every line matches, but an empty line has nothing to match.
+        assert_eq!(FuzzyMatcher::new("ab", 2, false).find(""), None);
+        assert_eq!(FuzzyMatcher::new("ab", 2, false).find("x"), Some((2, 1..1)));
+        assert_eq!(
+            FuzzyMatcher::new("ab", 2, false).find("xb"),
+            Some((1, 1..2))
+        );
+    }
+
+    #[test]
+    fn test_agrees_with_the_table() {
+        let lines = [
+            "the quick brown fox jumps over the lazy dog",
+            "a",
+            "aaaaaaaaab",
+            "kitten sitting mitten",
+            "Здравствуйте, мир",
+        ];
+        let patterns = [
+            "fox",
+            "quikc",
+            "sitting",
+            "mitt",
+            "ab",
+            "aaab",
+            "ветмир",
+            "zzzz",
+        ];
+
+        for line in lines {
+            for pattern in patterns {
+                let expected = naive_distance(pattern, line);
+                let matcher = FuzzyMatcher::new(pattern, usize::MAX, false);
+                assert_eq!(
+                    matcher.distance(line),
+                    Some(expected),
+                    "{pattern} in {line}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_long_patterns_use_the_table() {
+        let pattern = "a".repeat(70) + "b";
+        let line = "x".repeat(10) + &"a".repeat(70) + "c" + "yyy";
+        let matcher = FuzzyMatcher::new(&pattern, 1, false);
+        assert_eq!(matcher.find(&line), Some((1, 10..80)));
+        assert_eq!(naive_distance(&pattern, &line), 1);
+
+        // Right at the limit of the bit-parallel version.
+        let pattern = "ab".repeat(32);
+        let line = format!("--{}--", "ab".repeat(31) + "a");
+        assert_eq!(
+            FuzzyMatcher::new(&pattern, 2, false).distance(&line),
+            Some(1)
+        );
+    }
+}
@@ -0,0 +1,376 @@
+/*
+    Globs are used to decide which files and directories are skipped while walking a directory.
+    They follow the rules of .gitignore files:
+     - "*" matches any number of characters except "/", "?" matches exactly one character except "/".
+     - "[abc]", "[a-z]" and "[!a-z]" (or "[^a-z]") match one character of a set, or one outside of it.
+     - "**" as a whole path segment matches any number of directories, at the start, in the middle or at the end.
+     - A backslash takes the next character literally, so "\?" only matches a "?".
+     - A glob without a "/" is matched against the file name, no matter how deep the file is.
+     - A glob containing a "/" is matched against the path relative to the searched directory.
+     - A glob ending in "/" only matches directories.
+*/
+
+use std::path::{Component, Path};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Glob {
+    tokens: Vec<Token>,
+    anchored: bool,
+    directory_only: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Char(char),
+    AnyChar, // ?
+    Star,    // *
+    AnyDirs, // "**/", nothing or any number of directories
+    AnyPath, // "/**" at the end, everything below a directory
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Glob {
+        let directory_only = pattern.ends_with('/');
+        let pattern = pattern.trim_end_matches('/');
+        let anchored = pattern.contains('/');
+        let pattern = pattern.trim_start_matches('/');
+
+        Glob {
+            tokens: tokenize(&pattern.chars().collect::<Vec<char>>()),
+            anchored,
+            directory_only,
+        }
+    }
+
+    // Parses a comma separated list of globs, as used by the IGNORE environment variable.
+    pub fn parse_list(list: &str) -> Vec<Glob> {
+        list.split(',')
+            .map(str::trim)
+            .filter(|glob| !glob.is_empty())
+            .map(Glob::new)
+            .collect()
+    }
+
+    pub fn matches(&self, relative_path: &Path, is_dir: bool) -> bool {
+        if self.directory_only && !is_dir {
+            return false;
+        }
+
+        let text = match self.anchored {
+            true => path_to_slashes(relative_path),
+            false => match relative_path.file_name() {
+                Some(name) => name.to_string_lossy().into_owned(),
+                None => return false,
+            },
+        };
+        let mut text: Vec<char> = text.chars().collect();
+
+        // "target/**" matches everything inside target, so it matches the directory as a whole.
+        if is_dir && self.tokens.last() == Some(&Token::AnyPath) {
+            text.push('/');
+        }
+        wildcard_match(&self.tokens, &text)
+    }
+}
+
+fn tokenize(pattern: &[char]) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < pattern.len() {
+        let segment_start = i == 0 || pattern[i - 1] == '/';
+        match pattern[i] {
+            '*' if pattern.get(i + 1) == Some(&'*') && segment_start => match pattern.get(i + 2) {
+                Some('/') => {
+                    tokens.push(Token::AnyDirs);
+                    i += 3;
+                }
+                None => {
+                    tokens.push(Token::AnyPath);
+                    i += 2;
+                }
+                // Like in .gitignore files, "**" inside a segment is an ordinary "*".
+                Some(_) => {
+                    tokens.push(Token::Star);
+                    i += 2;
+                }
+            },
+            '*' => {
+                // Consecutive stars match the same as a single one.
+                if tokens.last() != Some(&Token::Star) {
+                    tokens.push(Token::Star);
+                }
+                i += 1;
+            }
+            '?' => {
+                tokens.push(Token::AnyChar);
+                i += 1;
+            }
+            '[' => match parse_class(&pattern[i + 1..]) {
+                Some((token, length)) => {
+                    tokens.push(token);
+                    i += length + 1;
+                }
+                // Without a closing bracket, "[" is an ordinary character.
+                None => {
+                    tokens.push(Token::Char('['));
+                    i += 1;
+                }
+            },
+            '\\' if i + 1 < pattern.len() => {
+                tokens.push(Token::Char(pattern[i + 1]));
+                i += 2;
+            }
+            c => {
+                tokens.push(Token::Char(c));
+                i += 1;
+            }
+        }
+    }
+
+    tokens
+}
+
+// Parses the rest of a class after the "[", returning it with the number of characters it took up.
+fn parse_class(pattern: &[char]) -> Option<(Token, usize)> {
+    let negated = matches!(pattern.first(), Some('!' | '^'));
+    let mut i = negated as usize;
+    let mut ranges = Vec::new();
+
+    // A "]" right at the start is part of the set, as an empty set would be useless.
+    while i < pattern.len() && (pattern[i] != ']' || i == negated as usize) {
+        let start = pattern[i];
+        match (pattern.get(i + 1), pattern.get(i + 2)) {
+            (Some('-'), Some(&end)) if end != ']' => {
+                ranges.push((start, end));
+                i += 3;
+            }
+            _ => {
+                ranges.push((start, start));
+                i += 1;
+            }
+        }
+    }
+
+    match i < pattern.len() {
+        true => Some((Token::Class { negated, ranges }, i + 1)),
+        false => None,
+    }
+}
+
+// Paths are compared with "/" as separator on every platform, like in .gitignore files.
+fn path_to_slashes(path: &Path) -> String {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/*
+    With "**" there are two kinds of stars, one that stops at "/" and one that does not,
+    so remembering the last star and backtracking to it is no longer enough.
+    Instead we fill in a table of which rest of the pattern matches which rest of the text,
+    from the end of both backwards: matched[t] says whether tokens[p..] matches text[t..].
+    Only the row of the next token is needed for that, so this takes O(tokens * text) time and O(text) memory.
+*/
+fn wildcard_match(tokens: &[Token], text: &[char]) -> bool {
+    // The empty pattern only matches the empty text.
+    let mut next: Vec<bool> = (0..=text.len()).map(|t| t == text.len()).collect();
+
+    for token in tokens.iter().rev() {
+        let mut matched = vec![false; text.len() + 1];
+        for t in (0..=text.len()).rev() {
+            let c = text.get(t).copied();
+            matched[t] = match token {
+                Token::Char(expected) => c == Some(*expected) && next[t + 1],
+                Token::AnyChar => c.is_some_and(|c| c != '/') && next[t + 1],
+                Token::Class { negated, ranges } => {
+                    c.is_some_and(|c| {
+                        let inside = ranges
+                            .iter()
+                            .any(|(start, end)| (*start..=*end).contains(&c));
+                        c != '/' && inside != *negated
+                    }) && next[t + 1]
+                }
+                // Either the star is done, or it takes one more character.
+                Token::Star => next[t] || (c.is_some_and(|c| c != '/') && matched[t + 1]),
+                Token::AnyPath => next[t] || (c.is_some() && matched[t + 1]),
+                // Either no directory at all, or everything up to some "/" further on.
+                Token::AnyDirs => {
+                    next[t]
+                        || text[t..]
+                            .iter()
+                            .enumerate()
+                            .any(|(offset, c)| *c == '/' && next[t + offset + 1])
+                }
+            };
+        }
+        next = matched;
+    }
+
+    next[0]
+}
+
+/*
+    The include and exclude rules set by -g, --type and --type-not for the files found while walking a directory.
+    A glob starting with "!" excludes, every other glob includes.
+    A file is searched if no exclude rule matches it, and if there are include rules, at least one of them does.
+    Directories are only checked against the exclude rules, as include rules like "*.rs" are meant for files.
+*/
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Rules {
+    include: Vec<Glob>,
+    exclude: Vec<Glob>,
+}
+
+impl Rules {
+    pub fn add(&mut self, rule: &str) {
+        match rule.strip_prefix('!') {
+            Some(glob) => self.exclude.push(Glob::new(glob)),
+            None => self.include.push(Glob::new(rule)),
+        }
+    }
+
+    pub fn include(&mut self, glob: Glob) {
+        self.include.push(glob);
+    }
+
+    pub fn exclude(&mut self, glob: Glob) {
+        self.exclude.push(glob);
+    }
+
+    pub fn allows(&self, relative_path: &Path, is_dir: bool) -> bool {
+        if self
+            .exclude
+            .iter()
+            .any(|glob| glob.matches(relative_path, is_dir))
+        {
+            return false;
+        }
+
+        is_dir
+            || self.include.is_empty()
+            || self
+                .include
+                .iter()
+                .any(|glob| glob.matches(relative_path, false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Glob, Rules};
+    use std::path::Path;
+
+    fn matches(glob: &str, path: &str) -> bool {
+        Glob::new(glob).matches(Path::new(path), false)
+    }
+
+    #[test]
+    fn test_name_globs_match_at_any_depth() {
+        let glob = Glob::new("*.log");
+        assert!(glob.matches(Path::new("app.log"), false));
+        assert!(glob.matches(Path::new("logs/2023/app.log"), false));
+        assert!(!glob.matches(Path::new("app.log.txt"), false));
+    }
+
+    #[test]
+    fn test_question_mark() {
+        let glob = Glob::new("file?.txt");
+        assert!(glob.matches(Path::new("file1.txt"), false));
+        assert!(!glob.matches(Path::new("file10.txt"), false));
+    }
+
+    #[test]
+    fn test_anchored_globs_match_relative_path() {
+        let glob = Glob::new("/src/*.rs");
+        assert!(glob.matches(Path::new("src/lib.rs"), false));
+        assert!(!glob.matches(Path::new("tests/src/lib.rs"), false));
+        assert!(!glob.matches(Path::new("src/nested/lib.rs"), false));
+    }
+
+    #[test]
+    fn test_directory_only_globs() {
+        let glob = Glob::new("target/");
+        assert!(glob.matches(Path::new("target"), true));
+        assert!(glob.matches(Path::new("nested/target"), true));
+        assert!(!glob.matches(Path::new("target"), false));
+    }
+
+    #[test]
+    fn test_parse_list() {
+        let globs = Glob::parse_list("target/, *.lock,,.git/");
+        assert_eq!(
+            globs,
+            vec![
+                Glob::new("target/"),
+                Glob::new("*.lock"),
+                Glob::new(".git/")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_double_star() {
+        assert!(matches("src/**/*.rs", "src/lib.rs"));
+        assert!(matches("src/**/*.rs", "src/a/b/lib.rs"));
+        assert!(!matches("src/**/*.rs", "tests/src/lib.rs"));
+        assert!(matches("**/tests/*.rs", "tests/cli.rs"));
+        assert!(matches("**/tests/*.rs", "crates/grep/tests/cli.rs"));
+        assert!(!matches("**/tests/*.rs", "mytests/cli.rs"));
+        assert!(matches("target/**", "target/debug/grep"));
+        assert!(!matches("target/**", "target"));
+        assert!(Glob::new("target/**").matches(Path::new("target"), true));
+        // Inside a segment, "**" is a plain "*".
+        assert!(matches("a**.rs", "abc.rs"));
+        assert!(!matches("/a**.rs", "a/b.rs"));
+    }
+
+    #[test]
+    fn test_classes() {
+        assert!(matches("file[0-9].txt", "file7.txt"));
+        assert!(!matches("file[0-9].txt", "filex.txt"));
+        assert!(matches("file[!0-9].txt", "filex.txt"));
+        assert!(!matches("file[^0-9].txt", "file7.txt"));
+        assert!(matches("[abc].rs", "b.rs"));
+        assert!(matches("[]x].rs", "].rs"));
+        assert!(matches("[a-].rs", "-.rs"));
+        assert!(!matches("/a[/]b", "a/b"));
+        // An unclosed bracket is taken literally.
+        assert!(matches("[abc", "[abc"));
+    }
+
+    #[test]
+    fn test_escapes_and_stars() {
+        assert!(matches("\\*.rs", "*.rs"));
+        assert!(!matches("\\*.rs", "lib.rs"));
+        assert!(matches("a*b*c", "aXbYbZc"));
+        assert!(!matches("a*b*c", "aXbYbZ"));
+        assert!(!matches("", "a"));
+    }
+
+    #[test]
+    fn test_rules() {
+        let allows = |rules: &Rules, path: &str, is_dir| rules.allows(Path::new(path), is_dir);
+
+        let mut rules = Rules::default();
+        assert!(allows(&rules, "anything.txt", false));
+
+        rules.add("*.toml");
+        rules.add("!target/**");
+        assert!(allows(&rules, "Cargo.toml", false));
+        assert!(allows(&rules, "crates/grep/Cargo.toml", false));
+        assert!(!allows(&rules, "src/lib.rs", false));
+        assert!(!allows(&rules, "target/package/Cargo.toml", false));
+        // Include rules do not keep us out of directories, exclude rules do.
+        assert!(allows(&rules, "src", true));
+        assert!(!allows(&rules, "target", true));
+    }
+}
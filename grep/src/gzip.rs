@@ -0,0 +1,598 @@
+/*
+    Decompresses gzip files (RFC 1952) while they are read, so they can be searched line by line like any other input.
+
+    A gzip file is a small header, data compressed with DEFLATE (RFC 1951) and a trailer with a CRC-32 checksum
+    and the size of the original data. Several such members may follow each other, their contents are concatenated.
+
+    DEFLATE splits the data into blocks. A block is either stored as it is, or encoded with Huffman codes:
+    literal bytes, and back-references that copy a number of bytes from up to 32 KiB earlier in the output.
+    The Huffman codes are either fixed by the specification or stored at the start of the block.
+    Codes are canonical, so they can be rebuilt from the code length of every symbol alone.
+
+    The decoder produces one byte at a time and only keeps the last 32 KiB of output for back-references,
+    so memory usage does not depend on the size of the file.
+
+    Zstandard files can be recognized by their first bytes as well, but are not supported.
+*/
+
+use std::io::{self, BufRead, Read};
+
+const WINDOW_SIZE: usize = 32 * 1024;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+// Flags of the gzip header, for optional fields that follow the fixed part.
+const FLAG_HEADER_CRC: u8 = 0x02;
+const FLAG_EXTRA: u8 = 0x04;
+const FLAG_NAME: u8 = 0x08;
+const FLAG_COMMENT: u8 = 0x10;
+
+// Lengths of back-references: a base for each of the symbols 257 to 285, plus a number of extra bits.
+const LENGTH_BASE: [usize; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u32; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [usize; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u32; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// The order in which the code lengths of the code length code are stored in a dynamic block.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+// The table of the CRC-32 used by gzip (and zip and PNG), computed at compile time.
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut crc = index as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = match crc & 1 {
+                1 => 0xedb88320 ^ (crc >> 1),
+                _ => crc >> 1,
+            };
+            bit += 1;
+        }
+        table[index] = crc;
+        index += 1;
+    }
+    table
+}
+
+pub fn is_gzip(start: &[u8]) -> bool {
+    start.starts_with(GZIP_MAGIC)
+}
+
+pub fn is_zstd(start: &[u8]) -> bool {
+    start.starts_with(ZSTD_MAGIC)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid gzip data: {message}"),
+    )
+}
+
+// Reads the input bit by bit, starting with the least significant bit of every byte, as DEFLATE requires.
+struct BitReader<R> {
+    input: R,
+    bits: u32,
+    count: u32,
+}
+
+impl<R: BufRead> BitReader<R> {
+    fn byte(&mut self) -> io::Result<u8> {
+        let byte = match self.input.fill_buf()?.first() {
+            Some(byte) => *byte,
+            None => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+        };
+        self.input.consume(1);
+        Ok(byte)
+    }
+
+    // Up to 16 bits at a time.
+    fn bits(&mut self, count: u32) -> io::Result<u32> {
+        while self.count < count {
+            self.bits |= u32::from(self.byte()?) << self.count;
+            self.count += 8;
+        }
+
+        let value = self.bits & ((1 << count) - 1);
+        self.bits >>= count;
+        self.count -= count;
+        Ok(value)
+    }
+
+    // Skips the rest of the current byte. Stored blocks and the trailer start at a byte boundary.
+    fn align(&mut self) {
+        let skipped = self.count % 8;
+        self.bits >>= skipped;
+        self.count -= skipped;
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        Ok(self.bits(16)? as u16)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(self.bits(16)? | (self.bits(16)? << 16))
+    }
+
+    fn is_at_end(&mut self) -> io::Result<bool> {
+        Ok(self.count == 0 && self.input.fill_buf()?.is_empty())
+    }
+
+    // Walks down the canonical code one bit at a time, see Huffman.
+    fn decode(&mut self, huffman: &Huffman) -> io::Result<usize> {
+        let mut code = 0;
+        let mut first = 0;
+        let mut index = 0;
+
+        for length in 1..16 {
+            code |= self.bits(1)? as usize;
+            let count = usize::from(huffman.counts[length]);
+            if code < first + count {
+                return Ok(usize::from(huffman.symbols[index + code - first]));
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        Err(invalid("unknown Huffman code"))
+    }
+}
+
+/*
+    A canonical Huffman code: the codes of one length are consecutive numbers, assigned to the symbols in order,
+    and the first code of a length follows the last code of the length before.
+    So it is enough to know how many codes there are of each length and which symbols they belong to.
+*/
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>, // Sorted by code length, then by symbol
+}
+
+impl Huffman {
+    // A length of 0 means the symbol does not occur.
+    fn new(lengths: &[u8]) -> io::Result<Huffman> {
+        let mut counts = [0; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        // There are only 2^length codes of each length, minus the prefixes of shorter codes.
+        let mut left: isize = 1;
+        for &count in &counts[1..] {
+            left = (left << 1) - count as isize;
+            if left < 0 {
+                return Err(invalid("too many Huffman codes"));
+            }
+        }
+
+        let mut offsets = [0; 16];
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[usize::from(offsets[length as usize])] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+
+        Ok(Huffman { counts, symbols })
+    }
+
+    // The codes of blocks with fixed Huffman codes, as defined in RFC 1951.
+    fn fixed() -> (Huffman, Huffman) {
+        let mut lengths = [0; 288];
+        lengths[..144].fill(8);
+        lengths[144..256].fill(9);
+        lengths[256..280].fill(7);
+        lengths[280..].fill(8);
+
+        let literals = Huffman::new(&lengths).unwrap();
+        let distances = Huffman::new(&[5; 30]).unwrap();
+        (literals, distances)
+    }
+}
+
+enum State {
+    Header,
+    BlockHeader,
+    Stored {
+        remaining: usize,
+    },
+    Compressed {
+        literals: Huffman,
+        distances: Huffman,
+    },
+    Trailer,
+    Done,
+}
+
+pub struct GzipDecoder<R> {
+    input: BitReader<R>,
+    state: State,
+    last_block: bool,
+    window: Vec<u8>,
+    written: usize, // Bytes produced by the current member
+    crc: u32,
+    // The rest of a back-reference that is being copied.
+    copy_length: usize,
+    copy_distance: usize,
+    // An error that came up after a read had produced some bytes, it is returned by the next read.
+    error: Option<io::Error>,
+}
+
+impl<R: BufRead> GzipDecoder<R> {
+    pub fn new(input: R) -> GzipDecoder<R> {
+        GzipDecoder {
+            input: BitReader {
+                input,
+                bits: 0,
+                count: 0,
+            },
+            state: State::Header,
+            last_block: false,
+            window: vec![0; WINDOW_SIZE],
+            written: 0,
+            crc: !0,
+            copy_length: 0,
+            copy_distance: 0,
+            error: None,
+        }
+    }
+
+    // The next byte of the decompressed data, or None at the end of the last member.
+    fn next_byte(&mut self) -> io::Result<Option<u8>> {
+        loop {
+            if self.copy_length > 0 {
+                self.copy_length -= 1;
+                let byte = self.window[(self.written - self.copy_distance) % WINDOW_SIZE];
+                return Ok(Some(self.output(byte)));
+            }
+
+            match &mut self.state {
+                State::Header => {
+                    self.read_header()?;
+                    self.state = State::BlockHeader;
+                }
+                State::BlockHeader if self.last_block => self.state = State::Trailer,
+                State::BlockHeader => self.state = self.read_block_header()?,
+                State::Stored { remaining: 0 } => self.state = State::BlockHeader,
+                State::Stored { remaining } => {
+                    *remaining -= 1;
+                    let byte = self.input.bits(8)? as u8;
+                    return Ok(Some(self.output(byte)));
+                }
+                State::Compressed {
+                    literals,
+                    distances,
+                } => match self.input.decode(literals)? {
+                    symbol @ 0..=255 => return Ok(Some(self.output(symbol as u8))),
+                    256 => self.state = State::BlockHeader,
+                    symbol => {
+                        let (length, distance) =
+                            read_reference(&mut self.input, symbol, distances)?;
+                        if distance > self.written.min(WINDOW_SIZE) {
+                            return Err(invalid("back-reference before the start of the data"));
+                        }
+                        self.copy_length = length;
+                        self.copy_distance = distance;
+                    }
+                },
+                State::Trailer => {
+                    self.read_trailer()?;
+                    // Another member may follow.
+                    self.state = match self.input.is_at_end()? {
+                        true => State::Done,
+                        false => State::Header,
+                    };
+                }
+                State::Done => return Ok(None),
+            }
+        }
+    }
+
+    fn output(&mut self, byte: u8) -> u8 {
+        self.window[self.written % WINDOW_SIZE] = byte;
+        self.written += 1;
+        self.crc = CRC_TABLE[((self.crc ^ u32::from(byte)) & 0xff) as usize] ^ (self.crc >> 8);
+        byte
+    }
+
+    fn read_header(&mut self) -> io::Result<()> {
+        let input = &mut self.input;
+        if input.bits(8)? != 0x1f || input.bits(8)? != 0x8b {
+            return Err(invalid("not a gzip file"));
+        }
+        if input.bits(8)? != 8 {
+            return Err(invalid("unknown compression method"));
+        }
+        let flags = input.bits(8)? as u8;
+        // Modification time, extra flags and operating system are of no interest.
+        for _ in 0..6 {
+            input.bits(8)?;
+        }
+
+        if flags & FLAG_EXTRA != 0 {
+            for _ in 0..input.u16()? {
+                input.bits(8)?;
+            }
+        }
+        for flag in [FLAG_NAME, FLAG_COMMENT] {
+            // Zero terminated strings
+            if flags & flag != 0 {
+                while input.bits(8)? != 0 {}
+            }
+        }
+        if flags & FLAG_HEADER_CRC != 0 {
+            input.u16()?;
+        }
+
+        self.last_block = false;
+        self.written = 0;
+        self.crc = !0;
+        Ok(())
+    }
+
+    fn read_block_header(&mut self) -> io::Result<State> {
+        self.last_block = self.input.bits(1)? == 1;
+
+        match self.input.bits(2)? {
+            0 => {
+                self.input.align();
+                let length = self.input.u16()?;
+                if self.input.u16()? != !length {
+                    return Err(invalid("corrupted length of stored block"));
+                }
+                Ok(State::Stored {
+                    remaining: usize::from(length),
+                })
+            }
+            1 => {
+                let (literals, distances) = Huffman::fixed();
+                Ok(State::Compressed {
+                    literals,
+                    distances,
+                })
+            }
+            2 => self.read_dynamic_codes(),
+            _ => Err(invalid("unknown block type")),
+        }
+    }
+
+    // The code lengths of both codes are themselves Huffman coded, with runs of repeated lengths.
+    fn read_dynamic_codes(&mut self) -> io::Result<State> {
+        let input = &mut self.input;
+        let literal_count = input.bits(5)? as usize + 257;
+        let distance_count = input.bits(5)? as usize + 1;
+        let code_length_count = input.bits(4)? as usize + 4;
+
+        let mut code_lengths = [0; 19];
+        for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+            code_lengths[symbol] = input.bits(3)? as u8;
+        }
+        let code_lengths = Huffman::new(&code_lengths)?;
+
+        let mut lengths = vec![0; literal_count + distance_count];
+        let mut index = 0;
+        while index < lengths.len() {
+            let (length, repeat) = match input.decode(&code_lengths)? {
+                length @ 0..=15 => (length as u8, 1),
+                16 if index == 0 => {
+                    return Err(invalid("repeated code length without a previous one"))
+                }
+                16 => (lengths[index - 1], 3 + input.bits(2)? as usize),
+                17 => (0, 3 + input.bits(3)? as usize),
+                _ => (0, 11 + input.bits(7)? as usize),
+            };
+            if index + repeat > lengths.len() {
+                return Err(invalid("too many code lengths"));
+            }
+            lengths[index..index + repeat].fill(length);
+            index += repeat;
+        }
+
+        if lengths[256] == 0 {
+            return Err(invalid("missing end of block code"));
+        }
+        Ok(State::Compressed {
+            literals: Huffman::new(&lengths[..literal_count])?,
+            distances: Huffman::new(&lengths[literal_count..])?,
+        })
+    }
+
+    fn read_trailer(&mut self) -> io::Result<()> {
+        self.input.align();
+        let crc = self.input.u32()?;
+        let size = self.input.u32()?;
+
+        if crc != !self.crc {
+            return Err(invalid("checksum mismatch"));
+        }
+        // The size is stored modulo 2^32.
+        if size != self.written as u32 {
+            return Err(invalid("size mismatch"));
+        }
+        Ok(())
+    }
+}
+
+// Reads the length and distance of a back-reference, given the symbol that starts it.
+fn read_reference<R: BufRead>(
+    input: &mut BitReader<R>,
+    symbol: usize,
+    distances: &Huffman,
+) -> io::Result<(usize, usize)> {
+    let index = symbol - 257;
+    if index >= LENGTH_BASE.len() {
+        return Err(invalid("unknown length symbol"));
+    }
+    let length = LENGTH_BASE[index] + input.bits(LENGTH_EXTRA[index])? as usize;
+
+    let index = input.decode(distances)?;
+    if index >= DISTANCE_BASE.len() {
+        return Err(invalid("unknown distance symbol"));
+    }
+    let distance = DISTANCE_BASE[index] + input.bits(DISTANCE_EXTRA[index])? as usize;
+
+    Ok((length, distance))
+}
+
+impl<R: BufRead> Read for GzipDecoder<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        let mut filled = 0;
+
+        while filled < buffer.len() {
+            match self.next_byte() {
+                Ok(Some(byte)) => buffer[filled] = byte,
+                Ok(None) => break,
+                // The bytes decoded so far are valid, so the caller gets them before the error.
+                Err(error) if filled > 0 => {
+                    self.error = Some(error);
+                    break;
+                }
+                Err(error) => return Err(error),
+            }
+            filled += 1;
+        }
+
+        Ok(filled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_gzip, is_zstd, GzipDecoder};
+    use std::io::{self, BufRead, BufReader, Read};
+
+    fn decompress(compressed: &[u8]) -> io::Result<Vec<u8>> {
+        let mut decompressed = Vec::new();
+        GzipDecoder::new(compressed).read_to_end(&mut decompressed)?;
+        Ok(decompressed)
+    }
+
+    // The content of log.txt.gz, generated with the same formula as the fixture.
+    fn log_line(index: usize) -> String {
+        const WORDS: [&str; 12] = [
+            "alpha", "bravo", "charlie", "delta", "echo", "foxtrot", "golf", "hotel", "india",
+            "juliet", "kilo", "lima",
+        ];
+        let status = if index.is_multiple_of(997) { "ERROR" } else { "ok" };
+        format!(
+            "{index:05} {} {} took {} ms status={status}",
+            WORDS[(index * index * 7 + 3) % 12],
+            WORDS[(index * 13 + 5) % 12],
+            (index * 37) % 1000
+        )
+    }
+
+    #[test]
+    fn test_magic_bytes() {
+        assert!(is_gzip(include_bytes!("../tests/fixtures/stored.gz")));
+        assert!(!is_gzip(b"plain text"));
+        assert!(is_zstd(&[0x28, 0xb5, 0x2f, 0xfd, 0x00]));
+        assert!(!is_zstd(&[0x28, 0xb5]));
+    }
+
+    #[test]
+    fn test_dynamic_codes() {
+        // Over 200 KiB, so back-references wrap around the window many times.
+        let reader = BufReader::new(GzipDecoder::new(
+            &include_bytes!("../tests/fixtures/log.txt.gz")[..],
+        ));
+
+        let mut count = 0;
+        for (index, line) in reader.lines().enumerate() {
+            assert_eq!(line.unwrap(), log_line(index));
+            count += 1;
+        }
+        assert_eq!(count, 6000);
+    }
+
+    #[test]
+    fn test_stored_and_fixed_blocks() {
+        assert_eq!(
+            decompress(include_bytes!("../tests/fixtures/stored.gz")).unwrap(),
+            b"first line\nsecond line with a match\nthird line\n"
+        );
+        assert_eq!(
+            decompress(include_bytes!("../tests/fixtures/fixed.gz")).unwrap(),
+            b"match match match\nno\nmatch\n"
+        );
+    }
+
+    #[test]
+    fn test_optional_header_fields() {
+        assert_eq!(
+            decompress(include_bytes!("../tests/fixtures/header.gz")).unwrap(),
+            b"header fields are skipped\n"
+        );
+    }
+
+    #[test]
+    fn test_multiple_members() {
+        assert_eq!(
+            decompress(include_bytes!("../tests/fixtures/multi.gz")).unwrap(),
+            b"member one\nmember two\n"
+        );
+    }
+
+    #[test]
+    fn test_corrupted_input() {
+        let error = decompress(include_bytes!("../tests/fixtures/bad_crc.gz")).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let error = decompress(include_bytes!("../tests/fixtures/truncated.gz")).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+
+        assert!(decompress(b"plain text").is_err());
+        assert!(decompress(b"").is_err());
+    }
+
+    #[test]
+    fn test_bytes_before_an_error_are_returned() {
+        let mut decoder = GzipDecoder::new(&include_bytes!("../tests/fixtures/truncated.gz")[..]);
+        let mut buffer = vec![0; 1 << 20];
+
+        let read = decoder.read(&mut buffer).unwrap();
+        assert!(read > 0);
+        let error = decoder.read(&mut buffer).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_streaming() {
+        // The first lines are available long before the end of the input was read.
+        let compressed = include_bytes!("../tests/fixtures/log.txt.gz");
+        let mut input = &compressed[..];
+        let mut reader = BufReader::with_capacity(64, GzipDecoder::new(&mut input));
+
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line.trim_end(), log_line(0));
+        drop(reader);
+        assert!(input.len() > compressed.len() / 2);
+    }
+}
@@ -0,0 +1,501 @@
+/*
+    Just enough JSON for the --json output: a Value type, a serializer and a parser.
+
+    The serializer is the Display implementation of Value, which writes compact JSON on a single line,
+    so every record of the output can be read with a line based reader.
+    The parser is the reverse, so other tools written in Rust (and our tests) can read the output back.
+
+    Objects keep their entries in a Vec instead of a map, so the keys are written in the order they were added,
+    e.g. "type" always comes before "data".
+
+    JSON strings can only hold valid UTF-8, so other bytes are written in base64 (the standard alphabet with padding).
+*/
+
+use std::fmt::{self, Write};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn object(entries: Vec<(&str, Value)>) -> Value {
+        Value::Object(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    // The value of the first entry with the given key, if this is an object.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(entries) => entries
+                .iter()
+                .find(|(entry, _)| entry == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(text: &str) -> Value {
+        Value::String(text.to_string())
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Value {
+        Value::Bool(value)
+    }
+}
+
+// Offsets and counts fit into an f64 without loss up to 2^53, which is more than any file we will search.
+impl From<usize> for Value {
+    fn from(number: usize) -> Value {
+        Value::Number(number as f64)
+    }
+}
+
+impl From<u64> for Value {
+    fn from(number: u64) -> Value {
+        Value::Number(number as f64)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(value) => write!(f, "{value}"),
+            // JSON has no representation for NaN and infinity.
+            Value::Number(number) if !number.is_finite() => write!(f, "null"),
+            Value::Number(number) => write!(f, "{number}"),
+            Value::String(text) => write_string(f, text),
+            Value::Array(values) => {
+                f.write_char('[')?;
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{value}")?;
+                }
+                f.write_char(']')
+            }
+            Value::Object(entries) => {
+                f.write_char('{')?;
+                for (index, (key, value)) in entries.iter().enumerate() {
+                    if index > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+// Quotes and backslashes are escaped, as well as all control characters. Everything else is written as UTF-8.
+fn write_string(f: &mut fmt::Formatter, text: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in text.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            '\u{8}' => f.write_str("\\b")?,
+            '\u{c}' => f.write_str("\\f")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+pub fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        // Three bytes make four characters of six bits each, missing bytes count as zero.
+        let group = chunk.iter().enumerate().fold(0u32, |group, (index, byte)| {
+            group | (*byte as u32) << (16 - 8 * index)
+        });
+        for index in 0..4 {
+            match index <= chunk.len() {
+                true => encoded.push(ALPHABET[(group >> (18 - 6 * index) & 0x3f) as usize] as char),
+                false => encoded.push('='),
+            }
+        }
+    }
+    encoded
+}
+
+// Parses a complete JSON document. Whitespace around the value is allowed, anything else after it is an error.
+pub fn parse(text: &str) -> Result<Value, String> {
+    let mut parser = Parser { text, position: 0 };
+    let value = parser.parse_value()?;
+    parser.skip_whitespace();
+
+    match parser.position == text.len() {
+        true => Ok(value),
+        false => Err(parser.error("Unexpected characters after the value")),
+    }
+}
+
+struct Parser<'t> {
+    text: &'t str,
+    position: usize, // In bytes
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> String {
+        format!("{message} at position {}", self.position)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.position..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(' ' | '\t' | '\n' | '\r') = self.peek() {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            _ => Err(self.error(&format!("Expected '{expected}'"))),
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+
+        match self.peek() {
+            Some('{') => self.parse_object(),
+            Some('[') => self.parse_array(),
+            Some('"') => Ok(Value::String(self.parse_string()?)),
+            Some('-' | '0'..='9') => self.parse_number(),
+            Some(_) => self.parse_literal(),
+            None => Err(self.error("Expected a value")),
+        }
+    }
+
+    fn parse_literal(&mut self) -> Result<Value, String> {
+        for (literal, value) in [
+            ("null", Value::Null),
+            ("true", Value::Bool(true)),
+            ("false", Value::Bool(false)),
+        ] {
+            if self.text[self.position..].starts_with(literal) {
+                self.position += literal.len();
+                return Ok(value);
+            }
+        }
+
+        Err(self.error("Expected a value"))
+    }
+
+    fn parse_object(&mut self) -> Result<Value, String> {
+        self.expect('{')?;
+        let mut entries = Vec::new();
+
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.position += 1;
+            return Ok(Value::Object(entries));
+        }
+
+        loop {
+            self.skip_whitespace();
+            let key = self.parse_string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            entries.push((key, self.parse_value()?));
+
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some('}') => return Ok(Value::Object(entries)),
+                _ => return Err(self.error("Expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.position += 1;
+            return Ok(Value::Array(values));
+        }
+
+        loop {
+            values.push(self.parse_value()?);
+
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some(']') => return Ok(Value::Array(values)),
+                _ => return Err(self.error("Expected ',' or ']'")),
+            }
+        }
+    }
+
+    // Follows the JSON grammar: an optional minus, no leading zeros, then an optional fraction and exponent.
+    fn parse_number(&mut self) -> Result<Value, String> {
+        let start = self.position;
+        let digits = |parser: &mut Parser| {
+            let start = parser.position;
+            while let Some('0'..='9') = parser.peek() {
+                parser.position += 1;
+            }
+            parser.position > start
+        };
+
+        if self.peek() == Some('-') {
+            self.position += 1;
+        }
+        if self.peek() == Some('0') {
+            self.position += 1;
+        } else if !digits(self) {
+            return Err(self.error("Expected a digit"));
+        }
+        if self.peek() == Some('.') {
+            self.position += 1;
+            if !digits(self) {
+                return Err(self.error("Expected a digit"));
+            }
+        }
+        if let Some('e' | 'E') = self.peek() {
+            self.position += 1;
+            if let Some('+' | '-') = self.peek() {
+                self.position += 1;
+            }
+            if !digits(self) {
+                return Err(self.error("Expected a digit"));
+            }
+        }
+
+        self.text[start..self.position]
+            .parse()
+            .map(Value::Number)
+            .map_err(|_| self.error("Invalid number"))
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut text = String::new();
+
+        loop {
+            match self.next() {
+                Some('"') => return Ok(text),
+                Some('\\') => text.push(self.parse_escape()?),
+                Some(c) if c < '\u{20}' => {
+                    return Err(self.error("Unescaped control character in string"))
+                }
+                Some(c) => text.push(c),
+                None => return Err(self.error("Unterminated string")),
+            }
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<char, String> {
+        let c = match self.next() {
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some('/') => '/',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('b') => '\u{8}',
+            Some('f') => '\u{c}',
+            Some('u') => return self.parse_unicode_escape(),
+            _ => return Err(self.error("Invalid escape sequence")),
+        };
+
+        Ok(c)
+    }
+
+    // Characters outside the Basic Multilingual Plane are written as two escapes, a UTF-16 surrogate pair.
+    fn parse_unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.parse_hex()?;
+        if !(0xD800..0xDC00).contains(&high) {
+            return char::from_u32(high).ok_or_else(|| self.error("Invalid unicode escape"));
+        }
+
+        if !self.text[self.position..].starts_with("\\u") {
+            return Err(self.error("Expected the second half of a surrogate pair"));
+        }
+        self.position += 2;
+        let low = self.parse_hex()?;
+        if !(0xDC00..0xE000).contains(&low) {
+            return Err(self.error("Invalid second half of a surrogate pair"));
+        }
+
+        char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00))
+            .ok_or_else(|| self.error("Invalid unicode escape"))
+    }
+
+    fn parse_hex(&mut self) -> Result<u32, String> {
+        let digits = self
+            .text
+            .get(self.position..self.position + 4)
+            .filter(|digits| digits.chars().all(|c| c.is_ascii_hexdigit()))
+            .ok_or_else(|| self.error("Expected four hex digits"))?;
+        self.position += 4;
+
+        Ok(u32::from_str_radix(digits, 16).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{base64, parse, Value};
+
+    fn round_trip(value: &Value) -> Value {
+        parse(&value.to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_serialize() {
+        let value = Value::object(vec![
+            ("type", "match".into()),
+            ("line_number", 3usize.into()),
+            ("binary", Value::Null),
+            ("ok", true.into()),
+            (
+                "list",
+                Value::Array(vec![1usize.into(), Value::Number(-0.5)]),
+            ),
+        ]);
+        assert_eq!(
+            value.to_string(),
+            r#"{"type":"match","line_number":3,"binary":null,"ok":true,"list":[1,-0.5]}"#
+        );
+        assert_eq!(Value::Number(f64::NAN).to_string(), "null");
+    }
+
+    #[test]
+    fn test_serialize_escapes() {
+        let value = Value::from("quote \" backslash \\ tab \t newline \n bell \u{7} ü 🦀");
+        assert_eq!(
+            value.to_string(),
+            r#""quote \" backslash \\ tab \t newline \n bell \u0007 ü 🦀""#
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let values = [
+            Value::Null,
+            Value::Bool(false),
+            Value::Number(0.0),
+            Value::Number(1234567890.0),
+            Value::Number(-1.25e-7),
+            Value::from(""),
+            Value::from("\u{0}\u{1f}\"\\/ Grüße नमस्ते 🦀\r\n"),
+            Value::Array(vec![]),
+            Value::Object(vec![]),
+            Value::object(vec![
+                (
+                    "nested",
+                    Value::Array(vec![Value::object(vec![("a", Value::Null)])]),
+                ),
+                ("text", "line\twith tab".into()),
+            ]),
+        ];
+
+        for value in &values {
+            assert_eq!(&round_trip(value), value);
+        }
+    }
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"a\xffb foo"), "Yf9iIGZvbw==");
+    }
+
+    #[test]
+    fn test_parse() {
+        let value =
+            parse(" { \"a\" : [ 1 , 2.5e2, -0 ] , \"b\":\"\\u00fc\\ud83e\\udd80\\/\" } ").unwrap();
+        assert_eq!(
+            value.get("a").unwrap().as_array().unwrap(),
+            &[
+                Value::Number(1.0),
+                Value::Number(250.0),
+                Value::Number(-0.0)
+            ]
+        );
+        assert_eq!(value.get("b").unwrap().as_str(), Some("ü🦀/"));
+        assert_eq!(value.get("c"), None);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for text in [
+            "",
+            "{",
+            "[1,]",
+            "{\"a\"}",
+            "01",
+            "1.",
+            "-",
+            "\"open",
+            "\"\\x\"",
+            "\"\\ud83e\"",
+            "tru",
+            "1 2",
+            "\"a\nb\"",
+        ] {
+            assert!(parse(text).is_err(), "{text:?} should not parse");
+        }
+    }
+}
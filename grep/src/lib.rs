@@ -1,96 +1,1051 @@
-use std::{env, path::Path};
+pub mod args;
+pub mod casefold;
+pub mod color;
+pub mod config;
+pub mod context;
+pub mod error;
+pub mod filetype;
+pub mod fuzzy;
+pub mod glob;
+pub mod gzip;
+pub mod json;
+pub mod literal;
+pub mod matcher;
+pub mod parallel;
+pub mod regex;
+pub mod replace;
+pub mod search;
+pub mod tui;
+pub mod walk;
 
-#[derive(Debug)]
+pub use args::{parse_arguments, ParseError, USAGE};
+pub use error::GrepError;
+
+use color::{ColorChoice, Colors};
+use fuzzy::FuzzyMatcher;
+use glob::{Glob, Rules};
+use gzip::GzipDecoder;
+use json::Value;
+use matcher::{AnyOf, Inverted, Matcher, Substring, WholeLine, Word};
+use regex::Regex;
+use replace::Replacement;
+use search::{Search, SearchLine};
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, IsTerminal, Read, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use tui::Document;
+
+// Reading from this path means reading from the standard input.
+pub const STDIN_PATH: &str = "-";
+
+#[derive(Debug, Default)]
 pub struct Arguments {
-    pub pattern: String,
-    pub path: String, // We don't use Path here, as Paths are OsStr, whose size is not known at compile time
+    pub patterns: Vec<String>, // A line is selected if any of the patterns matches
+    pub paths: Vec<String>, // We don't use Path here, as Paths are OsStr, whose size is not known at compile time. "-" is stdin.
     pub case_insensitive: bool,
+    pub regex: bool,
+    pub invert: bool,
+    pub word: bool,
+    pub line: bool,
+    pub line_number: bool,
+    pub count: bool,
+    pub files_with_matches: bool,
+    pub files_without_match: bool,
+    pub quiet: bool, // Prints nothing, the exit code tells whether a line was selected
+    pub max_count: Option<usize>, // Stops reading an input after this many selected lines
+    pub before_context: usize,
+    pub after_context: usize,
+    pub ignore: Vec<Glob>, // Only used when searching a directory
+    pub rules: Rules, // Set by -g, --type and --type-not, also only used when searching a directory
+    pub color: ColorChoice,
+    pub colors: String, // Decorations in the format of GREP_COLORS, see color.rs
+    pub threads: usize, // 0 picks one thread per available CPU
+    pub json: bool,
+    pub replace: Option<String>, // Replaces the matches in selected lines, can refer to capture groups
+    pub in_place: bool, // Writes the replacements back into the files instead of printing them
+    pub backup_suffix: Option<String>, // Keeps the original of every file edited in place
+    pub fuzzy: Option<usize>, // Matches substrings within this edit distance of a pattern, best matches first
+    pub decompress: bool,     // Searches the content of gzip compressed inputs
+    pub interactive: bool,    // Lets the user browse the results and pick the lines to print
+}
+
+/*
+    The outcome of a search that ran to the end.
+    Paths that could not be searched are reported on stderr right away and only counted here,
+    so the remaining paths are still searched.
+*/
+#[derive(Debug, Default, PartialEq)]
+pub struct Summary {
+    pub matched: bool,
+    pub errors: usize,
+    pub error_code: Option<i32>, // The exit code of the first error
+    pub stats: Stats,
+    pub quiet: bool, // Set by -q, where a selected line counts more than errors
+}
+
+/*
+    Counted per input and added up over the whole search.
+    Only --json counts the individual matches, as it is the only output that lists them.
+    -l, -L and -q stop reading at the first selected line, so they count at most one line per input.
+    With -m, no more than that many lines are counted per input.
+*/
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Stats {
+    pub searches: usize,
+    pub searches_with_match: usize,
+    pub matched_lines: usize,
+    pub matches: usize,
+}
+
+impl Stats {
+    // The stats of a single input in which the given number of lines were selected.
+    fn searched(matched_lines: usize) -> Stats {
+        Stats {
+            searches: 1,
+            searches_with_match: usize::from(matched_lines > 0),
+            matched_lines,
+            matches: 0,
+        }
+    }
+
+    fn add(&mut self, other: Stats) {
+        self.searches += other.searches;
+        self.searches_with_match += other.searches_with_match;
+        self.matched_lines += other.matched_lines;
+        self.matches += other.matches;
+    }
+
+    fn to_json(self) -> Value {
+        Value::object(vec![
+            ("searches", self.searches.into()),
+            ("searches_with_match", self.searches_with_match.into()),
+            ("matched_lines", self.matched_lines.into()),
+            ("matches", self.matches.into()),
+        ])
+    }
 }
 
-impl Arguments {
-    fn new(pattern: String, path: String, case_insensitive: bool) -> Arguments {
-        Arguments {
-            pattern,
-            path,
-            case_insensitive,
+impl Summary {
+    /*
+        Following the grep convention: 0 if a line was selected, 1 if none was.
+        If an error occurred, the exit code of the first one (see GrepError), or 2 if it is not known.
+        Like in GNU grep, -q exits with 0 once a line is selected, even if an error occurred before.
+    */
+    pub fn exit_code(&self) -> i32 {
+        if self.quiet && self.matched {
+            0
+        } else if self.errors > 0 {
+            self.error_code.unwrap_or(2)
+        } else if self.matched {
+            0
+        } else {
+            1
         }
     }
 }
 
-pub fn run(arguments: Arguments) -> Result<(), String> {
-    let content = read_file(&Path::new(&arguments.path))?;
+// Errors while reading only affect the current input, errors while writing affect the whole search.
+enum Failure {
+    Input(GrepError),
+    Output(io::Error),
+}
+
+impl From<io::Error> for Failure {
+    fn from(error: io::Error) -> Failure {
+        Failure::Output(error)
+    }
+}
+
+// Ends the search before all inputs were searched.
+enum Stop {
+    // With -q, the first selected line already decides the exit code, so the remaining inputs are skipped.
+    Answered,
+    Output(io::Error),
+}
+
+impl From<io::Error> for Stop {
+    fn from(error: io::Error) -> Stop {
+        Stop::Output(error)
+    }
+}
+
+pub fn run(arguments: Arguments) -> Result<Summary, GrepError> {
+    if arguments.in_place && arguments.replace.is_none() {
+        return Err(GrepError::Arguments(ParseError::RequiredOption {
+            option: "--in-place".to_string(),
+            required: "--replace".to_string(),
+        }));
+    }
+
+    let start = Instant::now();
+    let stdout = io::stdout();
+    // JSON is read by programs, which have no use for escape codes.
+    let colors = match !arguments.json && arguments.color.should_color(stdout.is_terminal()) {
+        true => Some(Colors::parse(&arguments.colors)),
+        false => None,
+    };
+    let searcher = Searcher {
+        arguments: &arguments,
+        matcher: build_matcher(&arguments)?,
+        ranking: fuzzy_matchers(&arguments),
+        colors,
+        replacement: arguments.replace.as_deref().map(Replacement::new),
+        // Like GNU grep, file names are only printed when there is more than one file to search.
+        show_names: arguments.paths.len() > 1
+            || arguments.paths.iter().any(|path| Path::new(path).is_dir()),
+    };
+
+    let mut summary = Summary {
+        quiet: arguments.quiet,
+        ..Summary::default()
+    };
+    let inputs = collect_inputs(&arguments, &mut summary);
+    let threads = match arguments.threads {
+        0 => parallel::default_threads(),
+        threads => threads,
+    };
+
+    if arguments.interactive {
+        return run_interactive(searcher, &inputs, summary);
+    }
+
+    let mut output = BufWriter::new(stdout.lock());
+    let result = search_inputs(&searcher, inputs, threads, &mut summary, &mut output);
 
-    let lines = match arguments.case_insensitive {
-        true => filter_case_insensitive(&content, &arguments.pattern),
-        false => filter_case_sensitive(&content, &arguments.pattern),
+    let result = match result {
+        Ok(()) | Err(Stop::Answered) => Ok(()),
+        Err(Stop::Output(error)) => Err(error),
     };
+    let result = result.and_then(|_| match arguments.json {
+        true => writeln!(output, "{}", json_summary(&summary, start.elapsed())),
+        false => Ok(()),
+    });
+    match result.and_then(|_| output.flush()) {
+        Ok(()) => Ok(summary),
+        Err(error) => output_error(error, summary),
+    }
+}
+
+/*
+    Reads all inputs into memory, lets the user pick lines in the browser and prints them like search results.
+    The lines are highlighted with the pattern the user ended up with.
+*/
+fn run_interactive(
+    mut searcher: Searcher,
+    inputs: &[Input],
+    mut summary: Summary,
+) -> Result<Summary, GrepError> {
+    let mut documents = Vec::new();
+    for input in inputs {
+        match searcher.read_input(input) {
+            Ok(document) => documents.extend(document),
+            Err(Failure::Input(error)) => report(&mut summary, error),
+            Err(Failure::Output(error)) => return output_error(error, summary),
+        }
+    }
+    summary.stats.searches = documents.len();
+
+    let (selection, matcher) =
+        tui::browse(searcher.arguments, documents).map_err(|error| GrepError::Io {
+            operation: "running the interactive mode".to_string(),
+            source: error,
+        })?;
+    searcher.matcher = matcher;
+
+    let mut names: Vec<&str> = selection
+        .iter()
+        .map(|selected| selected.name.as_str())
+        .collect();
+    names.dedup();
+    summary.matched = !selection.is_empty();
+    summary.stats.searches_with_match = names.len();
+    summary.stats.matched_lines = selection.len();
+
+    let stdout = io::stdout();
+    let mut output = BufWriter::new(stdout.lock());
+    let result = selection
+        .iter()
+        .try_for_each(|selected| searcher.write_line(&mut output, &selected.name, &selected.line));
+    match result.and_then(|_| output.flush()) {
+        Ok(()) => Ok(summary),
+        Err(error) => output_error(error, summary),
+    }
+}
+
+fn search_inputs(
+    searcher: &Searcher,
+    inputs: Vec<Input>,
+    threads: usize,
+    summary: &mut Summary,
+    output: &mut impl Write,
+) -> Result<(), Stop> {
+    if threads > 1 && inputs.len() > 1 {
+        // Every worker writes into its own buffer, which is copied to the output once it is this input's turn.
+        parallel::map_ordered(
+            inputs,
+            threads,
+            |input| {
+                let mut buffer = Vec::new();
+                let result = searcher.search_input(&input, &mut buffer);
+                (result, buffer)
+            },
+            |(result, buffer)| {
+                output.write_all(&buffer)?;
+                record(summary, result)
+            },
+        )
+    } else {
+        inputs.iter().try_for_each(|input| {
+            let result = searcher.search_input(input, output);
+            record(summary, result)
+        })
+    }
+}
+
+// A single thing to search. Directories are expanded into the files inside them before the search starts.
+enum Input {
+    Stdin,
+    File { path: PathBuf, in_directory: bool },
+}
+
+fn collect_inputs(arguments: &Arguments, summary: &mut Summary) -> Vec<Input> {
+    let mut inputs = Vec::new();
+
+    for path in &arguments.paths {
+        if path == STDIN_PATH {
+            inputs.push(Input::Stdin);
+        } else if Path::new(path).is_dir() {
+            match walk::walk(Path::new(path), &arguments.ignore, &arguments.rules) {
+                Ok(files) => inputs.extend(files.into_iter().map(|path| Input::File {
+                    path,
+                    in_directory: true,
+                })),
+                Err(error) => report(summary, error),
+            }
+        } else {
+            inputs.push(Input::File {
+                path: PathBuf::from(path),
+                in_directory: false,
+            });
+        }
+    }
+
+    inputs
+}
+
+// Adds the result of one input to the summary. Only errors while writing and the answer of -q stop the whole search.
+fn record(summary: &mut Summary, result: Result<Stats, Failure>) -> Result<(), Stop> {
+    match result {
+        Ok(stats) => {
+            summary.matched |= stats.matched_lines > 0;
+            summary.stats.add(stats);
+            if summary.quiet && summary.matched {
+                return Err(Stop::Answered);
+            }
+        }
+        Err(Failure::Input(error)) => report(summary, error),
+        Err(Failure::Output(error)) => return Err(Stop::Output(error)),
+    }
 
-    println!("{}", lines.join("\n"));
     Ok(())
 }
 
-pub fn parse_arguments(args: Vec<String>) -> Result<Arguments, String> {
-    let mut iterator = args.into_iter();
+// Errors of single inputs are printed right away and the search goes on.
+fn report(summary: &mut Summary, error: GrepError) {
+    eprintln!("{error}");
+    summary.errors += 1;
+    summary.error_code.get_or_insert(error.exit_code());
+}
+
+/*
+    The records of --json follow the JSON Lines format of ripgrep: one object per line with a "type" and "data".
+    Every input gets a "begin" record, a "match" or "context" record per printed line and an "end" record.
+    The last line is a "summary" record for the whole search.
+*/
+fn json_record(kind: &str, data: Vec<(&str, Value)>) -> Value {
+    Value::object(vec![("type", kind.into()), ("data", Value::object(data))])
+}
 
-    let pattern = match iterator.next() {
-        Some(pattern) => pattern,
-        None => {
-            return Err(
-                "No argument was provided for the pattern.\nUsage: grep <pattern> <file>"
-                    .to_string(),
-            )
+// Like in ripgrep, text is wrapped in an object, which leaves room to add other representations later.
+fn json_text(text: &str) -> Value {
+    Value::object(vec![("text", text.into())])
+}
+
+// Bytes that are not valid UTF-8 are sent as base64 in a "bytes" field instead, again like ripgrep.
+fn json_bytes(bytes: &[u8]) -> Value {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => json_text(text),
+        Err(_) => Value::object(vec![("bytes", json::base64(bytes).as_str().into())]),
+    }
+}
+
+fn json_summary(summary: &Summary, elapsed: Duration) -> Value {
+    json_record(
+        "summary",
+        vec![
+            (
+                "elapsed_total",
+                Value::object(vec![
+                    ("secs", elapsed.as_secs().into()),
+                    ("nanos", u64::from(elapsed.subsec_nanos()).into()),
+                    (
+                        "human",
+                        format!("{:.6}s", elapsed.as_secs_f64()).as_str().into(),
+                    ),
+                ]),
+            ),
+            ("stats", summary.stats.to_json()),
+        ],
+    )
+}
+
+fn output_error(error: io::Error, summary: Summary) -> Result<Summary, GrepError> {
+    match error.kind() {
+        // The reader of our output went away (e.g. "grep ... | head"), so there is nothing left to do.
+        io::ErrorKind::BrokenPipe => Ok(summary),
+        _ => Err(GrepError::writing("output", error)),
+    }
+}
+
+// Everything needed to search an input and print the results, set up once per run.
+struct Searcher<'a> {
+    arguments: &'a Arguments,
+    matcher: Box<dyn Matcher + Sync>,
+    // Ranks the lines selected by --fuzzy, empty otherwise.
+    ranking: Vec<FuzzyMatcher>,
+    colors: Option<Colors>,
+    replacement: Option<Replacement>,
+    show_names: bool,
+}
+
+impl Searcher<'_> {
+    fn search_input(&self, input: &Input, output: &mut impl Write) -> Result<Stats, Failure> {
+        let (path, in_directory) = match input {
+            Input::Stdin if self.arguments.in_place => {
+                return Err(Failure::Input(GrepError::NotAFile(PathBuf::from(
+                    "(standard input)",
+                ))))
+            }
+            Input::Stdin if self.arguments.decompress => {
+                let reader = self.decompress(io::stdin().lock(), "(standard input)")?;
+                return self.search_reader(reader, "(standard input)", output);
+            }
+            Input::Stdin => {
+                return self.search_reader(io::stdin().lock(), "(standard input)", output)
+            }
+            Input::File { path, in_directory } => (path, *in_directory),
+        };
+
+        let name = path.display().to_string();
+        let reader = match in_directory {
+            false => open_file(path).map_err(Failure::Input)?,
+            true => BufReader::new(
+                File::open(path)
+                    .map_err(|error| Failure::Input(GrepError::reading(&name, error)))?,
+            ),
+        };
+
+        match self.arguments.decompress {
+            true => {
+                let reader = self.decompress(reader, &name)?;
+                self.search_file(reader, path, &name, in_directory, output)
+            }
+            false => self.search_file(reader, path, &name, in_directory, output),
         }
+    }
+
+    // The whole content of the input, for the interactive mode. Binary files inside directories are skipped.
+    fn read_input(&self, input: &Input) -> Result<Option<Document>, Failure> {
+        let (reader, name, in_directory): (Box<dyn BufRead>, String, bool) = match input {
+            Input::Stdin => (
+                Box::new(io::stdin().lock()),
+                "(standard input)".to_string(),
+                false,
+            ),
+            Input::File { path, in_directory } => (
+                Box::new(open_file(path).map_err(Failure::Input)?),
+                path.display().to_string(),
+                *in_directory,
+            ),
+        };
+        let mut reader = match self.arguments.decompress {
+            true => self.decompress(reader, &name)?,
+            false => reader,
+        };
+
+        let mut content = Vec::new();
+        reader
+            .read_to_end(&mut content)
+            .map_err(|error| Failure::Input(GrepError::reading(&name, error)))?;
+        if in_directory && walk::is_binary(&content) {
+            return Ok(None);
+        }
+
+        Ok(Some(Document::new(&name, &content)))
+    }
+
+    fn search_file(
+        &self,
+        mut reader: impl BufRead,
+        path: &Path,
+        name: &str,
+        in_directory: bool,
+        output: &mut impl Write,
+    ) -> Result<Stats, Failure> {
+        // Binary files inside directories are skipped. Only the first chunk is checked, so they are never read completely.
+        if in_directory {
+            let start = reader
+                .fill_buf()
+                .map_err(|error| Failure::Input(GrepError::reading(name, error)))?;
+            if walk::is_binary(start) {
+                return Ok(Stats::default());
+            }
+        }
+
+        match (&self.replacement, self.arguments.in_place) {
+            (Some(replacement), true) => self.edit_in_place(reader, path, name, replacement),
+            _ => self.search_reader(reader, name, output),
+        }
+    }
+
+    /*
+        Compressed inputs are recognized by their first bytes instead of their name,
+        so the standard input and files with unusual names are decompressed as well.
+        Everything else is searched as it is.
+    */
+    fn decompress<'r>(
+        &self,
+        mut reader: impl BufRead + 'r,
+        name: &str,
+    ) -> Result<Box<dyn BufRead + 'r>, Failure> {
+        let start = reader
+            .fill_buf()
+            .map_err(|error| Failure::Input(GrepError::reading(name, error)))?;
+
+        if gzip::is_gzip(start) {
+            Ok(Box::new(BufReader::new(GzipDecoder::new(reader))))
+        } else if gzip::is_zstd(start) {
+            Err(Failure::Input(GrepError::reading(
+                name,
+                io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "zstd compression is not supported",
+                ),
+            )))
+        } else {
+            Ok(Box::new(reader))
+        }
+    }
+
+    /*
+        Replaces the matches in all selected lines of the file and writes it back.
+        Line endings and lines that are not valid UTF-8 are kept byte for byte.
+        Files without matches are not written at all, so they keep their timestamps and get no backup.
+    */
+    fn edit_in_place(
+        &self,
+        mut reader: impl BufRead,
+        path: &Path,
+        name: &str,
+        replacement: &Replacement,
+    ) -> Result<Stats, Failure> {
+        let arguments = self.arguments;
+        let mut content = Vec::new();
+        let mut line = Vec::new();
+        let mut stats = Stats::searched(0);
+
+        loop {
+            line.clear();
+            let read = reader
+                .read_until(b'\n', &mut line)
+                .map_err(|error| Failure::Input(GrepError::reading(name, error)))?;
+            if read == 0 {
+                break;
+            }
+
+            let text = line.strip_suffix(b"\n").unwrap_or(&line);
+            let text = text.strip_suffix(b"\r").unwrap_or(text);
+            let ending = &line[text.len()..];
+            let below_limit = arguments
+                .max_count
+                .is_none_or(|max| stats.matched_lines < max);
+            let text = match std::str::from_utf8(text) {
+                Ok(text) if below_limit && self.matcher.is_match(text) => text,
+                _ => {
+                    content.extend_from_slice(&line);
+                    continue;
+                }
+            };
+
+            let (replaced, ranges) = replacement.apply(text, &self.matcher.find_groups(text));
+            stats.matched_lines += 1;
+            stats.matches += ranges.len();
+            content.extend_from_slice(replaced.as_bytes());
+            content.extend_from_slice(ending);
+        }
+        stats.searches_with_match = usize::from(stats.matched_lines > 0);
+
+        if stats.matches > 0 {
+            // The file is replaced by a new one, so it must not be open anymore.
+            drop(reader);
+            let backup_suffix = arguments.backup_suffix.as_deref();
+            replace::write_in_place(path, &content, backup_suffix)
+                .map_err(|error| Failure::Input(GrepError::writing(name, error)))?;
+        }
+
+        Ok(stats)
+    }
+
+    fn search_reader(
+        &self,
+        reader: impl BufRead,
+        name: &str,
+        output: &mut impl Write,
+    ) -> Result<Stats, Failure> {
+        let arguments = self.arguments;
+        let is_selected = |line: &str| self.matcher.is_match(line);
+        let read_error = |error: io::Error| Failure::Input(GrepError::reading(name, error));
+
+        if arguments.json {
+            return self.search_json(reader, name, output);
+        }
+
+        // As the search is lazy, we stop reading the input after the first selected line, unless -m 0 allows none.
+        if arguments.quiet || arguments.files_with_matches || arguments.files_without_match {
+            let limit = arguments.max_count.unwrap_or(1).min(1);
+            let mut search = Search::new(reader, 0, 0, is_selected).stop_after(limit);
+            let matched = search.next().transpose().map_err(read_error)?.is_some();
+            let listed = match arguments.files_without_match {
+                true => !matched,
+                false => matched,
+            };
+            if listed && !arguments.quiet {
+                writeln!(output, "{}", self.paint(|colors| &colors.file_name, name))?;
+            }
+            return Ok(Stats::searched(usize::from(matched)));
+        }
+
+        if arguments.count {
+            let mut count = 0;
+            for line in self.search(reader, 0, 0) {
+                line.map_err(read_error)?;
+                count += 1;
+            }
+
+            if self.show_names {
+                self.write_prefix(output, name, ':')?;
+            }
+            writeln!(output, "{count}")?;
+            return Ok(Stats::searched(count));
+        }
+
+        // Inverted, none of the selected lines has a match, so there is nothing to rank.
+        if arguments.fuzzy.is_some() && !arguments.invert {
+            return self.search_ranked(reader, name, output);
+        }
+
+        let has_context = arguments.before_context > 0 || arguments.after_context > 0;
+        let search = self.search(reader, arguments.before_context, arguments.after_context);
+
+        let mut matched_lines = 0;
+        for line in search {
+            let line = line.map_err(read_error)?;
+            matched_lines += usize::from(line.is_match);
+
+            if line.after_gap && has_context {
+                writeln!(output, "{}", self.paint(|colors| &colors.separator, "--"))?;
+            }
+            self.write_line(output, name, &line)?;
+        }
+
+        Ok(Stats::searched(matched_lines))
+    }
+
+    // Searches for the selected lines and their context, stopping after as many selected lines as -m allows.
+    fn search<R: BufRead>(
+        &self,
+        reader: R,
+        before: usize,
+        after: usize,
+    ) -> Search<R, impl Fn(&str) -> bool + '_> {
+        let search = Search::new(reader, before, after, |line: &str| {
+            self.matcher.is_match(line)
+        });
+        match self.arguments.max_count {
+            Some(max) => search.stop_after(max),
+            None => search,
+        }
+    }
+
+    /*
+        Approximate matches are ranked: the lines with the smallest distance come first, ties keep their order.
+        That needs all selected lines of the input before the first one can be printed.
+    */
+    fn search_ranked(
+        &self,
+        reader: impl BufRead,
+        name: &str,
+        output: &mut impl Write,
+    ) -> Result<Stats, Failure> {
+        let mut lines = self
+            .search(reader, 0, 0)
+            .collect::<io::Result<Vec<SearchLine>>>()
+            .map_err(|error| Failure::Input(GrepError::reading(name, error)))?;
+
+        lines.sort_by_cached_key(|line| self.fuzzy_distance(&line.text));
+        for line in &lines {
+            self.write_line(output, name, line)?;
+        }
+
+        Ok(Stats::searched(lines.len()))
+    }
+
+    // The smallest edit distance of any fuzzy pattern to the line. Lines without a fuzzy match sort last.
+    fn fuzzy_distance(&self, line: &str) -> usize {
+        self.ranking
+            .iter()
+            .filter_map(|matcher| matcher.distance(line))
+            .min()
+            .unwrap_or(usize::MAX)
+    }
+
+    fn write_line(&self, output: &mut impl Write, name: &str, line: &SearchLine) -> io::Result<()> {
+        // Like GNU grep, selected lines use ':' as separator and context lines use '-'.
+        let separator = if line.is_match { ':' } else { '-' };
+        if self.show_names {
+            self.write_prefix(output, name, separator)?;
+        }
+        if self.arguments.line_number {
+            let number = (line.index + 1).to_string();
+            write!(
+                output,
+                "{}",
+                self.paint(|colors| &colors.line_number, &number)
+            )?;
+            write!(
+                output,
+                "{}",
+                self.paint(|colors| &colors.separator, &separator.to_string())
+            )?;
+        }
+
+        let text = match &self.replacement {
+            Some(replacement) if line.is_match && !self.arguments.invert => {
+                let groups = self.matcher.find_groups(&line.text);
+                let (replaced, ranges) = replacement.apply(&line.text, &groups);
+                self.highlight_ranges(&replaced, &ranges, true)
+            }
+            _ => self.highlight(&line.text, line.is_match),
+        };
+        writeln!(output, "{text}")
+    }
+
+    /*
+        Line numbers start at 1, offsets are in bytes and the line text has no line ending.
+        The offsets count the bytes of the input, so for lines that are not valid UTF-8
+        they are mapped back from the decoded text, and the line is sent as it was read.
+    */
+    fn search_json(
+        &self,
+        reader: impl BufRead,
+        name: &str,
+        output: &mut impl Write,
+    ) -> Result<Stats, Failure> {
+        let arguments = self.arguments;
+        let read_error = |error: io::Error| Failure::Input(GrepError::reading(name, error));
+        let search = self.search(reader, arguments.before_context, arguments.after_context);
+
+        writeln!(
+            output,
+            "{}",
+            json_record("begin", vec![("path", json_text(name))])
+        )?;
+
+        let mut stats = Stats::searched(0);
+        for line in search {
+            let line = line.map_err(read_error)?;
+
+            // The same lines as in highlight() contain matches.
+            let ranges = match line.is_match != arguments.invert {
+                true => self.matcher.find_matches(&line.text),
+                false => Vec::new(),
+            };
+            if line.is_match {
+                stats.matched_lines += 1;
+                stats.matches += ranges.len();
+            }
+
+            let submatches = ranges
+                .into_iter()
+                .map(|range| {
+                    let (text, range) = match &line.bytes {
+                        Some(bytes) => {
+                            let range = search::raw_offset(bytes, range.start)
+                                ..search::raw_offset(bytes, range.end);
+                            (json_bytes(&bytes[range.clone()]), range)
+                        }
+                        None => (json_text(&line.text[range.clone()]), range),
+                    };
+                    Value::object(vec![
+                        ("match", text),
+                        ("start", range.start.into()),
+                        ("end", range.end.into()),
+                    ])
+                })
+                .collect();
+            let text = match &line.bytes {
+                Some(bytes) => json_bytes(bytes),
+                None => json_text(&line.text),
+            };
+            let kind = if line.is_match { "match" } else { "context" };
+            let record = json_record(
+                kind,
+                vec![
+                    ("path", json_text(name)),
+                    ("lines", text),
+                    ("line_number", (line.index + 1).into()),
+                    ("absolute_offset", line.offset.into()),
+                    ("submatches", Value::Array(submatches)),
+                ],
+            );
+            writeln!(output, "{record}")?;
+        }
+        stats.searches_with_match = usize::from(stats.matched_lines > 0);
+
+        let end = json_record(
+            "end",
+            vec![("path", json_text(name)), ("stats", stats.to_json())],
+        );
+        writeln!(output, "{end}")?;
+
+        Ok(stats)
+    }
+
+    fn write_prefix(&self, output: &mut impl Write, name: &str, separator: char) -> io::Result<()> {
+        write!(output, "{}", self.paint(|colors| &colors.file_name, name))?;
+        write!(
+            output,
+            "{}",
+            self.paint(|colors| &colors.separator, &separator.to_string())
+        )
+    }
+
+    fn paint(&self, sgr: impl Fn(&Colors) -> &String, text: &str) -> String {
+        match &self.colors {
+            Some(colors) => color::paint(sgr(colors), text),
+            None => text.to_string(),
+        }
+    }
+
+    /*
+        Selected lines contain matches, unless the selection is inverted.
+        In that case the context lines are the ones that contain matches.
+    */
+    fn highlight(&self, text: &str, is_selected: bool) -> String {
+        if self.colors.is_none() {
+            return text.to_string();
+        }
+
+        let ranges = match is_selected != self.arguments.invert {
+            true => self.matcher.find_matches(text),
+            false => Vec::new(),
+        };
+        self.highlight_ranges(text, &ranges, is_selected)
+    }
+
+    fn highlight_ranges(&self, text: &str, ranges: &[Range<usize>], is_selected: bool) -> String {
+        let colors = match &self.colors {
+            Some(colors) => colors,
+            None => return text.to_string(),
+        };
+
+        let (match_sgr, line_sgr) = match is_selected {
+            true => (&colors.selected_match, &colors.selected_line),
+            false => (&colors.context_match, &colors.context_line),
+        };
+        color::highlight(text, ranges, match_sgr, line_sgr)
+    }
+}
+
+/*
+    Builds the matcher that decides which lines are selected, taking all matching options of the Arguments into account.
+    A line is selected if any of the patterns matches, so without any pattern nothing is selected.
+*/
+pub fn build_matcher(arguments: &Arguments) -> Result<Box<dyn Matcher + Sync>, GrepError> {
+    let matcher: Box<dyn Matcher + Sync> = match arguments.regex {
+        true if arguments.patterns.is_empty() => Box::new(AnyOf::<Regex>::new(Vec::new())),
+        true => {
+            /*
+                Each pattern is checked on its own first, so errors point into the pattern as it was written,
+                and a pattern like "a)|(?:b" can not break out of the group it is wrapped into below.
+            */
+            for pattern in &arguments.patterns {
+                Regex::new(pattern)?;
+            }
+
+            // Multiple patterns are combined into one alternation, so each line is scanned only once.
+            // Whole lines are checked by anchoring the alternation, so that a shorter alternative can not hide a longer one.
+            let alternation = arguments
+                .patterns
+                .iter()
+                .map(|pattern| format!("(?:{pattern})"))
+                .collect::<Vec<String>>()
+                .join("|");
+            let pattern = match arguments.line {
+                true => format!("^(?:{alternation})$"),
+                false => alternation,
+            };
+
+            let regex = match arguments.case_insensitive {
+                true => Regex::new_case_insensitive(&pattern),
+                false => Regex::new(&pattern),
+            }?;
+            match arguments.word {
+                true => Box::new(Word::new(regex)),
+                false => Box::new(regex),
+            }
+        }
+        // Every literal pattern is narrowed down on its own, so matches of two patterns never count as one word.
+        false => Box::new(AnyOf::new(
+            arguments
+                .patterns
+                .iter()
+                .map(|pattern| literal_matcher(arguments, pattern))
+                .collect(),
+        )),
     };
 
-    let path = match iterator.next() {
-        Some(path) => path,
-        None => {
-            return Err(
-                "No argument was provided for the path.\nUsage: grep <pattern> <file>".to_string(),
-            )
+    Ok(match arguments.invert {
+        true => Box::new(Inverted::new(matcher)),
+        false => matcher,
+    })
+}
+
+fn literal_matcher(arguments: &Arguments, pattern: &str) -> Box<dyn Matcher + Sync> {
+    let matcher: Box<dyn Matcher + Sync> = match (arguments.fuzzy, arguments.case_insensitive) {
+        (Some(distance), case_insensitive) => {
+            Box::new(FuzzyMatcher::new(pattern, distance, case_insensitive))
         }
+        (None, true) => Box::new(Substring::ignore_case(pattern)),
+        (None, false) => Box::new(Substring::new(pattern)),
     };
 
-    let case_insensitive = env::var("CASE_INSENSITIVE").is_ok();
+    if arguments.line {
+        Box::new(WholeLine::new(matcher))
+    } else if arguments.word {
+        Box::new(Word::new(matcher))
+    } else {
+        matcher
+    }
+}
 
-    Ok(Arguments::new(pattern, path, case_insensitive))
+fn fuzzy_matchers(arguments: &Arguments) -> Vec<FuzzyMatcher> {
+    match arguments.fuzzy {
+        Some(distance) => arguments
+            .patterns
+            .iter()
+            .map(|pattern| FuzzyMatcher::new(pattern, distance, arguments.case_insensitive))
+            .collect(),
+        None => Vec::new(),
+    }
 }
 
-pub fn read_file(path: &Path) -> Result<String, String> {
+pub fn open_file(path: &Path) -> Result<BufReader<File>, GrepError> {
     if !path.exists() {
-        return Err(format!("{} does not exist", path.display()));
+        return Err(GrepError::NotFound(path.to_path_buf()));
     } else if !path.is_file() {
-        return Err(format!("{} is not a file", path.display()));
+        return Err(GrepError::NotAFile(path.to_path_buf()));
     }
 
-    let content = std::fs::read_to_string(path);
-    if content.is_err() {
-        return Err(format!(
-            "Error while reading file: {}",
-            content.unwrap_err()
-        ));
-    }
+    let file =
+        File::open(path).map_err(|error| GrepError::reading(&path.display().to_string(), error))?;
+    Ok(BufReader::new(file))
+}
 
-    Ok(content.unwrap())
+// The whole file as text, e.g. to pass it to the filter functions.
+pub fn read_file(path: &Path) -> Result<String, GrepError> {
+    let mut content = Vec::new();
+    open_file(path)?
+        .read_to_end(&mut content)
+        .map_err(|error| GrepError::reading(&path.display().to_string(), error))?;
+
+    String::from_utf8(content).map_err(|error| GrepError::InvalidUtf8 {
+        path: path.to_path_buf(),
+        source: error.utf8_error(),
+    })
 }
 
-pub fn filter_case_sensitive<'a, 'b>(content: &'a str, pattern: &'b str) -> Vec<&'a str> {
+// The lines selected by the matcher, e.g. filter(content, &Word::new(Substring::ignore_case("error"))).
+pub fn filter<'a>(content: &'a str, matcher: &impl Matcher) -> Vec<&'a str> {
     content
         .lines()
-        .filter(|line| line.contains(pattern))
+        .filter(|line| matcher.is_match(line))
         .collect()
 }
 
-pub fn filter_case_insensitive<'a, 'b>(content: &'a str, pattern: &'b str) -> Vec<&'a str> {
-    content
-        .lines()
-        .filter(|line| line.to_lowercase().contains(&pattern.to_lowercase()))
+// The whole content is scanned for the pattern, and only the lines around hits are looked at.
+pub fn filter_case_sensitive<'a>(content: &'a str, pattern: &str) -> Vec<&'a str> {
+    literal::Finder::new(pattern).lines(content).collect()
+}
+
+// The pattern is folded once, and no line is copied or lowercased while filtering.
+pub fn filter_case_insensitive<'a>(content: &'a str, pattern: &str) -> Vec<&'a str> {
+    filter(content, &Substring::ignore_case(pattern))
+}
+
+// The byte ranges of all non-overlapping occurrences of the pattern, from left to right.
+pub fn find_case_sensitive(line: &str, pattern: &str) -> Vec<Range<usize>> {
+    literal::Finder::new(pattern).find_all(line.as_bytes())
+}
+
+// Like find_case_sensitive, but ignores case using Unicode case folding.
+pub fn find_case_insensitive(line: &str, pattern: &str) -> Vec<Range<usize>> {
+    casefold::CaseFolder::new(pattern).find_all(line)
+}
+
+// Empty matches are left out, as there is nothing to highlight.
+pub fn find_regex(line: &str, regex: &Regex) -> Vec<Range<usize>> {
+    regex
+        .find_iter(line)
+        .filter(|found| found.start() != found.end())
+        .map(|found| found.range())
         .collect()
 }
 
+// The lines containing the pattern within the given edit distance, ranked by their distance.
+pub fn filter_fuzzy<'a>(content: &'a str, pattern: &str, distance: usize) -> Vec<(&'a str, usize)> {
+    let matcher = FuzzyMatcher::new(pattern, distance, false);
+    let mut lines: Vec<(&str, usize)> = content
+        .lines()
+        .filter_map(|line| Some((line, matcher.distance(line)?)))
+        .collect();
+
+    lines.sort_by_key(|(_, distance)| *distance);
+    lines
+}
+
+pub fn filter_regex<'a>(content: &'a str, regex: &Regex) -> Vec<&'a str> {
+    filter(content, regex)
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::{filter_case_insensitive, filter_case_sensitive};
+    use crate::{
+        build_matcher, collect_inputs, filter, filter_case_insensitive, filter_case_sensitive,
+        filter_fuzzy, filter_regex, find_case_insensitive, find_case_sensitive, find_regex,
+        fuzzy_matchers, json,
+        matcher::{Inverted, Substring, WholeLine, Word},
+        parallel::default_threads,
+        read_file,
+        regex::Regex,
+        replace::Replacement,
+        run, search_inputs, Arguments, GrepError, Searcher, Summary,
+    };
+    use std::{env, fs, io, path::PathBuf, process, time::Instant};
 
     #[test]
     fn test_filter_case_sensitive() {
@@ -109,4 +1064,729 @@ mod tests {
         let result = filter_case_insensitive(content, pattern);
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn test_filter_case_insensitive_unicode() {
+        let content = "Die Straße ist lang\nSTRASSE\nΟΔΟΣ\nЗдравствуйте\nनमस्ते\n";
+        assert_eq!(
+            filter_case_insensitive(content, "strasse"),
+            vec!["Die Straße ist lang", "STRASSE"]
+        );
+        assert_eq!(filter_case_insensitive(content, "οδος"), vec!["ΟΔΟΣ"]);
+        assert_eq!(
+            filter_case_insensitive(content, "ЗДРАВ"),
+            vec!["Здравствуйте"]
+        );
+        assert_eq!(filter_case_insensitive(content, "नमस्ते"), vec!["नमस्ते"]);
+    }
+
+    #[test]
+    fn test_filter_regex() {
+        let content = "fn main() {}\nlet x = 42;\npub fn run() {}\n// fn in a comment\n";
+        let regex = Regex::new("^(pub )?fn \\w+\\(").unwrap();
+        let expected = vec!["fn main() {}", "pub fn run() {}"];
+        let result = filter_regex(content, &regex);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_filter_regex_case_insensitive() {
+        let content = "ERROR: disk full\nwarning: low memory\nInfo: all good\n";
+        let regex = Regex::new_case_insensitive("^(error|warning):").unwrap();
+        let expected = vec!["ERROR: disk full", "warning: low memory"];
+        let result = filter_regex(content, &regex);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_filter_with_matchers() {
+        let content = "an error\nERRORS: 2\nno problem\nError\n";
+        assert_eq!(
+            filter(content, &Word::new(Substring::ignore_case("error"))),
+            vec!["an error", "Error"]
+        );
+        assert_eq!(
+            filter(content, &WholeLine::new(Substring::ignore_case("error"))),
+            vec!["Error"]
+        );
+        assert_eq!(
+            filter(content, &Inverted::new(Substring::ignore_case("error"))),
+            vec!["no problem"]
+        );
+    }
+
+    #[test]
+    fn test_build_matcher() {
+        let matcher = |patterns: &[&str], arguments: Arguments| {
+            build_matcher(&Arguments {
+                patterns: patterns.iter().map(|pattern| pattern.to_string()).collect(),
+                ..arguments
+            })
+            .unwrap()
+        };
+
+        let word = matcher(
+            &["con", "cat"],
+            Arguments {
+                word: true,
+                case_insensitive: true,
+                ..Arguments::default()
+            },
+        );
+        assert!(word.is_match("a CAT"));
+        assert!(!word.is_match("concat"));
+
+        let line = matcher(
+            &["a|ab"],
+            Arguments {
+                regex: true,
+                line: true,
+                invert: true,
+                ..Arguments::default()
+            },
+        );
+        assert!(!line.is_match("ab"));
+        assert!(line.is_match("abc"));
+        assert_eq!(line.find_matches("ab"), vec![0..2]);
+
+        // Special characters of literal patterns have no meaning.
+        let literal = matcher(&["a.c"], Arguments::default());
+        assert!(literal.is_match("xa.cx"));
+        assert!(!literal.is_match("abc"));
+
+        assert!(!matcher(&[], Arguments::default()).is_match("anything"));
+        assert!(!matcher(
+            &[],
+            Arguments {
+                regex: true,
+                ..Arguments::default()
+            }
+        )
+        .is_match("anything"));
+
+        // Every pattern has to be valid by itself, and errors point into it.
+        let error = |patterns: &[&str], line: bool| {
+            let arguments = Arguments {
+                patterns: patterns.iter().map(|pattern| pattern.to_string()).collect(),
+                regex: true,
+                line,
+                ..Arguments::default()
+            };
+            match build_matcher(&arguments) {
+                Err(GrepError::InvalidPattern(error)) => error,
+                _ => panic!("{patterns:?} was accepted"),
+            }
+        };
+        assert_eq!(error(&["a)|(?:b"], false).position, 1);
+        assert_eq!(error(&["x", "a{3,2}"], true).position, 1);
+    }
+
+    #[test]
+    fn test_filter_fuzzy() {
+        let content = "conection refused
+connection reset
+no network
+collection done
+";
+        assert_eq!(
+            filter_fuzzy(content, "connection", 2),
+            vec![
+                ("connection reset", 0),
+                ("conection refused", 1),
+                ("collection done", 2)
+            ]
+        );
+        assert_eq!(
+            filter_fuzzy(content, "connection", 0),
+            vec![("connection reset", 0)]
+        );
+    }
+
+    #[test]
+    fn test_fuzzy_output_is_ranked() {
+        let arguments = Arguments {
+            patterns: vec!["timeout".to_string()],
+            fuzzy: Some(2),
+            line_number: true,
+            ..Arguments::default()
+        };
+        let output = search_text(
+            arguments,
+            "tmeout x
+request timeout
+nothing
+timeuot
+TIMEOUT
+",
+        );
+        assert_eq!(
+            output,
+            "2:request timeout
+1:tmeout x
+4:timeuot
+"
+        );
+    }
+
+    #[test]
+    fn test_summary_exit_code() {
+        let summary = |matched, errors| Summary {
+            matched,
+            errors,
+            ..Summary::default()
+        };
+        assert_eq!(summary(true, 0).exit_code(), 0);
+        assert_eq!(summary(false, 0).exit_code(), 1);
+        assert_eq!(summary(true, 1).exit_code(), 2);
+        assert_eq!(summary(false, 3).exit_code(), 2);
+    }
+
+    #[test]
+    fn test_find_case_sensitive() {
+        assert_eq!(find_case_sensitive("ab ab Ab", "ab"), vec![0..2, 3..5]);
+        assert_eq!(find_case_sensitive("aaaa", "aa"), vec![0..2, 2..4]);
+        assert!(find_case_sensitive("abc", "").is_empty());
+    }
+
+    #[test]
+    fn test_find_case_insensitive() {
+        assert_eq!(
+            find_case_insensitive("ab AB Ab", "aB"),
+            vec![0..2, 3..5, 6..8]
+        );
+        // The ranges are byte offsets into the original line, even for multi-byte characters.
+        assert_eq!(
+            find_case_insensitive("Grüße GRÜSSE", "grü"),
+            vec![0..4, 8..12]
+        );
+        assert!(find_case_insensitive("abc", "abcd").is_empty());
+    }
+
+    #[test]
+    fn test_find_regex() {
+        let regex = Regex::new("\\d+").unwrap();
+        assert_eq!(find_regex("a1 b22 c", &regex), vec![1..2, 4..6]);
+
+        // Empty matches have nothing to highlight.
+        let regex = Regex::new("x*").unwrap();
+        assert_eq!(find_regex("axb", &regex), vec![1..2]);
+    }
+
+    // Searches the content like run() does with --json and parses every line of the output back.
+    fn search_json(arguments: Arguments, content: impl AsRef<[u8]>) -> Vec<json::Value> {
+        let searcher = Searcher {
+            arguments: &arguments,
+            matcher: build_matcher(&arguments).unwrap(),
+            ranking: fuzzy_matchers(&arguments),
+            colors: None,
+            replacement: None,
+            show_names: false,
+        };
+        let mut output = Vec::new();
+        let stats = searcher
+            .search_reader(content.as_ref(), "notes.txt", &mut output)
+            .unwrap_or_else(|_| panic!("search failed"));
+
+        let records: Vec<json::Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| json::parse(line).unwrap())
+            .collect();
+        let end = records.last().unwrap().get("data").unwrap();
+        assert_eq!(end.get("stats"), Some(&stats.to_json()));
+
+        records
+    }
+
+    fn types(records: &[json::Value]) -> Vec<&str> {
+        records
+            .iter()
+            .map(|record| record.get("type").unwrap().as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_json_records() {
+        let arguments = Arguments {
+            patterns: vec!["o".to_string()],
+            json: true,
+            after_context: 1,
+            ..Arguments::default()
+        };
+        let records = search_json(arguments, "one two\n\"quoted\"\tx\nnothing\nzzz\n");
+        assert_eq!(
+            types(&records),
+            vec!["begin", "match", "match", "match", "context", "end"]
+        );
+
+        let first = records[1].get("data").unwrap();
+        assert_eq!(
+            first.get("path").unwrap().get("text").unwrap().as_str(),
+            Some("notes.txt")
+        );
+        assert_eq!(first.get("line_number").unwrap().as_f64(), Some(1.0));
+        assert_eq!(first.get("absolute_offset").unwrap().as_f64(), Some(0.0));
+        let submatches = first.get("submatches").unwrap().as_array().unwrap();
+        let ranges: Vec<(f64, f64)> = submatches
+            .iter()
+            .map(|submatch| {
+                assert_eq!(
+                    submatch.get("match").unwrap().get("text").unwrap().as_str(),
+                    Some("o")
+                );
+                (
+                    submatch.get("start").unwrap().as_f64().unwrap(),
+                    submatch.get("end").unwrap().as_f64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(ranges, vec![(0.0, 1.0), (6.0, 7.0)]);
+
+        // Quotes and tabs survive the round trip.
+        let second = records[2].get("data").unwrap();
+        assert_eq!(
+            second.get("lines").unwrap().get("text").unwrap().as_str(),
+            Some("\"quoted\"\tx")
+        );
+        assert_eq!(second.get("absolute_offset").unwrap().as_f64(), Some(8.0));
+
+        let end = records[5].get("data").unwrap().get("stats").unwrap();
+        assert_eq!(end.get("matched_lines").unwrap().as_f64(), Some(3.0));
+        assert_eq!(end.get("matches").unwrap().as_f64(), Some(4.0));
+    }
+
+    #[test]
+    fn test_json_offsets_count_bytes() {
+        let arguments = Arguments {
+            patterns: vec!["foo".to_string()],
+            json: true,
+            ..Arguments::default()
+        };
+        let records = search_json(arguments, b"a\xffb foo\n\xfe\n");
+        let data = records[1].get("data").unwrap();
+        assert_eq!(
+            data.get("lines").unwrap().get("bytes").unwrap().as_str(),
+            Some("Yf9iIGZvbw==")
+        );
+
+        let submatch = &data.get("submatches").unwrap().as_array().unwrap()[0];
+        assert_eq!(
+            submatch.get("match").unwrap().get("text").unwrap().as_str(),
+            Some("foo")
+        );
+        assert_eq!(submatch.get("start").unwrap().as_f64(), Some(4.0));
+        assert_eq!(submatch.get("end").unwrap().as_f64(), Some(7.0));
+    }
+
+    #[test]
+    fn test_json_inverted() {
+        // Inverted, the selected lines have no submatches, but the context lines do.
+        let arguments = Arguments {
+            patterns: vec!["b".to_string()],
+            json: true,
+            invert: true,
+            before_context: 1,
+            ..Arguments::default()
+        };
+        let records = search_json(arguments, "abc\nxyz\n");
+        assert_eq!(types(&records), vec!["begin", "context", "match", "end"]);
+
+        let submatches = |index: usize| {
+            let data = records[index].get("data").unwrap();
+            data.get("submatches").unwrap().as_array().unwrap().len()
+        };
+        assert_eq!(submatches(1), 1);
+        assert_eq!(submatches(2), 0);
+    }
+
+    #[test]
+    fn test_json_without_matches() {
+        let arguments = Arguments {
+            patterns: vec!["absent".to_string()],
+            json: true,
+            ..Arguments::default()
+        };
+        let records = search_json(arguments, "one\ntwo\n");
+        assert_eq!(types(&records), vec!["begin", "end"]);
+    }
+
+    fn search_text(arguments: Arguments, content: &str) -> String {
+        let searcher = Searcher {
+            arguments: &arguments,
+            matcher: build_matcher(&arguments).unwrap(),
+            ranking: fuzzy_matchers(&arguments),
+            colors: None,
+            replacement: arguments.replace.as_deref().map(Replacement::new),
+            show_names: false,
+        };
+        let mut output = Vec::new();
+        searcher
+            .search_reader(content.as_bytes(), "notes.txt", &mut output)
+            .unwrap_or_else(|_| panic!("search failed"));
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_replace_output() {
+        let arguments = Arguments {
+            patterns: vec!["cat".to_string(), "dog".to_string()],
+            replace: Some("pet".to_string()),
+            after_context: 1,
+            ..Arguments::default()
+        };
+        let output = search_text(
+            arguments,
+            "a cat and a dog\nno cat here? cat!\nfish\nbird\n",
+        );
+        // Context lines are printed as they are.
+        assert_eq!(output, "a pet and a pet\nno pet here? pet!\nfish\n");
+    }
+
+    #[test]
+    fn test_replace_output_with_groups() {
+        let arguments = Arguments {
+            patterns: vec!["(\\w+)@(\\w+)\\.com".to_string()],
+            regex: true,
+            replace: Some("$2 at ${1}".to_string()),
+            ..Arguments::default()
+        };
+        let output = search_text(arguments, "mail ann@example.com or bob@test.com\nnothing\n");
+        assert_eq!(output, "mail example at ann or test at bob\n");
+
+        // With -w, only whole words are replaced.
+        let arguments = Arguments {
+            patterns: vec!["c(a)t".to_string()],
+            regex: true,
+            word: true,
+            case_insensitive: true,
+            replace: Some("[$1]".to_string()),
+            ..Arguments::default()
+        };
+        let output = search_text(arguments, "Cat concatenate CAT\n");
+        assert_eq!(output, "[a] concatenate [A]\n");
+    }
+
+    #[test]
+    fn test_summary_modes() {
+        let content = "a cat\nno pets\ncat and dog\nthird cat\n";
+        let output = |arguments: Arguments| {
+            search_text(
+                Arguments {
+                    patterns: vec!["cat".to_string()],
+                    ..arguments
+                },
+                content,
+            )
+        };
+
+        let count = |max_count| Arguments {
+            count: true,
+            max_count,
+            ..Arguments::default()
+        };
+        assert_eq!(output(count(None)), "3\n");
+        assert_eq!(output(count(Some(2))), "2\n");
+
+        let names = |files_with_matches, files_without_match| Arguments {
+            files_with_matches,
+            files_without_match,
+            ..Arguments::default()
+        };
+        assert_eq!(output(names(true, false)), "notes.txt\n");
+        assert_eq!(output(names(false, true)), "");
+        assert_eq!(
+            output(Arguments {
+                max_count: Some(0),
+                ..names(false, true)
+            }),
+            "notes.txt\n"
+        );
+
+        // The context after the last allowed match is printed, later matches are not.
+        let output = output(Arguments {
+            max_count: Some(1),
+            after_context: 1,
+            line_number: true,
+            ..Arguments::default()
+        });
+        assert_eq!(output, "1:a cat\n2-no pets\n");
+        assert_eq!(
+            search_text(
+                Arguments {
+                    patterns: vec!["cat".to_string()],
+                    quiet: true,
+                    ..Arguments::default()
+                },
+                content
+            ),
+            ""
+        );
+    }
+
+    #[test]
+    fn test_quiet_stops_at_the_first_match() {
+        let dir = temp_dir("quiet");
+        fs::write(dir.join("a.txt"), "match\n").unwrap();
+        fs::write(dir.join("b.txt"), "match\n").unwrap();
+        let path = |name: &str| dir.join(name).display().to_string();
+
+        let arguments = |paths: Vec<String>| Arguments {
+            patterns: vec!["match".to_string()],
+            quiet: true,
+            threads: 1,
+            paths,
+            ..Arguments::default()
+        };
+        // The missing file is an error, but a selected line still decides the exit code.
+        let summary = run(arguments(vec![
+            path("missing.txt"),
+            path("a.txt"),
+            path("b.txt"),
+        ]))
+        .unwrap();
+        assert_eq!(summary.errors, 1);
+        assert_eq!(summary.stats.searches, 1);
+        assert_eq!(summary.exit_code(), 0);
+
+        let summary = run(arguments(vec![path("missing.txt")])).unwrap();
+        assert_eq!(summary.exit_code(), 3);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_max_count_in_place() {
+        let dir = temp_dir("in-place-max");
+        let path = dir.join("a.txt");
+        fs::write(&path, "x\nx\nx\n").unwrap();
+
+        let summary = run(Arguments {
+            patterns: vec!["x".to_string()],
+            replace: Some("y".to_string()),
+            in_place: true,
+            max_count: Some(2),
+            paths: vec![path.display().to_string()],
+            ..Arguments::default()
+        })
+        .unwrap();
+        assert_eq!(summary.stats.matches, 2);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "y\ny\nx\n");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("grep-lib-{name}-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /*
+        Compares the throughput of the sequential search with the worker pool, on the same files and searcher as run().
+        It takes a few seconds, so it only runs on request: cargo test --release -- --ignored --nocapture
+    */
+    #[test]
+    #[ignore]
+    fn benchmark_parallel_against_sequential() {
+        let dir = temp_dir("benchmark");
+        let mut bytes = 0;
+        for file in 0..64 {
+            let content: String = (0..5_000)
+                .map(|line| format!("file {file} line {line}: some text with an id_{line}x\n"))
+                .collect();
+            bytes += content.len();
+            fs::write(dir.join(format!("{file}.txt")), content).unwrap();
+        }
+
+        let arguments = Arguments {
+            patterns: vec!["id_\\d*7\\dx".to_string()],
+            regex: true,
+            paths: vec![dir.display().to_string()],
+            ..Arguments::default()
+        };
+        let searcher = Searcher {
+            arguments: &arguments,
+            matcher: build_matcher(&arguments).unwrap(),
+            ranking: Vec::new(),
+            colors: None,
+            replacement: None,
+            show_names: true,
+        };
+
+        let mut measurements = Vec::new();
+        for threads in [1, default_threads().max(2)] {
+            let mut summary = Summary::default();
+            let inputs = collect_inputs(&arguments, &mut summary);
+            let mut output = io::sink();
+
+            let start = Instant::now();
+            assert!(search_inputs(&searcher, inputs, threads, &mut summary, &mut output).is_ok());
+            let elapsed = start.elapsed();
+
+            let throughput = bytes as f64 / elapsed.as_secs_f64() / 1024.0 / 1024.0;
+            let matches = summary.stats.matched_lines;
+            println!("{threads} thread(s): {elapsed:?}, {throughput:.1} MiB/s, {matches} matches");
+            measurements.push(matches);
+        }
+
+        assert_eq!(measurements[0], measurements[1]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_replace_in_place() {
+        let dir = temp_dir("in-place");
+        fs::write(dir.join("a.txt"), "version = 1.2\r\nother = 1.2.3\n").unwrap();
+        fs::write(dir.join("b.txt"), "nothing to see\n").unwrap();
+        fs::write(dir.join("c.txt"), b"version = 7.0\n\xff\xfe\nversion = 8.1").unwrap();
+
+        let arguments = Arguments {
+            patterns: vec!["^version = (\\d+)\\.(\\d+)$".to_string()],
+            regex: true,
+            replace: Some("version = $1.${2}0".to_string()),
+            in_place: true,
+            backup_suffix: Some(".orig".to_string()),
+            paths: vec![dir.display().to_string()],
+            ..Arguments::default()
+        };
+        let summary = run(arguments).unwrap();
+        assert!(summary.matched);
+        assert_eq!(summary.errors, 0);
+        assert_eq!(summary.stats.matches, 3);
+
+        // Line endings, unmatched lines and invalid UTF-8 are kept as they are.
+        assert_eq!(
+            fs::read_to_string(dir.join("a.txt")).unwrap(),
+            "version = 1.20\r\nother = 1.2.3\n"
+        );
+        assert_eq!(
+            fs::read(dir.join("c.txt")).unwrap(),
+            b"version = 7.00\n\xff\xfe\nversion = 8.10"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("a.txt.orig")).unwrap(),
+            "version = 1.2\r\nother = 1.2.3\n"
+        );
+        // Files without matches are not touched and get no backup.
+        assert_eq!(
+            fs::read_to_string(dir.join("b.txt")).unwrap(),
+            "nothing to see\n"
+        );
+        assert!(!dir.join("b.txt.orig").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_replace_in_place_without_matches() {
+        let dir = temp_dir("in-place-none");
+        let path = dir.join("a.txt");
+        fs::write(&path, "abc\n").unwrap();
+
+        let arguments = Arguments {
+            patterns: vec!["xyz".to_string()],
+            replace: Some("!".to_string()),
+            in_place: true,
+            paths: vec![path.display().to_string()],
+            ..Arguments::default()
+        };
+        let summary = run(arguments).unwrap();
+        assert_eq!(summary.exit_code(), 1);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_search_compressed_files() {
+        let fixtures = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        let arguments = |decompress| Arguments {
+            patterns: vec!["status=ERROR".to_string()],
+            count: true,
+            decompress,
+            paths: vec![fixtures.join("log.txt.gz").display().to_string()],
+            ..Arguments::default()
+        };
+
+        // Every 997th line of the 6000 lines has an error.
+        let summary = run(arguments(true)).unwrap();
+        assert_eq!(summary.stats.matched_lines, 7);
+        let summary = run(arguments(false)).unwrap();
+        assert_eq!(summary.exit_code(), 1);
+
+        // Zstandard is recognized, but not supported.
+        let dir = temp_dir("zstd");
+        let path = dir.join("log.zst");
+        fs::write(&path, [0x28, 0xb5, 0x2f, 0xfd, 0x00, 0x00]).unwrap();
+        let summary = run(Arguments {
+            paths: vec![path.display().to_string()],
+            ..arguments(true)
+        })
+        .unwrap();
+        assert_eq!(summary.errors, 1);
+        assert_eq!(summary.exit_code(), 5);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_errors() {
+        let dir = temp_dir("errors");
+        let arguments = |paths: Vec<String>| Arguments {
+            patterns: vec!["x".to_string()],
+            threads: 1,
+            paths,
+            ..Arguments::default()
+        };
+
+        let missing = dir.join("missing.txt").display().to_string();
+        let summary = run(arguments(vec![missing.clone()])).unwrap();
+        assert_eq!(summary.exit_code(), 3);
+
+        let error = run(Arguments {
+            regex: true,
+            patterns: vec!["(x".to_string()],
+            ..arguments(vec![missing.clone()])
+        })
+        .unwrap_err();
+        assert!(matches!(error, GrepError::InvalidPattern(_)));
+        assert_eq!(error.exit_code(), 6);
+
+        let error = run(Arguments {
+            in_place: true,
+            ..arguments(vec![missing])
+        })
+        .unwrap_err();
+        assert!(matches!(error, GrepError::Arguments(_)));
+        assert_eq!(error.exit_code(), 2);
+
+        let summary = run(Arguments {
+            replace: Some("y".to_string()),
+            in_place: true,
+            ..arguments(vec!["-".to_string()])
+        })
+        .unwrap();
+        assert_eq!(summary.exit_code(), 4);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_read_file() {
+        let dir = temp_dir("read-file");
+        fs::write(dir.join("text.txt"), "Grüße\n").unwrap();
+        fs::write(dir.join("binary.bin"), b"ok\n\xff\xfe").unwrap();
+
+        assert_eq!(read_file(&dir.join("text.txt")).unwrap(), "Grüße\n");
+        let error = read_file(&dir.join("binary.bin")).unwrap_err();
+        assert!(matches!(error, GrepError::InvalidUtf8 { .. }));
+        assert_eq!(error.exit_code(), 7);
+        assert!(matches!(
+            read_file(&dir.join("absent.txt")),
+            Err(GrepError::NotFound(_))
+        ));
+        assert!(matches!(read_file(&dir), Err(GrepError::NotAFile(_))));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
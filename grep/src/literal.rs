@@ -0,0 +1,408 @@
+/*
+    Finds the lines containing a literal pattern by scanning the whole buffer instead of going line by line.
+
+    Going line by line means looking for the end of every line and then searching each line on its own,
+    which sets up a new search for every line, even though most lines contain nothing of interest.
+    Here the pattern is searched in the whole buffer first, and the line boundaries are only looked up around a hit.
+    Lines without a hit are skipped over without ever looking for their ends.
+
+    Bytes are found with memchr, which compares eight bytes at once by packing them into a u64
+    ("SWAR", SIMD within a register). Longer patterns are found by looking for their rarest byte with memchr
+    (e.g. the "Q" in "Quota") and comparing the whole pattern only where that byte occurs.
+    If even the rarest byte is a common one (e.g. in "the end"), memchr would stop all the time,
+    so Boyer-Moore-Horspool is used instead: the last byte of the window decides how far the pattern can be moved,
+    so most bytes of the buffer are never looked at.
+
+    The command line reads its input line by line (see search.rs), so there Finder only speeds up the search
+    within each line: Substring uses it for patterns that match case.
+    The buffer has to be in memory completely. Mapping large files with mmap would save reading them,
+    but std has no portable way to do that, and a mapped file that is truncated while we read it crashes the process.
+*/
+
+use std::ops::Range;
+
+// Every byte set to 0x01, and every byte set to 0x80.
+const LOW_BITS: u64 = u64::from_ne_bytes([0x01; 8]);
+const HIGH_BITS: u64 = u64::from_ne_bytes([0x80; 8]);
+
+// Whether any of the eight bytes in the word is zero. Subtracting 1 from a zero byte is the only way to set its high bit
+// where it was not set before (as long as no borrow comes in from a lower byte, which also needs a zero byte).
+fn has_zero_byte(word: u64) -> bool {
+    word.wrapping_sub(LOW_BITS) & !word & HIGH_BITS != 0
+}
+
+// The position of the first occurrence of the byte.
+pub fn memchr(needle: u8, haystack: &[u8]) -> Option<usize> {
+    let repeated = LOW_BITS * u64::from(needle);
+    let mut offset = 0;
+
+    // A word containing the needle has a zero byte after the XOR. Only that word is searched byte by byte.
+    for chunk in haystack.chunks_exact(8) {
+        let word = u64::from_ne_bytes(chunk.try_into().unwrap());
+        if has_zero_byte(word ^ repeated) {
+            break;
+        }
+        offset += 8;
+    }
+
+    haystack[offset..]
+        .iter()
+        .position(|&byte| byte == needle)
+        .map(|position| offset + position)
+}
+
+// The position of the last occurrence of the byte.
+pub fn memrchr(needle: u8, haystack: &[u8]) -> Option<usize> {
+    let repeated = LOW_BITS * u64::from(needle);
+    let mut end = haystack.len();
+
+    for chunk in haystack.rchunks_exact(8) {
+        let word = u64::from_ne_bytes(chunk.try_into().unwrap());
+        if has_zero_byte(word ^ repeated) {
+            break;
+        }
+        end -= 8;
+    }
+
+    haystack[..end].iter().rposition(|&byte| byte == needle)
+}
+
+/*
+    A guess how often the byte appears in text, from 0 (rare) to 255 (everywhere). Only the order matters.
+    Spaces, vowels and the most frequent consonants of English come first, then the other lowercase letters
+    and digits, then punctuation. Uppercase letters, control characters and the bytes of non-ASCII characters are rare.
+*/
+fn frequency(byte: u8) -> u8 {
+    match byte {
+        b' ' => 255,
+        b'e' | b't' | b'a' | b'o' | b'i' | b'n' | b's' | b'r' | b'h' | b'l' => 240,
+        // Logs and code are full of numbers.
+        b'a'..=b'z' | b'0'..=b'9' => 200,
+        b'.' | b',' | b':' | b'/' | b'-' | b'_' | b'=' | b'"' | b'\'' | b'(' | b')' => 180,
+        // Lead bytes of accented latin letters, and the continuation bytes of all multi-byte characters.
+        0xc3 | 0x80..=0xbf => 120,
+        b'A'..=b'Z' => 100,
+        _ => 60,
+    }
+}
+
+// Bytes at least this frequent stop memchr too often, so Horspool is used for needles made only of them.
+const FREQUENT: u8 = 200;
+
+#[derive(Debug, Clone)]
+pub struct Finder {
+    needle: Vec<u8>,
+    // The position of the rarest byte in the needle.
+    rare: usize,
+    // How far the window can move when its last byte is the index. Bytes not in the needle skip the whole needle.
+    shift: [usize; 256],
+}
+
+impl Finder {
+    pub fn new(needle: &str) -> Finder {
+        let needle = needle.as_bytes().to_vec();
+        let mut shift = [needle.len(); 256];
+        // The last byte is left out, as it would not move the window at all.
+        for (index, &byte) in needle
+            .iter()
+            .enumerate()
+            .take(needle.len().saturating_sub(1))
+        {
+            shift[usize::from(byte)] = needle.len() - 1 - index;
+        }
+
+        let rare = (0..needle.len())
+            .min_by_key(|&index| frequency(needle[index]))
+            .unwrap_or(0);
+
+        Finder {
+            needle,
+            rare,
+            shift,
+        }
+    }
+
+    // The position of the first occurrence of the needle. An empty needle is found right at the start.
+    pub fn find(&self, haystack: &[u8]) -> Option<usize> {
+        let needle = &self.needle;
+        let last = match needle.len() {
+            0 => return Some(0),
+            1 => return memchr(needle[0], haystack),
+            length => length - 1,
+        };
+        if frequency(needle[self.rare]) < FREQUENT {
+            return self.find_rare(haystack);
+        }
+
+        let mut position = 0;
+        while position + needle.len() <= haystack.len() {
+            let byte = haystack[position + last];
+            if byte == needle[last] && haystack[position..position + last] == needle[..last] {
+                return Some(position);
+            }
+            position += self.shift[usize::from(byte)];
+        }
+
+        None
+    }
+
+    // Only where the rarest byte occurs, the needle can start.
+    fn find_rare(&self, haystack: &[u8]) -> Option<usize> {
+        let needle = &self.needle;
+        let mut position = 0;
+
+        while position + needle.len() <= haystack.len() {
+            let found = memchr(needle[self.rare], &haystack[position + self.rare..])?;
+            let start = position + found;
+            if start + needle.len() > haystack.len() {
+                return None;
+            }
+            if haystack[start..start + needle.len()] == needle[..] {
+                return Some(start);
+            }
+            position = start + 1;
+        }
+
+        None
+    }
+
+    // The ranges of all non-overlapping occurrences, from left to right. The empty needle has nothing to report.
+    pub fn find_all(&self, haystack: &[u8]) -> Vec<Range<usize>> {
+        let mut ranges = Vec::new();
+        if self.needle.is_empty() {
+            return ranges;
+        }
+
+        let mut position = 0;
+        while let Some(found) = self.find(&haystack[position..]) {
+            let start = position + found;
+            position = start + self.needle.len();
+            ranges.push(start..position);
+        }
+        ranges
+    }
+
+    // The lines containing the needle, the same as content.lines().filter(|line| line.contains(needle)).
+    pub fn lines<'c>(&self, content: &'c str) -> Lines<'c, '_> {
+        Lines {
+            content,
+            finder: self,
+            position: 0,
+        }
+    }
+}
+
+pub struct Lines<'c, 'f> {
+    content: &'c str,
+    finder: &'f Finder,
+    // Where the search for the next hit starts.
+    position: usize,
+}
+
+impl<'c> Iterator for Lines<'c, '_> {
+    type Item = &'c str;
+
+    fn next(&mut self) -> Option<&'c str> {
+        let bytes = self.content.as_bytes();
+
+        // Every line contains the empty needle, so there is nothing to skip.
+        if self.finder.needle.is_empty() {
+            let line = self.content[self.position..].lines().next()?;
+            let end = self.position + line.len();
+            self.position = next_line(bytes, end);
+            return Some(line);
+        }
+
+        // No line contains a line break.
+        if self.finder.needle.contains(&b'\n') {
+            return None;
+        }
+
+        while self.position < bytes.len() {
+            let hit = self.position + self.finder.find(&bytes[self.position..])?;
+            let start = memrchr(b'\n', &bytes[..hit]).map_or(0, |newline| newline + 1);
+            let end = memchr(b'\n', &bytes[hit..]).map_or(bytes.len(), |newline| hit + newline);
+            // Like str::lines, "\r\n" ends a line, but a "\r" at the very end belongs to the line.
+            let end = match end < bytes.len() && end > start && bytes[end - 1] == b'\r' {
+                true => end - 1,
+                false => end,
+            };
+
+            // The hit may reach into the line ending or the next line, then the line itself does not contain the needle.
+            if hit + self.finder.needle.len() <= end {
+                self.position = next_line(bytes, end);
+                return Some(&self.content[start..end]);
+            }
+            self.position = hit + 1;
+        }
+
+        None
+    }
+}
+
+// Where the line after the one ending at the given position starts, past its "\n" or "\r\n".
+fn next_line(bytes: &[u8], end: usize) -> usize {
+    match memchr(b'\n', &bytes[end..]) {
+        Some(newline) => end + newline + 1,
+        None => bytes.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{memchr, memrchr, Finder};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_memchr() {
+        // Every position in and around the words, so the chunked and the byte by byte parts are both covered.
+        for length in 0..40 {
+            for position in 0..length {
+                let mut haystack = vec![b'a'; length];
+                haystack[position] = b'x';
+                assert_eq!(memchr(b'x', &haystack), Some(position));
+                assert_eq!(memrchr(b'x', &haystack), Some(position));
+            }
+            assert_eq!(memchr(b'x', &vec![b'a'; length]), None);
+            assert_eq!(memrchr(b'x', &vec![b'a'; length]), None);
+        }
+
+        let haystack = b"x.......x.......x";
+        assert_eq!(memchr(b'x', haystack), Some(0));
+        assert_eq!(memrchr(b'x', haystack), Some(16));
+        // Bytes with the high bit set must not be mistaken for a match.
+        assert_eq!(memchr(0x00, &[0x80, 0xff, 0x01, 0x7f, 0x00]), Some(4));
+        assert_eq!(memchr(0xff, &[0x00; 9]), None);
+    }
+
+    #[test]
+    fn test_finder() {
+        let haystack = "the quick brown fox jumps over the lazy dog, the end";
+        for needle in [
+            "the",
+            "dog",
+            "d",
+            "end",
+            "fox jumps",
+            "xyz",
+            "the lazy dog, the end!",
+            "e",
+        ] {
+            assert_eq!(
+                Finder::new(needle).find(haystack.as_bytes()),
+                haystack.find(needle),
+                "{needle}"
+            );
+        }
+        assert_eq!(Finder::new("").find(b"abc"), Some(0));
+        assert_eq!(Finder::new("aab").find(b"aaaab"), Some(2));
+        // Both with a rare byte in the needle and without one.
+        assert_eq!(Finder::new("Xab").find(b"aXaXabXab"), Some(3));
+        assert_eq!(Finder::new("abX").find(b"abXX"), Some(0));
+        assert_eq!(Finder::new("aXb").find(b"aXa"), None);
+        assert_eq!(Finder::new("abc").find(b""), None);
+    }
+
+    fn naive<'a>(content: &'a str, needle: &str) -> Vec<&'a str> {
+        content
+            .lines()
+            .filter(|line| line.contains(needle))
+            .collect()
+    }
+
+    #[test]
+    fn test_find_all() {
+        let haystack = "aaaa the end, then theend";
+        for needle in ["the", "aa", "end", "x", "the end, then theend"] {
+            let expected: Vec<_> = haystack
+                .match_indices(needle)
+                .map(|(start, _)| start..start + needle.len())
+                .collect();
+            assert_eq!(Finder::new(needle).find_all(haystack.as_bytes()), expected);
+        }
+        assert!(Finder::new("").find_all(b"abc").is_empty());
+    }
+
+    #[test]
+    fn test_lines_agree_with_str_lines() {
+        let contents = [
+            "alpha\nbeta\ngamma\n",
+            "alpha\r\nbeta\r\ngamma",
+            "a\n\n\nb\n",
+            "\n",
+            "",
+            "trailing carriage return\r",
+            "a\rb\nab\r\n",
+            "Grüße aus Köln\nGrüße\nkein Gruß\n",
+            "x x x\nx\nyyy\nx",
+        ];
+        let needles = [
+            "a", "ab", "b\n", "\r", "a\r", "Grüße", "ß", "x", "x x", "", "\n", "gamma",
+        ];
+
+        for content in contents {
+            for needle in needles {
+                let finder = Finder::new(needle);
+                assert_eq!(
+                    finder.lines(content).collect::<Vec<&str>>(),
+                    naive(content, needle),
+                    "{needle:?} in {content:?}"
+                );
+            }
+        }
+    }
+
+    /*
+        Compares the buffer scan with going line by line, like criterion would:
+        after a warm-up, every variant is timed several times and the median is reported.
+        It takes a few seconds, so it only runs on request: cargo test --release -- --ignored --nocapture
+    */
+    #[test]
+    #[ignore]
+    fn benchmark_buffer_scan_against_lines() {
+        const SAMPLES: usize = 11;
+        let content: String = (0..500_000)
+            .map(|line| {
+                let level = if line % 1_000 == 0 { "ERROR" } else { "INFO" };
+                format!(
+                    "2024-05-01 12:{:02}:{:02} {level} request {line} took {}ms\n",
+                    line % 60,
+                    line % 59,
+                    line % 97
+                )
+            })
+            .collect();
+        let megabytes = content.len() as f64 / 1024.0 / 1024.0;
+
+        let median = |search: &dyn Fn() -> usize| {
+            search();
+            let mut times: Vec<Duration> = (0..SAMPLES)
+                .map(|_| {
+                    let start = Instant::now();
+                    std::hint::black_box(search());
+                    start.elapsed()
+                })
+                .collect();
+            times.sort();
+            times[SAMPLES / 2]
+        };
+
+        for needle in ["ERROR", "took 96ms", "request 49999", "2024"] {
+            let finder = Finder::new(needle);
+            let by_lines = median(&|| naive(&content, needle).len());
+            let by_buffer = median(&|| finder.lines(&content).count());
+            assert_eq!(
+                naive(&content, needle),
+                finder.lines(&content).collect::<Vec<&str>>()
+            );
+
+            let throughput = |time: Duration| megabytes / time.as_secs_f64();
+            println!(
+                "{needle:>14}: lines {by_lines:>10.2?} ({:>6.0} MiB/s), buffer {by_buffer:>10.2?} ({:>6.0} MiB/s), {:.1}x",
+                throughput(by_lines),
+                throughput(by_buffer),
+                by_lines.as_secs_f64() / by_buffer.as_secs_f64()
+            );
+        }
+    }
+}
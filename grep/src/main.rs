@@ -1,19 +1,28 @@
-use grep::{parse_arguments, run};
+use grep::{parse_arguments, run, GrepError, ParseError, USAGE};
 use std::{
     env::{self},
     process::exit,
 };
 
 fn main() {
-    let arguments = env::args().collect::<Vec<String>>();
+    // The first argument is the path of the program itself.
+    let arguments = env::args().skip(1).collect::<Vec<String>>();
 
     let arguments = parse_arguments(arguments).unwrap_or_else(|error| {
-        eprintln!("Error while parsing arguments: {error}");
-        exit(1);
+        if error == ParseError::HelpRequested {
+            println!("{USAGE}");
+            exit(0);
+        }
+
+        eprintln!("Error while parsing arguments: {error}\n\n{USAGE}");
+        exit(GrepError::from(error).exit_code());
     });
 
-    run(arguments).unwrap_or_else(|error| {
+    // Every kind of error has its own exit code, see GrepError.
+    let summary = run(arguments).unwrap_or_else(|error| {
         eprintln!("Error while running: {error}");
-        exit(1);
+        exit(error.exit_code());
     });
+
+    exit(summary.exit_code());
 }
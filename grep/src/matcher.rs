@@ -0,0 +1,453 @@
+/*
+    Decides which lines are selected and where the matches in them are.
+
+    Matcher is a trait, so the different kinds of matching can be combined like building blocks:
+    Substring finds a literal pattern (optionally ignoring case), Regex and FuzzyMatcher match their own way,
+    and Word, WholeLine and Inverted wrap any other matcher to narrow it down or turn it around.
+    AnyOf selects a line if any of several matchers does, which is how multiple patterns are searched.
+
+    For example, "whole words of either pattern, ignoring case" is
+        AnyOf::new(vec![Word::new(Substring::ignore_case("cat")), Word::new(Substring::ignore_case("dog"))])
+
+    Every matcher has to be Sync to be shared by the worker threads that search several files at the same time.
+*/
+
+use crate::{
+    casefold::CaseFolder, find_regex, fuzzy::FuzzyMatcher, literal::Finder, regex::Regex,
+    replace::Groups,
+};
+use std::ops::Range;
+
+pub trait Matcher {
+    // Whether the line is selected.
+    fn is_match(&self, line: &str) -> bool {
+        !self.find_matches(line).is_empty()
+    }
+
+    // The byte ranges of the matches in the line, sorted and without overlaps. Empty matches are left out.
+    fn find_matches(&self, line: &str) -> Vec<Range<usize>>;
+
+    // The matches with the ranges of their capture groups, for replacements. Only regexes have groups besides group 0.
+    fn find_groups(&self, line: &str) -> Vec<Groups> {
+        self.find_matches(line)
+            .into_iter()
+            .map(|range| vec![Some(range)])
+            .collect()
+    }
+}
+
+// Boxed matchers are matchers too, so matchers can be chosen at runtime and still be combined.
+impl<M: Matcher + ?Sized> Matcher for Box<M> {
+    fn is_match(&self, line: &str) -> bool {
+        (**self).is_match(line)
+    }
+
+    fn find_matches(&self, line: &str) -> Vec<Range<usize>> {
+        (**self).find_matches(line)
+    }
+
+    fn find_groups(&self, line: &str) -> Vec<Groups> {
+        (**self).find_groups(line)
+    }
+}
+
+/*
+    Matches a literal pattern anywhere in the line.
+    An empty pattern selects every line, like str::contains does, but there is no match in it to highlight.
+*/
+#[derive(Debug, Clone)]
+pub struct Substring {
+    pattern: String,
+    // Both are prepared once up front instead of once per line.
+    search: LiteralSearch,
+}
+
+#[derive(Debug, Clone)]
+enum LiteralSearch {
+    Exact(Box<Finder>), // Boxed, its shift table takes 2 KiB
+    IgnoreCase(CaseFolder),
+}
+
+impl Substring {
+    pub fn new(pattern: &str) -> Substring {
+        Substring {
+            pattern: pattern.to_string(),
+            search: LiteralSearch::Exact(Box::new(Finder::new(pattern))),
+        }
+    }
+
+    pub fn ignore_case(pattern: &str) -> Substring {
+        Substring {
+            pattern: pattern.to_string(),
+            search: LiteralSearch::IgnoreCase(CaseFolder::new(pattern)),
+        }
+    }
+}
+
+impl Matcher for Substring {
+    fn is_match(&self, line: &str) -> bool {
+        match &self.search {
+            _ if self.pattern.is_empty() => true,
+            LiteralSearch::Exact(finder) => finder.find(line.as_bytes()).is_some(),
+            LiteralSearch::IgnoreCase(folder) => folder.is_match(line),
+        }
+    }
+
+    fn find_matches(&self, line: &str) -> Vec<Range<usize>> {
+        match &self.search {
+            LiteralSearch::Exact(finder) => finder.find_all(line.as_bytes()),
+            LiteralSearch::IgnoreCase(folder) => folder.find_all(line),
+        }
+    }
+}
+
+impl Matcher for Regex {
+    fn is_match(&self, line: &str) -> bool {
+        Regex::is_match(self, line)
+    }
+
+    fn find_matches(&self, line: &str) -> Vec<Range<usize>> {
+        find_regex(line, self)
+    }
+
+    fn find_groups(&self, line: &str) -> Vec<Groups> {
+        let mut matches = Vec::new();
+        let mut position = 0;
+
+        while let Some(captures) = self.captures_at(line, position) {
+            let whole = match captures.get(0) {
+                Some(whole) => whole.range(),
+                None => break,
+            };
+            // Like in find_regex, empty matches are skipped.
+            position = match whole.is_empty() {
+                true => match line[whole.end..].chars().next() {
+                    Some(c) => whole.end + c.len_utf8(),
+                    None => break,
+                },
+                false => whole.end,
+            };
+            if !whole.is_empty() {
+                matches.push(
+                    (0..captures.len())
+                        .map(|group| captures.get(group).map(|found| found.range()))
+                        .collect(),
+                );
+            }
+        }
+
+        matches
+    }
+}
+
+// The best match of the line, if it is close enough to the pattern.
+impl Matcher for FuzzyMatcher {
+    fn is_match(&self, line: &str) -> bool {
+        FuzzyMatcher::is_match(self, line)
+    }
+
+    fn find_matches(&self, line: &str) -> Vec<Range<usize>> {
+        self.find(line)
+            .map(|(_, range)| range)
+            .filter(|range| !range.is_empty())
+            .into_iter()
+            .collect()
+    }
+}
+
+// Only keeps the matches that are whole words: neither preceded nor followed by a word character.
+#[derive(Debug, Clone)]
+pub struct Word<M> {
+    inner: M,
+}
+
+impl<M: Matcher> Word<M> {
+    pub fn new(inner: M) -> Word<M> {
+        Word { inner }
+    }
+}
+
+impl<M: Matcher> Matcher for Word<M> {
+    fn find_matches(&self, line: &str) -> Vec<Range<usize>> {
+        self.inner
+            .find_matches(line)
+            .into_iter()
+            .filter(|range| is_whole_word(line, range))
+            .collect()
+    }
+
+    fn find_groups(&self, line: &str) -> Vec<Groups> {
+        self.inner
+            .find_groups(line)
+            .into_iter()
+            .filter(|groups| {
+                groups[0]
+                    .as_ref()
+                    .is_some_and(|range| is_whole_word(line, range))
+            })
+            .collect()
+    }
+}
+
+fn is_whole_word(line: &str, range: &Range<usize>) -> bool {
+    let is_word_char = |c: char| c.is_alphanumeric() || c == '_';
+    let before = line[..range.start]
+        .chars()
+        .next_back()
+        .is_some_and(is_word_char);
+    let after = line[range.end..].chars().next().is_some_and(is_word_char);
+    !before && !after
+}
+
+/*
+    Only keeps a match that spans the whole line.
+    An empty line is selected if the inner matcher selects it, as only an empty pattern can.
+*/
+#[derive(Debug, Clone)]
+pub struct WholeLine<M> {
+    inner: M,
+}
+
+impl<M: Matcher> WholeLine<M> {
+    pub fn new(inner: M) -> WholeLine<M> {
+        WholeLine { inner }
+    }
+}
+
+impl<M: Matcher> Matcher for WholeLine<M> {
+    fn is_match(&self, line: &str) -> bool {
+        match line.is_empty() {
+            true => self.inner.is_match(line),
+            false => !self.find_matches(line).is_empty(),
+        }
+    }
+
+    fn find_matches(&self, line: &str) -> Vec<Range<usize>> {
+        self.inner
+            .find_matches(line)
+            .into_iter()
+            .filter(|range| *range == (0..line.len()))
+            .collect()
+    }
+
+    fn find_groups(&self, line: &str) -> Vec<Groups> {
+        self.inner
+            .find_groups(line)
+            .into_iter()
+            .filter(|groups| groups[0] == Some(0..line.len()))
+            .collect()
+    }
+}
+
+/*
+    Selects the lines the inner matcher does not select.
+    The matches are still those of the inner matcher, so they can be highlighted in the lines that are not selected.
+*/
+#[derive(Debug, Clone)]
+pub struct Inverted<M> {
+    inner: M,
+}
+
+impl<M: Matcher> Inverted<M> {
+    pub fn new(inner: M) -> Inverted<M> {
+        Inverted { inner }
+    }
+}
+
+impl<M: Matcher> Matcher for Inverted<M> {
+    fn is_match(&self, line: &str) -> bool {
+        !self.inner.is_match(line)
+    }
+
+    fn find_matches(&self, line: &str) -> Vec<Range<usize>> {
+        self.inner.find_matches(line)
+    }
+
+    fn find_groups(&self, line: &str) -> Vec<Groups> {
+        self.inner.find_groups(line)
+    }
+}
+
+// Selects a line if any of the matchers does. Without any matcher, nothing is selected.
+#[derive(Debug, Clone)]
+pub struct AnyOf<M> {
+    matchers: Vec<M>,
+}
+
+impl<M: Matcher> AnyOf<M> {
+    pub fn new(matchers: Vec<M>) -> AnyOf<M> {
+        AnyOf { matchers }
+    }
+}
+
+impl<M: Matcher> Matcher for AnyOf<M> {
+    fn is_match(&self, line: &str) -> bool {
+        self.matchers.iter().any(|matcher| matcher.is_match(line))
+    }
+
+    // Matches of different matchers can overlap, so they are merged.
+    fn find_matches(&self, line: &str) -> Vec<Range<usize>> {
+        let mut ranges: Vec<Range<usize>> = self
+            .matchers
+            .iter()
+            .flat_map(|matcher| matcher.find_matches(line))
+            .collect();
+        ranges.sort_by_key(|range| range.start);
+
+        let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+        merged
+    }
+
+    // Merged matches have no groups of their own, only group 0.
+    fn find_groups(&self, line: &str) -> Vec<Groups> {
+        let groups: Vec<Groups> = self
+            .matchers
+            .iter()
+            .flat_map(|matcher| matcher.find_groups(line))
+            .collect();
+
+        self.find_matches(line)
+            .into_iter()
+            .map(|range| {
+                groups
+                    .iter()
+                    .find(|groups| groups[0].as_ref() == Some(&range))
+                    .cloned()
+                    .unwrap_or_else(|| vec![Some(range)])
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AnyOf, Inverted, Matcher, Substring, WholeLine, Word};
+    use crate::{fuzzy::FuzzyMatcher, regex::Regex};
+
+    #[test]
+    fn test_substring() {
+        let matcher = Substring::new("ab");
+        assert!(matcher.is_match("xaby"));
+        assert!(!matcher.is_match("AB"));
+        assert_eq!(matcher.find_matches("ab ab Ab"), vec![0..2, 3..5]);
+
+        let matcher = Substring::ignore_case("straße");
+        assert!(matcher.is_match("STRASSE"));
+        assert_eq!(matcher.find_matches("Die Straße"), vec![4..11]);
+
+        // An empty pattern selects everything, but has nothing to highlight.
+        assert!(Substring::new("").is_match("anything"));
+        assert!(Substring::ignore_case("")
+            .find_matches("anything")
+            .is_empty());
+    }
+
+    #[test]
+    fn test_word() {
+        let matcher = Word::new(Substring::new("cat"));
+        assert!(matcher.is_match("a cat."));
+        assert!(matcher.is_match("cat"));
+        assert!(!matcher.is_match("concatenate"));
+        assert!(!matcher.is_match("cat_food"));
+        assert_eq!(matcher.find_matches("concat cat"), vec![7..10]);
+
+        let matcher = Word::new(Substring::ignore_case("über"));
+        assert!(matcher.is_match("ÜBER alles"));
+        assert!(!matcher.is_match("überall"));
+    }
+
+    #[test]
+    fn test_whole_line() {
+        let matcher = WholeLine::new(Substring::ignore_case("hello world"));
+        assert!(matcher.is_match("Hello World"));
+        assert!(!matcher.is_match("Hello World!"));
+        assert_eq!(matcher.find_matches("HELLO WORLD"), vec![0..11]);
+
+        assert!(WholeLine::new(Substring::new("")).is_match(""));
+        assert!(!WholeLine::new(Substring::new("")).is_match("x"));
+    }
+
+    #[test]
+    fn test_inverted() {
+        let matcher = Inverted::new(Substring::new("error"));
+        assert!(matcher.is_match("all good"));
+        assert!(!matcher.is_match("an error"));
+        // The matches of the pattern are still reported, for highlighting.
+        assert_eq!(matcher.find_matches("an error"), vec![3..8]);
+
+        // Inverting twice selects the same lines as not inverting.
+        let twice = Inverted::new(Inverted::new(Substring::new("error")));
+        assert!(twice.is_match("an error"));
+    }
+
+    #[test]
+    fn test_any_of() {
+        let matcher = AnyOf::new(vec![Substring::new("abc"), Substring::new("cde")]);
+        assert!(matcher.is_match("xcde"));
+        assert!(!matcher.is_match("xyz"));
+        assert_eq!(matcher.find_matches("abcde abc"), vec![0..5, 6..9]);
+        assert!(!AnyOf::<Substring>::new(vec![]).is_match("anything"));
+    }
+
+    #[test]
+    fn test_combinations() {
+        // Each pattern has to be a whole word on its own, merged matches do not count.
+        let matcher = AnyOf::new(vec![
+            Word::new(Substring::ignore_case("con")),
+            Word::new(Substring::ignore_case("cat")),
+        ]);
+        assert!(!matcher.is_match("concat"));
+        assert!(matcher.is_match("CON or CAT"));
+
+        let matcher: Vec<Box<dyn Matcher>> = vec![
+            Box::new(WholeLine::new(Substring::new("exact"))),
+            Box::new(Word::new(Regex::new("\\d+").unwrap())),
+        ];
+        let matcher = Inverted::new(AnyOf::new(matcher));
+        assert!(!matcher.is_match("exact"));
+        assert!(!matcher.is_match("id 42"));
+        assert!(matcher.is_match("not exact"));
+        assert!(matcher.is_match("id42"));
+    }
+
+    #[test]
+    fn test_regex_groups() {
+        let regex = Regex::new("(\\w)(\\d)?").unwrap();
+        let groups = regex.find_groups("a1 b");
+        assert_eq!(
+            groups,
+            vec![
+                vec![Some(0..2), Some(0..1), Some(1..2)],
+                vec![Some(3..4), Some(3..4), None]
+            ]
+        );
+
+        // Only whole words keep their groups.
+        let matcher = Word::new(Regex::new("c(a)t").unwrap());
+        assert_eq!(
+            matcher.find_groups("cat concat"),
+            vec![vec![Some(0..3), Some(1..2)]]
+        );
+
+        // Empty matches are skipped, like in find_matches.
+        let regex = Regex::new("x*").unwrap();
+        assert_eq!(regex.find_groups("axxb"), vec![vec![Some(1..3)]]);
+    }
+
+    #[test]
+    fn test_fuzzy() {
+        let matcher = Word::new(FuzzyMatcher::new("timeout", 1, false));
+        assert!(matcher.is_match("request timout"));
+        assert!(!matcher.is_match("request timoutx"));
+        assert_eq!(
+            FuzzyMatcher::new("timeout", 1, false).find_matches("a timout"),
+            vec![2..8]
+        );
+    }
+}
@@ -0,0 +1,216 @@
+/*
+    Spreads work over a fixed number of worker threads, but hands the results back in the original order.
+
+    This is the worker pool from the Rust book: jobs go through a channel whose receiver is shared by all workers
+    behind a Mutex, so every job is taken by exactly one worker. The results travel back through a second channel.
+    Results that arrive too early are parked until all results before them were consumed,
+    which makes the output deterministic no matter which worker finishes first.
+
+    Jobs are not queued all at once. Only a window of a few jobs per thread is handed out,
+    and the next job is sent whenever a result was consumed. A slow input at the front therefore stalls the workers
+    instead of letting finished results pile up behind it, so memory stays bounded by the window, not the number of items.
+
+    The workers are scoped threads, so they can borrow from the caller and we don't need to wrap everything in an Arc.
+*/
+
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Mutex,
+    },
+    thread,
+};
+
+// How many jobs per thread can be in progress or waiting for their turn.
+const JOBS_PER_THREAD: usize = 2;
+
+// The number of threads to use when none were requested explicitly.
+pub fn default_threads() -> usize {
+    thread::available_parallelism()
+        .map(|threads| threads.get())
+        .unwrap_or(1)
+}
+
+/*
+    Runs work for every item on the given number of threads and passes the results to consume, in the order of the items.
+    If consume fails, the remaining items are not started and the error is returned once all workers stopped.
+*/
+pub fn map_ordered<T, R, E>(
+    items: Vec<T>,
+    threads: usize,
+    work: impl Fn(T) -> R + Sync,
+    mut consume: impl FnMut(R) -> Result<(), E>,
+) -> Result<(), E>
+where
+    T: Send,
+    R: Send,
+{
+    let threads = threads.clamp(1, items.len().max(1));
+    let cancelled = AtomicBool::new(false);
+
+    let (job_sender, job_receiver) = mpsc::channel();
+    let job_receiver = Mutex::new(job_receiver);
+    let mut jobs = items.into_iter().enumerate();
+    for job in jobs.by_ref().take(threads * JOBS_PER_THREAD) {
+        job_sender.send(job).unwrap();
+    }
+
+    thread::scope(|scope| {
+        let (result_sender, result_receiver) = mpsc::channel();
+
+        for _ in 0..threads {
+            let result_sender = result_sender.clone();
+            let (job_receiver, cancelled, work) = (&job_receiver, &cancelled, &work);
+
+            scope.spawn(move || loop {
+                if cancelled.load(Ordering::Relaxed) {
+                    break;
+                }
+
+                // The lock is released at the end of this statement, before the work starts.
+                let job = job_receiver.lock().unwrap().recv();
+                let (index, item) = match job {
+                    Ok(job) => job,
+                    Err(_) => break,
+                };
+
+                if result_sender.send((index, work(item))).is_err() {
+                    break;
+                }
+            });
+        }
+        // Only the workers hold senders now, so the loop below ends when the last worker is done.
+        drop(result_sender);
+
+        // Dropping the job sender once all jobs are sent lets recv() fail, which stops the workers.
+        let mut job_sender = (jobs.len() > 0).then_some(job_sender);
+
+        let mut parked = BTreeMap::new();
+        let mut next = 0;
+        for (index, result) in result_receiver {
+            parked.insert(index, result);
+
+            while let Some(result) = parked.remove(&next) {
+                next += 1;
+                if let Err(error) = consume(result) {
+                    cancelled.store(true, Ordering::Relaxed);
+                    return Err(error);
+                }
+
+                // A slot in the window is free again.
+                if let Some(sender) = &job_sender {
+                    sender.send(jobs.next().unwrap()).unwrap();
+                    if jobs.len() == 0 {
+                        job_sender = None;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{map_ordered, JOBS_PER_THREAD};
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+        time::Duration,
+    };
+
+    #[test]
+    fn test_results_keep_the_item_order() {
+        // Earlier items take longer, so they finish last.
+        let items: Vec<u64> = (0..20).collect();
+        let mut results = Vec::new();
+
+        map_ordered(
+            items,
+            4,
+            |item| {
+                thread::sleep(Duration::from_millis(20 - item));
+                item * 2
+            },
+            |result| {
+                results.push(result);
+                Ok::<(), ()>(())
+            },
+        )
+        .unwrap();
+
+        assert_eq!(results, (0..20).map(|item| item * 2).collect::<Vec<u64>>());
+    }
+
+    #[test]
+    fn test_failing_consumer_stops_the_workers() {
+        let items: Vec<usize> = (0..1000).collect();
+        let mut consumed = 0;
+
+        let result = map_ordered(
+            items,
+            2,
+            |item| {
+                thread::sleep(Duration::from_millis(1));
+                item
+            },
+            |item| {
+                consumed += 1;
+                match item {
+                    3 => Err("stop"),
+                    _ => Ok(()),
+                }
+            },
+        );
+
+        assert_eq!(result, Err("stop"));
+        assert_eq!(consumed, 4);
+    }
+
+    #[test]
+    fn test_slow_first_item_holds_back_later_jobs() {
+        let started = AtomicUsize::new(0);
+        let mut started_before_first = None;
+
+        map_ordered(
+            (0..100).collect(),
+            2,
+            |item: usize| {
+                started.fetch_add(1, Ordering::SeqCst);
+                if item == 0 {
+                    thread::sleep(Duration::from_millis(50));
+                }
+                item
+            },
+            |item| {
+                if item == 0 {
+                    started_before_first = Some(started.load(Ordering::SeqCst));
+                }
+                Ok::<(), ()>(())
+            },
+        )
+        .unwrap();
+
+        assert_eq!(started.into_inner(), 100);
+        assert!(started_before_first.unwrap() <= 2 * JOBS_PER_THREAD);
+    }
+
+    #[test]
+    fn test_no_items_and_more_threads_than_items() {
+        let mut results = Vec::new();
+        map_ordered(Vec::<u8>::new(), 8, |item| item, |_| Ok::<(), ()>(())).unwrap();
+        map_ordered(
+            vec![1, 2],
+            8,
+            |item| item + 1,
+            |result| {
+                results.push(result);
+                Ok::<(), ()>(())
+            },
+        )
+        .unwrap();
+        assert_eq!(results, vec![2, 3]);
+    }
+}
@@ -0,0 +1,1126 @@
+/*
+    A small regular expression engine, so grep can search for structured patterns without pulling in external crates.
+
+    A pattern goes through three steps:
+     - The parser turns the pattern into a syntax tree (Node).
+     - The compiler turns the syntax tree into a list of instructions (Inst) for a tiny virtual machine.
+     - The virtual machine (a Pike VM) runs all possible paths through the instructions in lockstep.
+
+    Because the virtual machine never backtracks, matching takes linear time in the length of the searched text,
+    no matter how the pattern looks like.
+
+    Supported syntax:
+     - Literals, escaped metacharacters (\., \*, ...) and \n, \t, \r
+     - Any character: .
+     - Character classes: [abc], [a-z], [^0-9] and the shorthands \d, \D, \w, \W, \s, \S
+     - Anchors: ^, $, \b, \B
+     - Groups: (...) captures, (?:...) does not
+     - Alternation: a|b
+     - Quantifiers: *, +, ?, {n}, {n,}, {n,m}, each optionally followed by ? to make it lazy
+*/
+
+use crate::casefold;
+use std::fmt;
+
+// Counted repetitions are expanded into copies of their body, so we need an upper bound to keep programs small.
+const MAX_REPETITION: u32 = 1000;
+const MAX_PROGRAM_SIZE: usize = 100_000;
+// The parser and the compiler follow the syntax tree by recursion, so its depth is limited to keep the stack small.
+const MAX_NESTING: usize = 250;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegexError {
+    pub message: String,
+    pub position: usize,
+}
+
+impl RegexError {
+    fn new(message: &str, position: usize) -> RegexError {
+        RegexError {
+            message: message.to_string(),
+            position,
+        }
+    }
+}
+
+impl fmt::Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl std::error::Error for RegexError {}
+
+#[derive(Debug, Clone)]
+pub struct Regex {
+    pattern: String,
+    program: Vec<Inst>,
+    slots: usize,
+    case_insensitive: bool,
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, RegexError> {
+        Regex::build(pattern, false)
+    }
+
+    pub fn new_case_insensitive(pattern: &str) -> Result<Regex, RegexError> {
+        Regex::build(pattern, true)
+    }
+
+    fn build(pattern: &str, case_insensitive: bool) -> Result<Regex, RegexError> {
+        let (node, groups) = Parser::new(pattern).parse()?;
+        let program = Compiler::new().compile(&node)?;
+
+        Ok(Regex {
+            pattern: pattern.to_string(),
+            program,
+            slots: (groups + 1) * 2,
+            case_insensitive,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    // The number of groups, including the implicit group 0 that spans the whole match.
+    pub fn captures_len(&self) -> usize {
+        self.slots / 2
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.find(text).is_some()
+    }
+
+    pub fn find<'t>(&self, text: &'t str) -> Option<Match<'t>> {
+        self.find_at(text, 0)
+    }
+
+    pub fn find_at<'t>(&self, text: &'t str, start: usize) -> Option<Match<'t>> {
+        let slots = self.search(text, start)?;
+        Some(Match::new(text, slots[0]?, slots[1]?))
+    }
+
+    pub fn find_iter<'r, 't>(&'r self, text: &'t str) -> FindMatches<'r, 't> {
+        FindMatches {
+            regex: self,
+            text,
+            position: 0,
+            last_end: None,
+        }
+    }
+
+    pub fn captures<'t>(&self, text: &'t str) -> Option<Captures<'t>> {
+        self.captures_at(text, 0)
+    }
+
+    pub fn captures_at<'t>(&self, text: &'t str, start: usize) -> Option<Captures<'t>> {
+        let slots = self.search(text, start)?;
+        Some(Captures { text, slots })
+    }
+
+    // Runs the Pike VM, returning the capture slots of the leftmost match starting at or after `start`.
+    fn search(&self, text: &str, start: usize) -> Option<Vec<Option<usize>>> {
+        let mut current = Threads::new(self.program.len());
+        let mut next = Threads::new(self.program.len());
+        let mut matched = None;
+        let mut at = start;
+
+        loop {
+            // As long as nothing matched, a new attempt starts at every position.
+            // It is added last, so attempts that started further left keep their priority.
+            if matched.is_none() {
+                self.add_thread(&mut current, 0, text, at, vec![None; self.slots]);
+            }
+            if current.list.is_empty() && matched.is_some() {
+                break;
+            }
+
+            let character = text[at..].chars().next();
+            for thread in current.list.drain(..) {
+                let step = match (&self.program[thread.pc], character) {
+                    (Inst::Match, _) => {
+                        // Threads after this one have a lower priority, so they are cut off.
+                        matched = Some(thread.slots);
+                        break;
+                    }
+                    (Inst::Char(expected), Some(c)) => self.chars_equal(*expected, c),
+                    (Inst::Any, Some(_)) => true,
+                    (Inst::Class(class), Some(c)) => self.class_matches(class, c),
+                    _ => false,
+                };
+
+                if let (true, Some(c)) = (step, character) {
+                    self.add_thread(
+                        &mut next,
+                        thread.pc + 1,
+                        text,
+                        at + c.len_utf8(),
+                        thread.slots,
+                    );
+                }
+            }
+
+            match character {
+                Some(c) => at += c.len_utf8(),
+                None => break,
+            }
+
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+        }
+
+        matched
+    }
+
+    /*
+        Follows all instructions that do not consume a character, so the list only holds threads waiting for input.
+        The paths are kept on a stack instead of following them by recursion, because patterns like ((a?){100}){100}
+        chain thousands of such instructions. The preferred side of a split is pushed last, so it is followed first.
+    */
+    fn add_thread(
+        &self,
+        threads: &mut Threads,
+        pc: usize,
+        text: &str,
+        at: usize,
+        slots: Vec<Option<usize>>,
+    ) {
+        let mut stack = vec![(pc, slots)];
+
+        while let Some((pc, mut slots)) = stack.pop() {
+            if !threads.visit(pc) {
+                continue;
+            }
+
+            match &self.program[pc] {
+                Inst::Jump(target) => stack.push((*target, slots)),
+                Inst::Split(preferred, other) => {
+                    stack.push((*other, slots.clone()));
+                    stack.push((*preferred, slots));
+                }
+                Inst::Save(slot) => {
+                    slots[*slot] = Some(at);
+                    stack.push((pc + 1, slots));
+                }
+                Inst::Assert(assertion) => {
+                    if assertion.holds(text, at) {
+                        stack.push((pc + 1, slots));
+                    }
+                }
+                _ => threads.list.push(Thread { pc, slots }),
+            }
+        }
+    }
+
+    fn chars_equal(&self, expected: char, actual: char) -> bool {
+        expected == actual
+            || (self.case_insensitive && casefold::chars_equal(expected, actual, false))
+    }
+
+    /*
+        With -i a character is in the class when one of its case variants is.
+        The negation is applied only afterwards, otherwise [^a] would match 'A' through its variant 'a'.
+    */
+    fn class_matches(&self, class: &Class, c: char) -> bool {
+        let contained = class.contains(c)
+            || (self.case_insensitive
+                && c.to_lowercase()
+                    .chain(c.to_uppercase())
+                    .chain(casefold::fold(c, false))
+                    .any(|other| class.contains(other)));
+        contained != class.negated
+    }
+}
+
+// Escapes all metacharacters, so the text is matched literally.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\.+*?()|[]{}^$".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+impl fmt::Display for Regex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.pattern)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Match<'t> {
+    text: &'t str,
+    start: usize,
+    end: usize,
+}
+
+impl<'t> Match<'t> {
+    fn new(text: &'t str, start: usize, end: usize) -> Match<'t> {
+        Match { text, start, end }
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    pub fn range(&self) -> std::ops::Range<usize> {
+        self.start..self.end
+    }
+
+    pub fn as_str(&self) -> &'t str {
+        &self.text[self.start..self.end]
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Captures<'t> {
+    text: &'t str,
+    slots: Vec<Option<usize>>,
+}
+
+impl<'t> Captures<'t> {
+    // Group 0 is the whole match. Groups that did not take part in the match are None.
+    pub fn get(&self, group: usize) -> Option<Match<'t>> {
+        let start = (*self.slots.get(group * 2)?)?;
+        let end = (*self.slots.get(group * 2 + 1)?)?;
+        Some(Match::new(self.text, start, end))
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len() / 2
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+}
+
+pub struct FindMatches<'r, 't> {
+    regex: &'r Regex,
+    text: &'t str,
+    position: usize,
+    last_end: Option<usize>,
+}
+
+impl<'r, 't> Iterator for FindMatches<'r, 't> {
+    type Item = Match<'t>;
+
+    fn next(&mut self) -> Option<Match<'t>> {
+        loop {
+            if self.position > self.text.len() {
+                return None;
+            }
+
+            let found = self.regex.find_at(self.text, self.position)?;
+
+            // An empty match right after the previous match would make us find the same spot forever.
+            if found.start == found.end && Some(found.end) == self.last_end {
+                match self.text[found.end..].chars().next() {
+                    Some(c) => self.position = found.end + c.len_utf8(),
+                    None => return None,
+                }
+                continue;
+            }
+
+            self.position = if found.start == found.end {
+                match self.text[found.end..].chars().next() {
+                    Some(c) => found.end + c.len_utf8(),
+                    None => self.text.len() + 1,
+                }
+            } else {
+                found.end
+            };
+            self.last_end = Some(found.end);
+
+            return Some(found);
+        }
+    }
+}
+
+struct Thread {
+    pc: usize,
+    slots: Vec<Option<usize>>,
+}
+
+// A list of threads that remembers which instructions were already visited at the current position.
+struct Threads {
+    list: Vec<Thread>,
+    visited: Vec<usize>,
+    generation: usize,
+}
+
+impl Threads {
+    fn new(size: usize) -> Threads {
+        Threads {
+            list: Vec::new(),
+            visited: vec![0; size],
+            generation: 1,
+        }
+    }
+
+    fn visit(&mut self, pc: usize) -> bool {
+        if self.visited[pc] == self.generation {
+            return false;
+        }
+        self.visited[pc] = self.generation;
+        true
+    }
+
+    fn clear(&mut self) {
+        self.list.clear();
+        self.generation += 1;
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Empty,
+    Literal(char),
+    Any,
+    Class(Class),
+    Assertion(Assertion),
+    Group(Box<Node>, Option<usize>),
+    Concat(Vec<Node>),
+    Alternation(Vec<Node>),
+    Repetition {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+        greedy: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Assertion {
+    LineStart,
+    LineEnd,
+    WordBoundary,
+    NotWordBoundary,
+}
+
+impl Assertion {
+    fn holds(&self, text: &str, at: usize) -> bool {
+        match self {
+            Assertion::LineStart => at == 0,
+            Assertion::LineEnd => at == text.len(),
+            Assertion::WordBoundary => Assertion::is_word_boundary(text, at),
+            Assertion::NotWordBoundary => !Assertion::is_word_boundary(text, at),
+        }
+    }
+
+    fn is_word_boundary(text: &str, at: usize) -> bool {
+        let before = text[..at].chars().next_back().is_some_and(is_word_char);
+        let after = text[at..].chars().next().is_some_and(is_word_char);
+        before != after
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PerlClass {
+    Digit,
+    Word,
+    Space,
+}
+
+impl PerlClass {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            PerlClass::Digit => c.is_ascii_digit(),
+            PerlClass::Word => is_word_char(c),
+            PerlClass::Space => c.is_whitespace(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ClassItem {
+    Range(char, char),
+    Perl(PerlClass, bool),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Class {
+    items: Vec<ClassItem>,
+    negated: bool,
+}
+
+impl Class {
+    fn perl(class: PerlClass, negated: bool) -> Class {
+        Class {
+            items: vec![ClassItem::Perl(class, negated)],
+            negated: false,
+        }
+    }
+
+    // Whether c is one of the items, ignoring the negation of the class.
+    fn contains(&self, c: char) -> bool {
+        self.items.iter().any(|item| match item {
+            ClassItem::Range(from, to) => *from <= c && c <= *to,
+            ClassItem::Perl(class, negated) => class.matches(c) != *negated,
+        })
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+    groups: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn new(pattern: &str) -> Parser {
+        Parser {
+            chars: pattern.chars().collect(),
+            position: 0,
+            groups: 0,
+            depth: 0,
+        }
+    }
+
+    fn parse(mut self) -> Result<(Node, usize), RegexError> {
+        let node = self.parse_alternation()?;
+
+        if let Some(c) = self.peek() {
+            // The only way to stop early is an unbalanced closing parenthesis.
+            return Err(RegexError::new(&format!("unexpected '{c}'"), self.position));
+        }
+
+        Ok((node, self.groups))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += 1;
+        Some(c)
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.position += 1;
+            return true;
+        }
+        false
+    }
+
+    fn parse_alternation(&mut self) -> Result<Node, RegexError> {
+        let mut branches = vec![self.parse_concat()?];
+        while self.eat('|') {
+            branches.push(self.parse_concat()?);
+        }
+
+        Ok(match branches.len() {
+            1 => branches.pop().unwrap(),
+            _ => Node::Alternation(branches),
+        })
+    }
+
+    fn parse_concat(&mut self) -> Result<Node, RegexError> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            nodes.push(self.parse_repetition()?);
+        }
+
+        Ok(match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.pop().unwrap(),
+            _ => Node::Concat(nodes),
+        })
+    }
+
+    fn parse_repetition(&mut self) -> Result<Node, RegexError> {
+        let mut node = self.parse_atom()?;
+
+        // Stacked operators like a*+* nest just like groups do.
+        let mut stacked = 0;
+        loop {
+            let position = self.position;
+            let (min, max) = match self.peek() {
+                Some('*') => {
+                    self.position += 1;
+                    (0, None)
+                }
+                Some('+') => {
+                    self.position += 1;
+                    (1, None)
+                }
+                Some('?') => {
+                    self.position += 1;
+                    (0, Some(1))
+                }
+                Some('{') => match self.parse_counted()? {
+                    Some(bounds) => bounds,
+                    // Not a valid counted repetition, so the brace is read as a literal by the next atom.
+                    None => break,
+                },
+                _ => break,
+            };
+
+            stacked += 1;
+            if self.depth + stacked > MAX_NESTING {
+                return Err(RegexError::new("pattern is nested too deeply", position));
+            }
+
+            let greedy = !self.eat('?');
+            node = Node::Repetition {
+                node: Box::new(node),
+                min,
+                max,
+                greedy,
+            };
+        }
+
+        Ok(node)
+    }
+
+    fn parse_counted(&mut self) -> Result<Option<(u32, Option<u32>)>, RegexError> {
+        let start = self.position;
+        self.position += 1;
+
+        let min = match self.parse_number() {
+            Some(min) => min,
+            None => {
+                self.position = start;
+                return Ok(None);
+            }
+        };
+        let max = if self.eat(',') {
+            self.parse_number()
+        } else {
+            Some(min)
+        };
+
+        if !self.eat('}') {
+            self.position = start;
+            return Ok(None);
+        }
+
+        if min > MAX_REPETITION || max.is_some_and(|max| max > MAX_REPETITION) {
+            return Err(RegexError::new("repetition count is too large", start));
+        }
+        if max.is_some_and(|max| max < min) {
+            return Err(RegexError::new("invalid repetition range", start));
+        }
+
+        Ok(Some((min, max)))
+    }
+
+    fn parse_number(&mut self) -> Option<u32> {
+        let start = self.position;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.position += 1;
+        }
+
+        self.chars[start..self.position]
+            .iter()
+            .collect::<String>()
+            .parse()
+            .ok()
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, RegexError> {
+        let position = self.position;
+        let c = match self.next() {
+            Some(c) => c,
+            None => return Err(RegexError::new("unexpected end of pattern", position)),
+        };
+
+        match c {
+            '(' => self.parse_group(position),
+            '[' => self.parse_class(position),
+            '\\' => self.parse_escape(),
+            '.' => Ok(Node::Any),
+            '^' => Ok(Node::Assertion(Assertion::LineStart)),
+            '$' => Ok(Node::Assertion(Assertion::LineEnd)),
+            '*' | '+' | '?' => Err(RegexError::new(
+                "repetition operator is missing an expression",
+                position,
+            )),
+            c => Ok(Node::Literal(c)),
+        }
+    }
+
+    fn parse_group(&mut self, start: usize) -> Result<Node, RegexError> {
+        if self.depth == MAX_NESTING {
+            return Err(RegexError::new("pattern is nested too deeply", start));
+        }
+
+        let index = if self.chars[self.position..].starts_with(&['?', ':']) {
+            self.position += 2;
+            None
+        } else {
+            self.groups += 1;
+            Some(self.groups)
+        };
+
+        self.depth += 1;
+        let node = self.parse_alternation()?;
+        self.depth -= 1;
+        if !self.eat(')') {
+            return Err(RegexError::new("unclosed group", start));
+        }
+
+        Ok(Node::Group(Box::new(node), index))
+    }
+
+    fn parse_class(&mut self, start: usize) -> Result<Node, RegexError> {
+        let negated = self.eat('^');
+        let mut items = Vec::new();
+
+        // A closing bracket right at the beginning is a literal.
+        if self.eat(']') {
+            items.push(ClassItem::Range(']', ']'));
+        }
+
+        loop {
+            let c = match self.next() {
+                Some(']') => break,
+                Some(c) => c,
+                None => return Err(RegexError::new("unclosed character class", start)),
+            };
+
+            let from = if c == '\\' {
+                match self.parse_class_escape()? {
+                    Ok(c) => c,
+                    Err(item) => {
+                        items.push(item);
+                        continue;
+                    }
+                }
+            } else {
+                c
+            };
+
+            // A dash forms a range, unless it is the last character in the class.
+            if self.peek() == Some('-') && self.chars.get(self.position + 1) != Some(&']') {
+                self.position += 1;
+                let to = match self.next() {
+                    Some('\\') => match self.parse_class_escape()? {
+                        Ok(c) => c,
+                        Err(_) => {
+                            return Err(RegexError::new("invalid range in character class", start))
+                        }
+                    },
+                    Some(c) => c,
+                    None => return Err(RegexError::new("unclosed character class", start)),
+                };
+
+                if to < from {
+                    return Err(RegexError::new("invalid range in character class", start));
+                }
+                items.push(ClassItem::Range(from, to));
+            } else {
+                items.push(ClassItem::Range(from, from));
+            }
+        }
+
+        Ok(Node::Class(Class { items, negated }))
+    }
+
+    // Escapes inside a class either stand for a single character or for a whole shorthand class.
+    fn parse_class_escape(&mut self) -> Result<Result<char, ClassItem>, RegexError> {
+        Ok(match self.parse_escape()? {
+            Node::Literal(c) => Ok(c),
+            Node::Class(class) => Err(class.items[0].clone()),
+            _ => {
+                return Err(RegexError::new(
+                    "invalid escape in character class",
+                    self.position - 1,
+                ))
+            }
+        })
+    }
+
+    fn parse_escape(&mut self) -> Result<Node, RegexError> {
+        let position = self.position;
+        let c = match self.next() {
+            Some(c) => c,
+            None => return Err(RegexError::new("pattern ends with a backslash", position)),
+        };
+
+        Ok(match c {
+            'd' => Node::Class(Class::perl(PerlClass::Digit, false)),
+            'D' => Node::Class(Class::perl(PerlClass::Digit, true)),
+            'w' => Node::Class(Class::perl(PerlClass::Word, false)),
+            'W' => Node::Class(Class::perl(PerlClass::Word, true)),
+            's' => Node::Class(Class::perl(PerlClass::Space, false)),
+            'S' => Node::Class(Class::perl(PerlClass::Space, true)),
+            'b' => Node::Assertion(Assertion::WordBoundary),
+            'B' => Node::Assertion(Assertion::NotWordBoundary),
+            'n' => Node::Literal('\n'),
+            't' => Node::Literal('\t'),
+            'r' => Node::Literal('\r'),
+            c if c.is_ascii_alphanumeric() => {
+                return Err(RegexError::new(
+                    &format!("unknown escape sequence '\\{c}'"),
+                    position - 1,
+                ))
+            }
+            c => Node::Literal(c),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    Assert(Assertion),
+    // Split tries the first target before the second one, which is how greediness and alternation order work.
+    Split(usize, usize),
+    Jump(usize),
+    Save(usize),
+    Match,
+}
+
+struct Compiler {
+    program: Vec<Inst>,
+}
+
+impl Compiler {
+    fn new() -> Compiler {
+        Compiler {
+            program: Vec::new(),
+        }
+    }
+
+    fn compile(mut self, node: &Node) -> Result<Vec<Inst>, RegexError> {
+        // Slots 0 and 1 hold the bounds of the whole match.
+        self.push(Inst::Save(0))?;
+        self.compile_node(node)?;
+        self.push(Inst::Save(1))?;
+        self.push(Inst::Match)?;
+
+        Ok(self.program)
+    }
+
+    fn push(&mut self, inst: Inst) -> Result<usize, RegexError> {
+        if self.program.len() >= MAX_PROGRAM_SIZE {
+            return Err(RegexError::new("pattern is too large", 0));
+        }
+
+        self.program.push(inst);
+        Ok(self.program.len() - 1)
+    }
+
+    fn compile_node(&mut self, node: &Node) -> Result<(), RegexError> {
+        match node {
+            Node::Empty => {}
+            Node::Literal(c) => {
+                self.push(Inst::Char(*c))?;
+            }
+            Node::Any => {
+                self.push(Inst::Any)?;
+            }
+            Node::Class(class) => {
+                self.push(Inst::Class(class.clone()))?;
+            }
+            Node::Assertion(assertion) => {
+                self.push(Inst::Assert(*assertion))?;
+            }
+            Node::Group(node, None) => self.compile_node(node)?,
+            Node::Group(node, Some(index)) => {
+                self.push(Inst::Save(index * 2))?;
+                self.compile_node(node)?;
+                self.push(Inst::Save(index * 2 + 1))?;
+            }
+            Node::Concat(nodes) => {
+                for node in nodes {
+                    self.compile_node(node)?;
+                }
+            }
+            Node::Alternation(branches) => self.compile_alternation(branches)?,
+            Node::Repetition {
+                node,
+                min,
+                max,
+                greedy,
+            } => self.compile_repetition(node, *min, *max, *greedy)?,
+        }
+
+        Ok(())
+    }
+
+    fn compile_alternation(&mut self, branches: &[Node]) -> Result<(), RegexError> {
+        let mut jumps = Vec::new();
+
+        for (index, branch) in branches.iter().enumerate() {
+            if index == branches.len() - 1 {
+                self.compile_node(branch)?;
+                break;
+            }
+
+            let split = self.push(Inst::Split(0, 0))?;
+            self.compile_node(branch)?;
+            jumps.push(self.push(Inst::Jump(0))?);
+
+            let next = self.program.len();
+            self.program[split] = Inst::Split(split + 1, next);
+        }
+
+        let end = self.program.len();
+        for jump in jumps {
+            self.program[jump] = Inst::Jump(end);
+        }
+
+        Ok(())
+    }
+
+    fn compile_repetition(
+        &mut self,
+        node: &Node,
+        min: u32,
+        max: Option<u32>,
+        greedy: bool,
+    ) -> Result<(), RegexError> {
+        for _ in 0..min {
+            self.compile_node(node)?;
+        }
+
+        match max {
+            // Unbounded: loop back to a split that either runs the body again or leaves.
+            None => {
+                let split = self.push(Inst::Split(0, 0))?;
+                self.compile_node(node)?;
+                self.push(Inst::Jump(split))?;
+
+                let end = self.program.len();
+                self.program[split] = Compiler::split(greedy, split + 1, end);
+            }
+            // Bounded: each optional copy may be skipped, which also skips all copies after it.
+            Some(max) => {
+                let mut splits = Vec::new();
+                for _ in min..max {
+                    splits.push(self.push(Inst::Split(0, 0))?);
+                    self.compile_node(node)?;
+                }
+
+                let end = self.program.len();
+                for split in splits {
+                    self.program[split] = Compiler::split(greedy, split + 1, end);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn split(greedy: bool, body: usize, exit: usize) -> Inst {
+        match greedy {
+            true => Inst::Split(body, exit),
+            false => Inst::Split(exit, body),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape, Regex, MAX_NESTING};
+
+    fn find<'t>(pattern: &str, text: &'t str) -> Option<&'t str> {
+        Regex::new(pattern).unwrap().find(text).map(|m| m.as_str())
+    }
+
+    #[test]
+    fn test_literal() {
+        assert_eq!(find("abc", "xxabcxx"), Some("abc"));
+        assert_eq!(find("abc", "ab"), None);
+        assert_eq!(find("a\\.c", "abc a.c"), Some("a.c"));
+    }
+
+    #[test]
+    fn test_any_and_quantifiers() {
+        assert_eq!(find("a.c", "xabcx"), Some("abc"));
+        assert_eq!(find("ab*c", "ac"), Some("ac"));
+        assert_eq!(find("ab+c", "ac abbbc"), Some("abbbc"));
+        assert_eq!(find("colou?r", "color"), Some("color"));
+        assert_eq!(find("a{2,3}", "aaaa"), Some("aaa"));
+        assert_eq!(find("a{2}", "a aa"), Some("aa"));
+        assert_eq!(find("a{2,}", "aaaaa"), Some("aaaaa"));
+        assert_eq!(find("x{1}", "x{"), Some("x"));
+        assert_eq!(find("a{", "a{"), Some("a{"));
+    }
+
+    #[test]
+    fn test_lazy_quantifiers() {
+        assert_eq!(find("<.+>", "<a><b>"), Some("<a><b>"));
+        assert_eq!(find("<.+?>", "<a><b>"), Some("<a>"));
+        assert_eq!(find("a{2,4}?", "aaaa"), Some("aa"));
+    }
+
+    #[test]
+    fn test_classes() {
+        assert_eq!(find("[0-9]+", "abc 123 def"), Some("123"));
+        assert_eq!(find("[^a-z ]+", "abc DEF"), Some("DEF"));
+        assert_eq!(find("[-a]+", "b-a-b"), Some("-a-"));
+        assert_eq!(find("[]a]+", "b]a]b"), Some("]a]"));
+        assert_eq!(find("\\d+", "room 42"), Some("42"));
+        assert_eq!(find("\\w+", "  hello_world!"), Some("hello_world"));
+        assert_eq!(find("\\s\\S", "a b"), Some(" b"));
+        assert_eq!(find("[\\d.]+", "v1.2.3"), Some("1.2.3"));
+    }
+
+    #[test]
+    fn test_anchors() {
+        assert_eq!(find("^abc", "abcabc").map(str::len), Some(3));
+        assert_eq!(find("^abc", "xabc"), None);
+        assert_eq!(find("abc$", "abcx"), None);
+        assert_eq!(find("abc$", "xabc"), Some("abc"));
+        assert_eq!(find("\\bcat\\b", "concat cat"), Some("cat"));
+        assert_eq!(find("\\Bcat", "concat cat"), Some("cat"));
+        assert!(Regex::new("\\bcat\\b")
+            .unwrap()
+            .find("concatenate")
+            .is_none());
+    }
+
+    #[test]
+    fn test_alternation_and_groups() {
+        assert_eq!(find("cat|dog", "hotdog"), Some("dog"));
+        assert_eq!(find("(ab)+", "xababx"), Some("abab"));
+        assert_eq!(find("(?:ab|cd)+e", "abcde"), Some("abcde"));
+        assert_eq!(find("a(|b)c", "ac"), Some("ac"));
+        // The leftmost branch wins, not the longest.
+        assert_eq!(find("a|ab", "ab"), Some("a"));
+    }
+
+    #[test]
+    fn test_captures() {
+        let regex = Regex::new("(\\w+)@(\\w+)\\.(com|org)").unwrap();
+        let captures = regex.captures("mail me: ferris@rust.org").unwrap();
+
+        assert_eq!(captures.len(), 4);
+        assert_eq!(captures.get(0).unwrap().as_str(), "ferris@rust.org");
+        assert_eq!(captures.get(1).unwrap().as_str(), "ferris");
+        assert_eq!(captures.get(2).unwrap().as_str(), "rust");
+        assert_eq!(captures.get(3).unwrap().as_str(), "org");
+
+        let captures = Regex::new("a(x)?b").unwrap().captures("ab").unwrap();
+        assert!(captures.get(1).is_none());
+    }
+
+    #[test]
+    fn test_find_iter() {
+        let regex = Regex::new("\\d+").unwrap();
+        let matches: Vec<&str> = regex.find_iter("1 22 333").map(|m| m.as_str()).collect();
+        assert_eq!(matches, vec!["1", "22", "333"]);
+
+        let regex = Regex::new("x*").unwrap();
+        let ranges: Vec<(usize, usize)> = regex
+            .find_iter("axb")
+            .map(|m| (m.start(), m.end()))
+            .collect();
+        assert_eq!(ranges, vec![(0, 0), (1, 2), (3, 3)]);
+    }
+
+    #[test]
+    fn test_case_insensitive() {
+        let regex = Regex::new_case_insensitive("hello [a-z]+").unwrap();
+        assert_eq!(
+            regex.find("Say HELLO World").unwrap().as_str(),
+            "HELLO World"
+        );
+    }
+
+    #[test]
+    fn test_case_insensitive_uses_case_folding() {
+        let regex = Regex::new_case_insensitive("stra(ß|x)e").unwrap();
+        assert!(regex.is_match("STRAẞE"));
+        assert!(Regex::new_case_insensitive("οδοσ")
+            .unwrap()
+            .is_match("ΟΔΟΣ οδος"));
+        assert!(Regex::new_case_insensitive("[σ]").unwrap().is_match("ς"));
+    }
+
+    #[test]
+    fn test_case_insensitive_negated_classes() {
+        let regex = Regex::new_case_insensitive("^[^a]$").unwrap();
+        assert!(!regex.is_match("a"));
+        assert!(!regex.is_match("A"));
+        assert!(regex.is_match("b"));
+
+        let regex = Regex::new_case_insensitive("^[^a-z]+$").unwrap();
+        assert!(!regex.is_match("HELLO"));
+        assert!(regex.is_match("123"));
+        assert!(!Regex::new_case_insensitive("[^σ]").unwrap().is_match("Σς"));
+    }
+
+    #[test]
+    fn test_unicode() {
+        assert_eq!(find("з.р", "Здравствуйте, мир"), None);
+        assert_eq!(find("м.р", "Здравствуйте, мир"), Some("мир"));
+        assert_eq!(find("\\w+", "¡Hola!"), Some("Hola"));
+    }
+
+    #[test]
+    fn test_nested_empty_loops_terminate() {
+        assert_eq!(find("(a*)*b", "aaab"), Some("aaab"));
+        assert_eq!(find("(a?)+$", "aa"), Some("aa"));
+    }
+
+    #[test]
+    fn test_escape() {
+        let text = "a.b*c (d) [e] {1} ^$ x|y \\ ?+";
+        let regex = Regex::new(&escape(text)).unwrap();
+        assert_eq!(regex.find(text).unwrap().as_str(), text);
+        assert!(!regex.is_match("aXb*c (d) [e] {1} ^$ x|y \\ ?+"));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(Regex::new("(abc").is_err());
+        assert!(Regex::new("abc)").is_err());
+        assert!(Regex::new("[abc").is_err());
+        assert!(Regex::new("*abc").is_err());
+        assert!(Regex::new("[z-a]").is_err());
+        assert!(Regex::new("abc\\").is_err());
+        assert!(Regex::new("\\q").is_err());
+        assert!(Regex::new("a{3,2}").is_err());
+        assert!(Regex::new("a{5000}").is_err());
+
+        let error = Regex::new("ab(c").unwrap_err();
+        assert_eq!(error.position, 2);
+    }
+
+    #[test]
+    fn test_deep_nesting_is_rejected() {
+        let deep = format!("{}a{}", "(".repeat(20_000), ")".repeat(20_000));
+        let error = Regex::new(&deep).unwrap_err();
+        assert_eq!(error.message, "pattern is nested too deeply");
+        assert_eq!(error.position, MAX_NESTING);
+
+        let stacked = format!("a{}", "*".repeat(20_000));
+        assert!(Regex::new(&stacked).is_err());
+
+        let nested = format!("{}a{}", "(".repeat(100), ")".repeat(100));
+        assert_eq!(find(&nested, "xay"), Some("a"));
+    }
+
+    #[test]
+    fn test_long_chains_of_empty_steps() {
+        let regex = Regex::new("((a?){100}){100}b").unwrap();
+        assert!(regex.is_match("aaab"));
+        assert!(!regex.is_match("aaa"));
+    }
+}
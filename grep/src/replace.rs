@@ -0,0 +1,262 @@
+/*
+    Replaces matches with a template, like the replacement part of sed's "s/pattern/replacement/".
+
+    The template may refer to capture groups of a regular expression: "$1" or "${1}" is replaced by the text of group 1,
+    "$0" by the whole match. The braces are needed when the group is followed by a digit, e.g. "${1}0".
+    "$$" writes a single "$". A "$" that is not followed by a group number is kept as it is.
+    Groups that do not exist or did not take part in the match are replaced by nothing.
+
+    Files are edited in place by writing the new content to a temporary file next to the original
+    and renaming it over the original. A rename within the same directory is atomic, so the file is never
+    left half written, even if we are interrupted.
+*/
+
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    process,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Group(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replacement {
+    parts: Vec<Part>,
+}
+
+// The byte ranges of the capture groups of one match. Group 0 is the whole match and always present.
+pub type Groups = Vec<Option<Range<usize>>>;
+
+impl Replacement {
+    pub fn new(template: &str) -> Replacement {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut rest = template;
+
+        while let Some(dollar) = rest.find('$') {
+            text.push_str(&rest[..dollar]);
+            rest = &rest[dollar + 1..];
+
+            if let Some(after) = rest.strip_prefix('$') {
+                text.push('$');
+                rest = after;
+                continue;
+            }
+
+            let (group, after) = match parse_group(rest) {
+                Some(parsed) => parsed,
+                None => {
+                    text.push('$');
+                    continue;
+                }
+            };
+            if !text.is_empty() {
+                parts.push(Part::Text(std::mem::take(&mut text)));
+            }
+            parts.push(Part::Group(group));
+            rest = after;
+        }
+
+        text.push_str(rest);
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+
+        Replacement { parts }
+    }
+
+    /*
+        Replaces every match in the line and returns the new line,
+        together with the ranges the replacements ended up at (e.g. to highlight them).
+        The matches have to be sorted and must not overlap.
+    */
+    pub fn apply(&self, line: &str, matches: &[Groups]) -> (String, Vec<Range<usize>>) {
+        let mut replaced = String::with_capacity(line.len());
+        let mut ranges = Vec::with_capacity(matches.len());
+        let mut position = 0;
+
+        for groups in matches {
+            let whole = match &groups[0] {
+                Some(whole) => whole.clone(),
+                None => continue,
+            };
+            replaced.push_str(&line[position..whole.start]);
+
+            let start = replaced.len();
+            for part in &self.parts {
+                match part {
+                    Part::Text(text) => replaced.push_str(text),
+                    Part::Group(group) => {
+                        if let Some(Some(range)) = groups.get(*group) {
+                            replaced.push_str(&line[range.clone()]);
+                        }
+                    }
+                }
+            }
+            ranges.push(start..replaced.len());
+            position = whole.end;
+        }
+        replaced.push_str(&line[position..]);
+
+        (replaced, ranges)
+    }
+}
+
+// Parses "12" or "{12}" at the start of the text and returns the group number and the text after it.
+fn parse_group(text: &str) -> Option<(usize, &str)> {
+    if let Some(braced) = text.strip_prefix('{') {
+        let (number, after) = braced.split_once('}')?;
+        return Some((number.parse().ok()?, after));
+    }
+
+    let digits = text.len() - text.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let number = text[..digits].parse().ok()?;
+    Some((number, &text[digits..]))
+}
+
+/*
+    Replaces the content of the file with the given content.
+    With a backup suffix, the original content is kept in a copy whose name ends with the suffix.
+    The permissions of the original file are kept.
+*/
+pub fn write_in_place(path: &Path, content: &[u8], backup_suffix: Option<&str>) -> io::Result<()> {
+    let permissions = fs::metadata(path)?.permissions();
+    let temporary = sibling(path, &format!(".grep-{}.tmp", process::id()), true);
+
+    let written = File::create(&temporary).and_then(|mut file| {
+        file.write_all(content)?;
+        file.set_permissions(permissions)?;
+        // The content has to reach the disk before the rename makes it visible.
+        file.sync_all()
+    });
+    if let Err(error) = written {
+        let _ = fs::remove_file(&temporary);
+        return Err(error);
+    }
+
+    if let Some(suffix) = backup_suffix {
+        if let Err(error) = fs::copy(path, sibling(path, suffix, false)) {
+            let _ = fs::remove_file(&temporary);
+            return Err(error);
+        }
+    }
+
+    fs::rename(&temporary, path).inspect_err(|_| {
+        let _ = fs::remove_file(&temporary);
+    })
+}
+
+// A path in the same directory as the given one, with a suffix appended to the file name. Hidden if requested.
+fn sibling(path: &Path, suffix: &str, hidden: bool) -> PathBuf {
+    let mut name = match hidden {
+        true => ".".into(),
+        false => std::ffi::OsString::new(),
+    };
+    name.push(path.file_name().unwrap_or_default());
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_in_place, Groups, Replacement};
+    use std::{env, fs, path::PathBuf, process};
+
+    fn whole(ranges: &[std::ops::Range<usize>]) -> Vec<Groups> {
+        ranges
+            .iter()
+            .map(|range| vec![Some(range.clone())])
+            .collect()
+    }
+
+    #[test]
+    fn test_plain_replacement() {
+        let replacement = Replacement::new("X");
+        assert_eq!(
+            replacement.apply("a foo b foo", &whole(&[2..5, 8..11])),
+            ("a X b X".to_string(), vec![2..3, 6..7])
+        );
+        assert_eq!(replacement.apply("none", &[]), ("none".to_string(), vec![]));
+    }
+
+    #[test]
+    fn test_group_references() {
+        // "2024-01-31" with the groups year, month and day.
+        let line = "on 2024-01-31.";
+        let groups = vec![vec![Some(3..13), Some(3..7), Some(8..10), Some(11..13)]];
+
+        let apply = |template: &str| Replacement::new(template).apply(line, &groups).0;
+        assert_eq!(apply("$3.$2.$1"), "on 31.01.2024.");
+        assert_eq!(apply("${1}0"), "on 20240.");
+        assert_eq!(apply("[$0]"), "on [2024-01-31].");
+        // Unknown groups are empty, "$$" is a dollar and a lone "$" is kept.
+        assert_eq!(apply("$9|$$1|$x|${}|$"), "on |$1|$x|${}|$.");
+    }
+
+    #[test]
+    fn test_unmatched_group_is_empty() {
+        let groups = vec![vec![Some(0..3), None, Some(0..3)]];
+        let (replaced, _) = Replacement::new("<$1$2>").apply("abc", &groups);
+        assert_eq!(replaced, "<abc>");
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("grep-replace-{name}-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_write_in_place() {
+        let dir = temp_dir("write");
+        let path = dir.join("notes.txt");
+        fs::write(&path, "old\n").unwrap();
+
+        write_in_place(&path, b"new\n", None).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new\n");
+        // Neither the temporary file nor a backup is left behind.
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        write_in_place(&path, b"newer\n", Some(".bak")).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "newer\n");
+        assert_eq!(
+            fs::read_to_string(dir.join("notes.txt.bak")).unwrap(),
+            "new\n"
+        );
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_in_place_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir("permissions");
+        let path = dir.join("script.sh");
+        fs::write(&path, "echo old\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o750)).unwrap();
+
+        write_in_place(&path, b"echo new\n", None).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o750);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_in_place_missing_file() {
+        let dir = temp_dir("missing");
+        assert!(write_in_place(&dir.join("absent.txt"), b"", None).is_err());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
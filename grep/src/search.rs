@@ -0,0 +1,328 @@
+/*
+    Searches a stream line by line instead of reading the whole input into memory first.
+
+    Search is an iterator, so lines are only read when the next result is requested.
+    This keeps the memory usage bounded by the length of the longest line plus the lines kept for --before-context,
+    no matter how large the input is. It also means that a caller who stops early (e.g. after the first match)
+    never reads the rest of the input. With stop_after, the search stops on its own once enough lines matched,
+    after reading the context that follows the last of them.
+
+    Lines that are not valid UTF-8 are converted lossily, invalid bytes are replaced by U+FFFD.
+    Such lines keep their original bytes as well, for output that has to report them exactly (see raw_offset).
+*/
+
+use std::{
+    borrow::Cow,
+    collections::VecDeque,
+    io::{self, BufRead},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchLine {
+    pub index: usize,  // Zero based, add one to get the line number
+    pub offset: usize, // Of the first byte of the line, counted from the start of the input
+    pub text: String,
+    pub bytes: Option<Vec<u8>>, // The line without its line ending, only if it is not valid UTF-8
+    pub is_match: bool,
+    pub after_gap: bool, // Lines were skipped between the previous yielded line and this one
+}
+
+pub struct Search<R, F> {
+    reader: R,
+    is_match: F,
+    before: usize,
+    after: usize,
+    buffer: Vec<u8>,
+    index: usize,
+    offset: usize,
+    before_lines: VecDeque<SearchLine>,
+    pending: VecDeque<SearchLine>,
+    after_remaining: usize,
+    // How many more matches may be yielded, None if there is no limit.
+    remaining_matches: Option<usize>,
+    last_yielded: Option<usize>,
+    done: bool,
+}
+
+impl<R: BufRead, F: Fn(&str) -> bool> Search<R, F> {
+    pub fn new(reader: R, before: usize, after: usize, is_match: F) -> Search<R, F> {
+        Search {
+            reader,
+            is_match,
+            before,
+            after,
+            buffer: Vec::new(),
+            index: 0,
+            offset: 0,
+            before_lines: VecDeque::with_capacity(before),
+            pending: VecDeque::new(),
+            after_remaining: 0,
+            remaining_matches: None,
+            last_yielded: None,
+            done: false,
+        }
+    }
+
+    // Like GNU grep's --max-count: after the given number of matches, only their after context is read.
+    pub fn stop_after(mut self, matches: usize) -> Search<R, F> {
+        self.remaining_matches = Some(matches);
+        self
+    }
+
+    fn push(&mut self, mut line: SearchLine, is_match: bool) {
+        line.after_gap = self.last_yielded.is_some_and(|last| line.index > last + 1);
+        line.is_match = is_match;
+        self.last_yielded = Some(line.index);
+        self.pending.push_back(line);
+    }
+}
+
+impl<R: BufRead, F: Fn(&str) -> bool> Iterator for Search<R, F> {
+    type Item = io::Result<SearchLine>;
+
+    fn next(&mut self) -> Option<io::Result<SearchLine>> {
+        loop {
+            if let Some(line) = self.pending.pop_front() {
+                return Some(Ok(line));
+            }
+            if self.done {
+                return None;
+            }
+            if self.remaining_matches == Some(0) && self.after_remaining == 0 {
+                self.done = true;
+                return None;
+            }
+
+            self.buffer.clear();
+            match self.reader.read_until(b'\n', &mut self.buffer) {
+                Ok(0) => {
+                    self.done = true;
+                    continue;
+                }
+                Ok(_) => {}
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => {
+                    self.done = true;
+                    return Some(Err(error));
+                }
+            }
+
+            let index = self.index;
+            let offset = self.offset;
+            self.index += 1;
+            self.offset += self.buffer.len();
+
+            // The line is only copied if we have to keep it, which is rare for large inputs.
+            let text = decode_line(&self.buffer);
+            let is_match = self.remaining_matches != Some(0) && (self.is_match)(&text);
+            let keep = is_match || self.after_remaining > 0 || self.before > 0;
+            if !keep {
+                continue;
+            }
+            let bytes = match text {
+                Cow::Borrowed(_) => None,
+                Cow::Owned(_) => Some(strip_line_ending(&self.buffer).to_vec()),
+            };
+            let line = SearchLine {
+                index,
+                offset,
+                text: text.into_owned(),
+                bytes,
+                is_match: false,
+                after_gap: false,
+            };
+
+            if is_match {
+                if let Some(remaining) = &mut self.remaining_matches {
+                    *remaining -= 1;
+                }
+                while let Some(line) = self.before_lines.pop_front() {
+                    self.push(line, false);
+                }
+                self.push(line, true);
+                self.after_remaining = self.after;
+            } else if self.after_remaining > 0 {
+                self.after_remaining -= 1;
+                self.push(line, false);
+            } else {
+                if self.before_lines.len() == self.before {
+                    self.before_lines.pop_front();
+                }
+                self.before_lines.push_back(line);
+            }
+        }
+    }
+}
+
+// Strips the line ending ("\n" or "\r\n") and replaces invalid UTF-8.
+pub fn decode_line(line: &[u8]) -> Cow<'_, str> {
+    String::from_utf8_lossy(strip_line_ending(line))
+}
+
+fn strip_line_ending(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+/*
+    Turns an offset into the text of decode_line back into an offset into the original bytes.
+    from_utf8_lossy replaces every run of invalid bytes by a single U+FFFD, which takes 3 bytes in the text.
+*/
+pub fn raw_offset(bytes: &[u8], offset: usize) -> usize {
+    let (mut decoded, mut raw) = (0, 0);
+    for chunk in bytes.utf8_chunks() {
+        let valid = chunk.valid().len();
+        if offset <= decoded + valid {
+            return raw + offset - decoded;
+        }
+        decoded += valid;
+        raw += valid;
+        if !chunk.invalid().is_empty() {
+            decoded += char::REPLACEMENT_CHARACTER.len_utf8();
+            raw += chunk.invalid().len();
+        }
+    }
+    raw
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{raw_offset, Search, SearchLine};
+    use std::io::{self, BufRead, Read};
+
+    fn search(content: &[u8], before: usize, after: usize) -> Vec<SearchLine> {
+        Search::new(content, before, after, |line: &str| line.contains("match"))
+            .collect::<io::Result<Vec<SearchLine>>>()
+            .unwrap()
+    }
+
+    fn summary(lines: &[SearchLine]) -> Vec<(usize, bool, bool)> {
+        lines
+            .iter()
+            .map(|line| (line.index, line.is_match, line.after_gap))
+            .collect()
+    }
+
+    #[test]
+    fn test_matches_only() {
+        let lines = search(b"a match\nb\nc match\r\nd", 0, 0);
+        let texts: Vec<&str> = lines.iter().map(|line| line.text.as_str()).collect();
+        assert_eq!(texts, vec!["a match", "c match"]);
+        assert_eq!(summary(&lines), vec![(0, true, false), (2, true, true)]);
+    }
+
+    #[test]
+    fn test_context_and_gaps() {
+        let content = b"1\n2\n3 match\n4\n5\n6\n7\n8 match\n9\n10\n";
+        let lines = search(content, 1, 1);
+        assert_eq!(
+            summary(&lines),
+            vec![
+                (1, false, false),
+                (2, true, false),
+                (3, false, false),
+                (6, false, true),
+                (7, true, false),
+                (8, false, false),
+            ]
+        );
+
+        // With enough context, both windows merge and there is no gap.
+        let lines = search(content, 2, 2);
+        assert!(lines.iter().all(|line| !line.after_gap));
+        assert_eq!(lines.len(), 10);
+    }
+
+    #[test]
+    fn test_offsets() {
+        let content = "match\r\nÜber\nmatch again\n";
+        let lines = search(content.as_bytes(), 1, 0);
+        let offsets: Vec<usize> = lines.iter().map(|line| line.offset).collect();
+        assert_eq!(offsets, vec![0, 7, 13]);
+        assert_eq!(&content[13..24], "match again");
+    }
+
+    #[test]
+    fn test_invalid_utf8_is_replaced() {
+        let lines = search(b"bad \xff match\n", 0, 0);
+        assert_eq!(lines[0].text, "bad \u{fffd} match");
+        assert_eq!(lines[0].bytes.as_deref(), Some(&b"bad \xff match"[..]));
+        assert_eq!(search(b"good match\n", 0, 0)[0].bytes, None);
+    }
+
+    #[test]
+    fn test_raw_offset() {
+        let bytes = b"a\xffb \xe2\x82 match";
+        let text = String::from_utf8_lossy(bytes);
+        let start = text.find("match").unwrap();
+        assert_eq!(raw_offset(bytes, start), 7);
+        assert_eq!(raw_offset(bytes, text.find('b').unwrap()), 2);
+        assert_eq!(raw_offset(bytes, 0), 0);
+        assert_eq!(raw_offset(bytes, text.len()), bytes.len());
+        assert_eq!(raw_offset(b"\xff", 3), 1);
+    }
+
+    #[test]
+    fn test_input_without_trailing_newline() {
+        let lines = search(b"match", 0, 0);
+        assert_eq!(lines[0].text, "match");
+    }
+
+    // Produces "line match\n" forever, so the test only ends if the search stops reading on its own.
+    struct Endless;
+
+    impl Read for Endless {
+        fn read(&mut self, _buffer: &mut [u8]) -> io::Result<usize> {
+            unreachable!("Only BufRead is used")
+        }
+    }
+
+    impl BufRead for Endless {
+        fn fill_buf(&mut self) -> io::Result<&[u8]> {
+            Ok(b"line match\n")
+        }
+
+        fn consume(&mut self, _amount: usize) {}
+    }
+
+    #[test]
+    fn test_stop_after() {
+        let content = b"1 match\n2\n3 match\n4 match\n5\n6\n";
+        let matches = |before, after, max| {
+            let search = Search::new(&content[..], before, after, |line: &str| {
+                line.contains("match")
+            });
+            summary(
+                &search
+                    .stop_after(max)
+                    .map(Result::unwrap)
+                    .collect::<Vec<SearchLine>>(),
+            )
+        };
+
+        assert_eq!(matches(0, 0, 2), vec![(0, true, false), (2, true, true)]);
+        assert!(matches(1, 1, 0).is_empty());
+        // The context after the last match is still read, but later matches are only context.
+        assert_eq!(
+            matches(1, 1, 2),
+            vec![
+                (0, true, false),
+                (1, false, false),
+                (2, true, false),
+                (3, false, false)
+            ]
+        );
+
+        // The search ends on its own, even if the input does not.
+        let search = Search::new(Endless, 0, 2, |line: &str| line.contains("match"));
+        assert_eq!(search.stop_after(5).count(), 7);
+    }
+
+    #[test]
+    fn test_search_is_lazy() {
+        let search = Search::new(Endless, 0, 0, |line: &str| line.contains("match"));
+        let lines: Vec<SearchLine> = search.take(3).map(Result::unwrap).collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[2].index, 2);
+    }
+}
@@ -0,0 +1,746 @@
+/*
+    An interactive browser for search results, drawn with nothing but ANSI escape codes.
+
+    The inputs are read into memory once. Every change to the pattern searches those lines again,
+    with a matcher built from the same options as the regular search, so -i, -E, -w, -x, -v and --fuzzy still apply.
+    The selected lines are listed at the top, with a preview of the lines around the current one below them.
+
+    Keys:
+     - typing edits the pattern, Backspace deletes its last character and Ctrl-U clears it
+     - Up and Down (or Ctrl-P and Ctrl-N), PageUp, PageDown, Home and End move through the list
+     - Tab marks or unmarks the current line and moves on to the next one
+     - Enter quits and prints the marked lines, or the current line if none are marked
+     - Esc or Ctrl-C quits without printing anything
+
+    The terminal is switched to raw mode with stty, so keys arrive one by one and are not echoed.
+    Keys are read from /dev/tty and the browser is drawn on it, so the standard input can still be searched
+    and the standard output only receives the final selection, which can be piped into another program.
+*/
+
+use crate::{
+    build_matcher, color,
+    matcher::{AnyOf, Matcher, Substring},
+    search::{self, SearchLine},
+    Arguments,
+};
+use std::{
+    collections::BTreeSet,
+    fs::{File, OpenOptions},
+    io::{self, Read, Write},
+    process::{Command, Stdio},
+};
+
+// The lines shown above and below the current line in the preview.
+const PREVIEW_CONTEXT: usize = 3;
+
+// The preview is only shown if at least this many lines are left for the list.
+const MIN_LIST_ROWS: usize = 3;
+
+// Used when the size of the terminal can not be determined.
+const DEFAULT_SIZE: (usize, usize) = (24, 80);
+
+// An input that was read completely, with the byte offset of every line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    pub name: String,
+    lines: Vec<(usize, String)>,
+}
+
+impl Document {
+    pub fn new(name: &str, content: &[u8]) -> Document {
+        let mut lines = Vec::new();
+        let mut offset = 0;
+
+        for line in content.split_inclusive(|&byte| byte == b'\n') {
+            lines.push((offset, search::decode_line(line).into_owned()));
+            offset += line.len();
+        }
+
+        Document {
+            name: name.to_string(),
+            lines,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Key {
+    Char(char),
+    Backspace,
+    ClearPattern,
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Tab,
+    Enter,
+    Quit,
+    // Not a key, but the answer of the terminal to a size request: rows and columns.
+    Resize(usize, usize),
+}
+
+// Turns the bytes read from the terminal into keys. Unknown control characters and escape sequences are dropped.
+pub fn parse_keys(bytes: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut rest = bytes;
+
+    while let Some(&byte) = rest.first() {
+        let (key, length) = match byte {
+            0x1b => parse_escape(rest),
+            b'\r' | b'\n' => (Some(Key::Enter), 1),
+            b'\t' => (Some(Key::Tab), 1),
+            0x7f | 0x08 => (Some(Key::Backspace), 1),
+            0x03 => (Some(Key::Quit), 1),
+            0x15 => (Some(Key::ClearPattern), 1),
+            0x10 => (Some(Key::Up), 1),
+            0x0e => (Some(Key::Down), 1),
+            _ if byte < 0x20 => (None, 1),
+            _ => parse_char(rest),
+        };
+        keys.extend(key);
+        rest = &rest[length..];
+    }
+
+    keys
+}
+
+/*
+    Cursor keys send sequences like "ESC [ A" or "ESC O A", others like "ESC [ 5 ~".
+    An escape that is not followed by "[" or "O" is the Escape key itself.
+*/
+fn parse_escape(bytes: &[u8]) -> (Option<Key>, usize) {
+    if !matches!(bytes.get(1), Some(b'[' | b'O')) {
+        return (Some(Key::Quit), 1);
+    }
+
+    // Parameters are digits and semicolons, the sequence ends with the first other byte.
+    let end = match bytes[2..]
+        .iter()
+        .position(|byte| !byte.is_ascii_digit() && *byte != b';')
+    {
+        Some(position) => 2 + position,
+        None => return (None, bytes.len()),
+    };
+    let key = match &bytes[2..=end] {
+        [b'8', b';', size @ .., b't'] => parse_size(size),
+        b"A" => Some(Key::Up),
+        b"B" => Some(Key::Down),
+        b"H" | b"1~" | b"7~" => Some(Key::Home),
+        b"F" | b"4~" | b"8~" => Some(Key::End),
+        b"5~" => Some(Key::PageUp),
+        b"6~" => Some(Key::PageDown),
+        _ => None,
+    };
+
+    (key, end + 1)
+}
+
+// The reply to "ESC [ 18 t" is "ESC [ 8 ; <rows> ; <columns> t".
+fn parse_size(size: &[u8]) -> Option<Key> {
+    let size = std::str::from_utf8(size).ok()?;
+    let (rows, columns) = size.split_once(';')?;
+    match (rows.parse().ok()?, columns.parse().ok()?) {
+        (0, _) | (_, 0) => None,
+        (rows, columns) => Some(Key::Resize(rows, columns)),
+    }
+}
+
+// A character of UTF-8 input. Invalid bytes are skipped one at a time.
+fn parse_char(bytes: &[u8]) -> (Option<Key>, usize) {
+    let length = match bytes[0] {
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => 1,
+    };
+
+    match bytes
+        .get(..length)
+        .and_then(|bytes| std::str::from_utf8(bytes).ok())
+    {
+        Some(text) => (text.chars().next().map(Key::Char), length),
+        None => (None, 1),
+    }
+}
+
+// A line picked in the browser, to be printed like a search result.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selected {
+    pub name: String,
+    pub line: SearchLine,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    Continue,
+    // Quitting without a selection leaves it empty.
+    Quit(Vec<Selected>),
+}
+
+pub struct Browser<'a> {
+    arguments: &'a Arguments,
+    documents: Vec<Document>,
+    pattern: String,
+    matcher: Box<dyn Matcher + Sync>,
+    // The selected lines as (document, line index), in the order of the inputs.
+    hits: Vec<(usize, usize)>,
+    // Set while the pattern is not a valid regex. The hits of the last valid pattern stay until it is.
+    error: Option<String>,
+    cursor: usize,
+    // The first hit shown in the list.
+    scroll: usize,
+    marked: BTreeSet<(usize, usize)>,
+}
+
+impl<'a> Browser<'a> {
+    // Starts with the first pattern of the arguments. An empty pattern selects every line.
+    pub fn new(arguments: &'a Arguments, documents: Vec<Document>) -> Browser<'a> {
+        let mut browser = Browser {
+            arguments,
+            documents,
+            pattern: String::new(),
+            // Replaced right away, unless the first pattern is invalid.
+            matcher: Box::new(AnyOf::<Substring>::new(Vec::new())),
+            hits: Vec::new(),
+            error: None,
+            cursor: 0,
+            scroll: 0,
+            marked: BTreeSet::new(),
+        };
+        browser.set_pattern(arguments.patterns.first().cloned().unwrap_or_default());
+        browser
+    }
+
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    // The matcher of the last valid pattern, e.g. to highlight the selection once it is printed.
+    pub fn into_matcher(self) -> Box<dyn Matcher + Sync> {
+        self.matcher
+    }
+
+    fn set_pattern(&mut self, pattern: String) {
+        self.pattern = pattern;
+
+        let arguments = Arguments {
+            patterns: vec![self.pattern.clone()],
+            case_insensitive: self.arguments.case_insensitive,
+            regex: self.arguments.regex,
+            invert: self.arguments.invert,
+            word: self.arguments.word,
+            line: self.arguments.line,
+            fuzzy: self.arguments.fuzzy,
+            ..Arguments::default()
+        };
+        let matcher = match build_matcher(&arguments) {
+            Ok(matcher) => matcher,
+            Err(error) => {
+                self.error = Some(error.to_string());
+                return;
+            }
+        };
+
+        self.hits = self
+            .documents
+            .iter()
+            .enumerate()
+            .flat_map(|(document, Document { lines, .. })| {
+                let matcher = &matcher;
+                lines
+                    .iter()
+                    .enumerate()
+                    .filter(move |(_, (_, text))| matcher.is_match(text))
+                    .map(move |(index, _)| (document, index))
+            })
+            .collect();
+        self.matcher = matcher;
+        self.error = None;
+        self.cursor = 0;
+        self.scroll = 0;
+    }
+
+    // Page is the number of hits PageUp and PageDown move, usually the height of the list.
+    pub fn handle(&mut self, key: Key, page: usize) -> Action {
+        match key {
+            Key::Char(c) => {
+                let mut pattern = self.pattern.clone();
+                pattern.push(c);
+                self.set_pattern(pattern);
+            }
+            Key::Backspace => {
+                let mut pattern = self.pattern.clone();
+                if pattern.pop().is_some() {
+                    self.set_pattern(pattern);
+                }
+            }
+            Key::ClearPattern => self.set_pattern(String::new()),
+            Key::Up => self.move_to(self.cursor.saturating_sub(1)),
+            Key::Down => self.move_to(self.cursor + 1),
+            Key::PageUp => self.move_to(self.cursor.saturating_sub(page)),
+            Key::PageDown => self.move_to(self.cursor + page),
+            Key::Home => self.move_to(0),
+            Key::End => self.move_to(usize::MAX),
+            Key::Tab => {
+                if let Some(&hit) = self.hits.get(self.cursor) {
+                    if !self.marked.remove(&hit) {
+                        self.marked.insert(hit);
+                    }
+                    self.move_to(self.cursor + 1);
+                }
+            }
+            Key::Enter => {
+                let chosen: Vec<(usize, usize)> = match self.marked.is_empty() {
+                    true => self.hits.get(self.cursor).copied().into_iter().collect(),
+                    false => self.marked.iter().copied().collect(),
+                };
+                return Action::Quit(chosen.into_iter().map(|hit| self.selected(hit)).collect());
+            }
+            Key::Quit => return Action::Quit(Vec::new()),
+            // The caller keeps track of the size, it only changes what fits on a page.
+            Key::Resize(..) => {}
+        }
+
+        // The list follows the cursor, so it is always visible.
+        let page = page.max(1);
+        self.scroll = self
+            .scroll
+            .min(self.cursor)
+            .max((self.cursor + 1).saturating_sub(page));
+        Action::Continue
+    }
+
+    fn move_to(&mut self, cursor: usize) {
+        self.cursor = cursor.min(self.hits.len().saturating_sub(1));
+    }
+
+    fn selected(&self, (document, index): (usize, usize)) -> Selected {
+        let Document { name, lines } = &self.documents[document];
+        let (offset, text) = &lines[index];
+
+        Selected {
+            name: name.clone(),
+            line: SearchLine {
+                index,
+                offset: *offset,
+                text: text.clone(),
+                bytes: None,
+                is_match: true,
+                after_gap: false,
+            },
+        }
+    }
+
+    // The number of rows the list gets on a terminal of the given height.
+    pub fn page(height: usize) -> usize {
+        Browser::layout(height).0
+    }
+
+    // The rows of the list and of the preview. The first row always holds the pattern.
+    fn layout(height: usize) -> (usize, usize) {
+        let rows = height.saturating_sub(1);
+        // The preview needs a separator line and the current line with its context.
+        let preview = 2 * PREVIEW_CONTEXT + 2;
+        match rows >= preview + MIN_LIST_ROWS {
+            true => (rows - preview, preview),
+            false => (rows, 0),
+        }
+    }
+
+    /*
+        Draws the whole screen, starting at the top left corner.
+        Every row is cleared before it is drawn, so nothing of the previous screen remains.
+        The cursor is hidden while drawing and ends up behind the pattern.
+    */
+    pub fn render(&self, width: usize, height: usize) -> String {
+        let (list_rows, preview_rows) = Browser::layout(height);
+        let mut rows = Vec::with_capacity(height);
+
+        let status = match &self.error {
+            Some(error) => error.clone(),
+            None => format!("{} lines, {} marked", self.hits.len(), self.marked.len()),
+        };
+        rows.push(fit(
+            &format!("> {}  [{status}]", sanitize(&self.pattern)),
+            width,
+        ));
+
+        for row in 0..list_rows {
+            let position = self.scroll + row;
+            rows.push(match self.hits.get(position) {
+                Some(&hit) => self.render_hit(hit, width, position == self.cursor),
+                None => String::new(),
+            });
+        }
+        if preview_rows > 0 {
+            rows.extend(self.render_preview(width));
+        }
+
+        let mut screen = String::from("\x1b[?25l");
+        for (row, text) in rows.iter().enumerate() {
+            screen.push_str(&format!("\x1b[{};1H\x1b[2K{text}", row + 1));
+        }
+        let column = (self.pattern.chars().count() + 3).min(width.max(1));
+        screen.push_str(&format!("\x1b[1;{column}H\x1b[?25h"));
+
+        screen
+    }
+
+    // The current hit is shown in reverse video, the others with their matches highlighted.
+    fn render_hit(&self, hit: (usize, usize), width: usize, is_current: bool) -> String {
+        let (document, index) = hit;
+        let Document { name, lines } = &self.documents[document];
+
+        let mark = if self.marked.contains(&hit) { '*' } else { ' ' };
+        let prefix = match self.documents.len() > 1 {
+            true => format!("{mark}{}:{}:", sanitize(name), index + 1),
+            false => format!("{mark}{}:", index + 1),
+        };
+        let text = fit(&format!("{prefix}{}", sanitize(&lines[index].1)), width);
+
+        if is_current {
+            return format!("\x1b[7m{text}\x1b[m");
+        }
+        // Only the line itself is highlighted, and only the matches that are still visible.
+        let (prefix, line) = text.split_at(prefix.len().min(text.len()));
+        let ranges = self.matcher.find_matches(line);
+        format!("{prefix}{}", color::highlight(line, &ranges, "01;31", ""))
+    }
+
+    fn render_preview(&self, width: usize) -> Vec<String> {
+        let (document, index) = match self.hits.get(self.cursor) {
+            Some(&hit) => hit,
+            None => return vec![fit(&"─".repeat(width), width)],
+        };
+        let Document { name, lines } = &self.documents[document];
+
+        let title = format!("── {}:{} ", sanitize(name), index + 1);
+        let mut rows = vec![fit(&(title + &"─".repeat(width)), width)];
+        for number in index.saturating_sub(PREVIEW_CONTEXT)..index + PREVIEW_CONTEXT + 1 {
+            let marker = if number == index { '>' } else { ' ' };
+            rows.push(match lines.get(number) {
+                Some((_, text)) => fit(
+                    &format!("{marker}{:>6} {}", number + 1, sanitize(text)),
+                    width,
+                ),
+                None => String::new(),
+            });
+        }
+        // Near the start of an input, there are fewer lines before the current one.
+        rows.resize(2 * PREVIEW_CONTEXT + 2, String::new());
+
+        rows
+    }
+}
+
+// Control characters like tabs or escapes would move the cursor, so they are shown as spaces.
+fn sanitize(text: &str) -> String {
+    text.chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect()
+}
+
+// Cuts the text to the given number of characters, so it does not wrap into the next row.
+fn fit(text: &str, width: usize) -> String {
+    text.chars().take(width).collect()
+}
+
+/*
+    Lets the user browse the lines of the documents until they quit.
+    Returns the selection and the matcher of the final pattern.
+*/
+pub fn browse(
+    arguments: &Arguments,
+    documents: Vec<Document>,
+) -> io::Result<(Vec<Selected>, Box<dyn Matcher + Sync>)> {
+    let mut browser = Browser::new(arguments, documents);
+    let mut tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+
+    let raw_mode = RawMode::enable()?;
+    // The alternate screen keeps whatever the terminal showed before, it comes back when we leave.
+    tty.write_all(b"\x1b[?1049h")?;
+    let selection = interact(&mut browser, &mut tty);
+    let restored = tty.write_all(b"\x1b[?25h\x1b[?1049l");
+    drop(raw_mode);
+
+    restored?;
+    Ok((selection?, browser.into_matcher()))
+}
+
+/*
+    The size is asked from stty only once. After every redraw, the terminal is asked for its size with "ESC [ 18 t",
+    and its reply arrives between the keys. So a resize is noticed with the next key, without starting a process.
+    Terminals that do not answer just keep the size from the start.
+*/
+fn interact(browser: &mut Browser, tty: &mut File) -> io::Result<Vec<Selected>> {
+    let mut buffer = [0; 256];
+    let (mut height, mut width) = terminal_size();
+    let mut redraw = true;
+
+    loop {
+        if redraw {
+            tty.write_all(browser.render(width, height).as_bytes())?;
+            tty.write_all(b"\x1b[18t")?;
+            tty.flush()?;
+        }
+
+        let read = match tty.read(&mut buffer) {
+            Ok(0) => return Ok(Vec::new()),
+            Ok(read) => read,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        };
+        // A reply with the size we already know must not redraw, or every redraw would ask again.
+        redraw = false;
+        for key in parse_keys(&buffer[..read]) {
+            if let Key::Resize(rows, columns) = key {
+                redraw |= (rows, columns) != (height, width);
+                (height, width) = (rows, columns);
+                continue;
+            }
+            redraw = true;
+            if let Action::Quit(selection) = browser.handle(key, Browser::page(height)) {
+                return Ok(selection);
+            }
+        }
+    }
+}
+
+// Puts the terminal into raw mode until it is dropped, which restores the previous settings.
+struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    fn enable() -> io::Result<RawMode> {
+        let saved = stty(&["-g"])?.trim().to_string();
+        stty(&["raw", "-echo"])?;
+        Ok(RawMode { saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = stty(&[&self.saved]);
+    }
+}
+
+// stty works on the terminal it gets as standard input, which is /dev/tty even if our own input is redirected.
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(File::open("/dev/tty")?)
+        .stderr(Stdio::piped())
+        .output()?;
+
+    match output.status.success() {
+        true => Ok(String::from_utf8_lossy(&output.stdout).into_owned()),
+        false => Err(io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        )),
+    }
+}
+
+// The rows and columns of the terminal.
+fn terminal_size() -> (usize, usize) {
+    let size = stty(&["size"]).unwrap_or_default();
+    let mut numbers = size.split_whitespace().map(|number| number.parse().ok());
+
+    match (numbers.next().flatten(), numbers.next().flatten()) {
+        (Some(rows), Some(columns)) if rows > 0 && columns > 0 => (rows, columns),
+        _ => DEFAULT_SIZE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_keys, Action, Browser, Document, Key};
+    use crate::Arguments;
+
+    fn documents() -> Vec<Document> {
+        vec![
+            Document::new("a.txt", b"alpha\nbeta\r\ngamma\n"),
+            Document::new("b.txt", b"Alpine\nbetter\tlate"),
+        ]
+    }
+
+    fn arguments(pattern: &str) -> Arguments {
+        Arguments {
+            patterns: vec![pattern.to_string()],
+            ..Arguments::default()
+        }
+    }
+
+    fn names(action: Action) -> Vec<(String, usize, String)> {
+        match action {
+            Action::Quit(selection) => selection
+                .into_iter()
+                .map(|selected| (selected.name, selected.line.index, selected.line.text))
+                .collect(),
+            Action::Continue => panic!("the browser did not quit"),
+        }
+    }
+
+    #[test]
+    fn test_parse_keys() {
+        assert_eq!(
+            parse_keys("aé\x7f\r\t".as_bytes()),
+            vec![
+                Key::Char('a'),
+                Key::Char('é'),
+                Key::Backspace,
+                Key::Enter,
+                Key::Tab
+            ]
+        );
+        assert_eq!(
+            parse_keys(b"\x1b[A\x1bOB\x1b[5~\x1b[6~\x1b[H\x1b[4~"),
+            vec![
+                Key::Up,
+                Key::Down,
+                Key::PageUp,
+                Key::PageDown,
+                Key::Home,
+                Key::End
+            ]
+        );
+        assert_eq!(parse_keys(b"\x1b"), vec![Key::Quit]);
+        assert_eq!(parse_keys(b"\x03\x15"), vec![Key::Quit, Key::ClearPattern]);
+        // Unknown sequences, other control characters and invalid UTF-8 are dropped.
+        assert_eq!(parse_keys(b"\x1b[1;5C\x01\xffx\x1b["), vec![Key::Char('x')]);
+        // The reply to a size request.
+        assert_eq!(
+            parse_keys(b"\x1b[8;40;120tq\x1b[8;0;5t\x1b[8;1t"),
+            vec![Key::Resize(40, 120), Key::Char('q')]
+        );
+    }
+
+    #[test]
+    fn test_documents_keep_offsets() {
+        let document = Document::new("a.txt", b"alpha\nbeta\r\ngamma\n");
+        assert_eq!(
+            document.lines,
+            vec![
+                (0, "alpha".to_string()),
+                (6, "beta".to_string()),
+                (12, "gamma".to_string())
+            ]
+        );
+        assert!(Document::new("empty", b"").lines.is_empty());
+    }
+
+    #[test]
+    fn test_typing_refilters() {
+        let arguments = Arguments {
+            case_insensitive: true,
+            ..arguments("al")
+        };
+        let mut browser = Browser::new(&arguments, documents());
+        assert_eq!(browser.hits, vec![(0, 0), (1, 0)]);
+
+        browser.handle(Key::Char('p'), 10);
+        browser.handle(Key::Char('i'), 10);
+        assert_eq!(browser.pattern(), "alpi");
+        assert_eq!(browser.hits, vec![(1, 0)]);
+
+        browser.handle(Key::Backspace, 10);
+        browser.handle(Key::Backspace, 10);
+        assert_eq!(browser.hits.len(), 2);
+
+        // An empty pattern selects every line.
+        browser.handle(Key::ClearPattern, 10);
+        assert_eq!(browser.hits.len(), 5);
+    }
+
+    #[test]
+    fn test_invalid_regex_keeps_the_last_hits() {
+        let arguments = Arguments {
+            regex: true,
+            ..arguments("be")
+        };
+        let mut browser = Browser::new(&arguments, documents());
+        browser.handle(Key::Char('('), 10);
+        assert!(browser.error.is_some());
+        assert_eq!(browser.hits, vec![(0, 1), (1, 1)]);
+
+        browser.handle(Key::Char('t'), 10);
+        browser.handle(Key::Char('t'), 10);
+        browser.handle(Key::Char(')'), 10);
+        assert!(browser.error.is_none());
+        assert_eq!(browser.hits, vec![(1, 1)]);
+    }
+
+    #[test]
+    fn test_navigation_and_selection() {
+        let arguments = arguments("");
+        let mut browser = Browser::new(&arguments, documents());
+
+        browser.handle(Key::Up, 2);
+        assert_eq!(browser.cursor, 0);
+        browser.handle(Key::PageDown, 2);
+        assert_eq!((browser.cursor, browser.scroll), (2, 1));
+        browser.handle(Key::End, 2);
+        assert_eq!((browser.cursor, browser.scroll), (4, 3));
+        browser.handle(Key::Home, 2);
+        assert_eq!((browser.cursor, browser.scroll), (0, 0));
+
+        // Without marks, Enter picks the current line.
+        browser.handle(Key::Down, 2);
+        assert_eq!(
+            names(browser.handle(Key::Enter, 2)),
+            vec![("a.txt".to_string(), 1, "beta".to_string())]
+        );
+
+        // Marked lines are printed in the order of the inputs, no matter in which order they were marked.
+        // Tab on a marked line unmarks it.
+        browser.handle(Key::End, 2);
+        browser.handle(Key::Tab, 2);
+        browser.handle(Key::Home, 2);
+        browser.handle(Key::Tab, 2);
+        browser.handle(Key::Tab, 2);
+        browser.handle(Key::Up, 2);
+        browser.handle(Key::Tab, 2);
+        let selection = names(browser.handle(Key::Enter, 2));
+        let lines: Vec<(&str, usize)> = selection
+            .iter()
+            .map(|(name, index, _)| (name.as_str(), *index))
+            .collect();
+        assert_eq!(lines, vec![("a.txt", 0), ("b.txt", 1)]);
+
+        assert_eq!(names(browser.handle(Key::Quit, 2)), vec![]);
+    }
+
+    #[test]
+    fn test_enter_without_hits() {
+        let arguments = arguments("absent");
+        let mut browser = Browser::new(&arguments, documents());
+        browser.handle(Key::Down, 5);
+        browser.handle(Key::Tab, 5);
+        assert_eq!(names(browser.handle(Key::Enter, 5)), vec![]);
+    }
+
+    #[test]
+    fn test_render() {
+        let arguments = arguments("be");
+        let mut browser = Browser::new(&arguments, documents());
+        browser.handle(Key::Down, 10);
+
+        let screen = browser.render(30, 14);
+        assert!(screen.starts_with("\x1b[?25l\x1b[1;1H\x1b[2K> be  [2 lines, 0 marked]"));
+        // The other hit has its match highlighted, the current one is reversed and the tab is a space.
+        assert!(screen.contains("\x1b[2;1H\x1b[2K a.txt:2:\x1b[01;31m\x1b[Kbe\x1b[m\x1b[Kta"));
+        assert!(screen.contains("\x1b[3;1H\x1b[2K\x1b[7m b.txt:2:better late\x1b[m"));
+        // The preview of the current line starts below the list, with a title cut to the width.
+        assert!(screen.contains("\x1b[7;1H\x1b[2K── b.txt:2 ───────────────────"));
+        assert!(screen.contains("\x1b[8;1H\x1b[2K      1 Alpine"));
+        assert!(screen.contains("\x1b[9;1H\x1b[2K>     2 better late"));
+        assert!(screen.ends_with("\x1b[1;5H\x1b[?25h"));
+
+        // Terminals too small for a preview only get the list.
+        let screen = browser.render(12, 4);
+        assert!(!screen.contains("──"));
+        assert!(screen.contains("\x1b[1;1H\x1b[2K> be  [2 lin\x1b[2;1H"));
+    }
+}
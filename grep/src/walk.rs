@@ -0,0 +1,184 @@
+/*
+    Collects all files below a directory, so run() can search a whole source tree.
+    Entries matching one of the ignore globs are skipped, and so is everything inside ignored directories.
+    The rules of -g, --type and --type-not then decide which of the remaining files are searched.
+    Symbolic links to directories are not followed, which protects us from endless loops.
+*/
+
+use crate::{
+    glob::{Glob, Rules},
+    GrepError,
+};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+// The same heuristic GNU grep uses: a NUL byte near the start means the file is not text.
+const BINARY_CHECK_LENGTH: usize = 8 * 1024;
+
+pub fn walk(root: &Path, ignore: &[Glob], rules: &Rules) -> Result<Vec<PathBuf>, GrepError> {
+    let reading = |error| GrepError::reading(&format!("directory {}", root.display()), error);
+    if !root.is_dir() {
+        return Err(reading(io::ErrorKind::NotADirectory.into()));
+    }
+
+    let mut files = Vec::new();
+    let mut directories = vec![root.to_path_buf()];
+
+    while let Some(directory) = directories.pop() {
+        let entries = match fs::read_dir(&directory) {
+            Ok(entries) => entries,
+            // An unreadable subdirectory should not stop the whole search.
+            Err(error) if directory != root => {
+                eprintln!("Skipping {}: {error}", directory.display());
+                continue;
+            }
+            Err(error) => return Err(reading(error)),
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let file_type = match entry.file_type() {
+                Ok(file_type) => file_type,
+                Err(_) => continue,
+            };
+            let is_dir = file_type.is_dir();
+
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            if ignore.iter().any(|glob| glob.matches(relative, is_dir))
+                || !rules.allows(relative, is_dir)
+            {
+                continue;
+            }
+
+            if is_dir {
+                directories.push(path);
+            } else if file_type.is_file() || (file_type.is_symlink() && path.is_file()) {
+                files.push(path);
+            }
+        }
+    }
+
+    // read_dir does not guarantee any order, but the output should be the same on every run.
+    files.sort();
+    Ok(files)
+}
+
+pub fn is_binary(content: &[u8]) -> bool {
+    content[..content.len().min(BINARY_CHECK_LENGTH)].contains(&0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_binary, walk};
+    use crate::glob::{Glob, Rules};
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    fn create_tree(name: &str, files: &[&str]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("grep_walk_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+
+        for file in files {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "content").unwrap();
+        }
+
+        root
+    }
+
+    fn relative(root: &Path, files: Vec<PathBuf>) -> Vec<String> {
+        files
+            .iter()
+            .map(|file| {
+                file.strip_prefix(root)
+                    .unwrap()
+                    .to_string_lossy()
+                    .replace('\\', "/")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_walk_is_recursive_and_sorted() {
+        let root = create_tree("recursive", &["b.txt", "a/c.txt", "a/b/d.txt"]);
+
+        let files = walk(&root, &[], &Rules::default()).unwrap();
+        assert_eq!(
+            relative(&root, files),
+            vec!["a/b/d.txt", "a/c.txt", "b.txt"]
+        );
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_walk_skips_ignored_entries() {
+        let root = create_tree(
+            "ignore",
+            &[
+                "src/lib.rs",
+                "src/app.log",
+                "target/debug/out.rs",
+                "Cargo.lock",
+            ],
+        );
+
+        let ignore = Glob::parse_list("target/,*.log,Cargo.lock");
+        let files = walk(&root, &ignore, &Rules::default()).unwrap();
+        assert_eq!(relative(&root, files), vec!["src/lib.rs"]);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_walk_rejects_files() {
+        let root = create_tree("file", &["only.txt"]);
+
+        assert!(walk(&root.join("only.txt"), &[], &Rules::default()).is_err());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_walk_applies_rules() {
+        let root = create_tree(
+            "rules",
+            &[
+                "Cargo.toml",
+                "README.md",
+                "src/lib.rs",
+                "src/notes.md",
+                "vendor/dep/lib.rs",
+            ],
+        );
+
+        let mut rules = Rules::default();
+        rules.add("*.rs");
+        rules.add("*.toml");
+        rules.add("!vendor/**");
+        let files = walk(&root, &[], &rules).unwrap();
+        assert_eq!(relative(&root, files), vec!["Cargo.toml", "src/lib.rs"]);
+
+        let mut rules = Rules::default();
+        rules.exclude(Glob::new("*.md"));
+        let files = walk(&root, &[], &rules).unwrap();
+        assert_eq!(
+            relative(&root, files),
+            vec!["Cargo.toml", "src/lib.rs", "vendor/dep/lib.rs"]
+        );
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_is_binary() {
+        assert!(!is_binary(b"plain text\nwith lines\n"));
+        assert!(is_binary(b"\x7fELF\x00\x01"));
+        assert!(!is_binary(b""));
+    }
+}
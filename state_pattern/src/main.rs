@@ -37,6 +37,7 @@
     Then, we'll also do a more natural approach.
 */
 
+mod state_machine;
 mod state_natural;
 mod state_traditional;
 
@@ -48,4 +49,9 @@ fn main() {
 
     println!("=== Natural Approach ===");
     state_natural::run();
+
+    println!();
+
+    println!("=== Generated Approach ===");
+    state_machine::run();
 }
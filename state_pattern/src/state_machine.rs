@@ -0,0 +1,519 @@
+/*
+    Both approaches need a lot of code that looks the same for every state.
+    Adding a state to the traditional approach means a new struct, a full impl State block,
+    and changes to the states that should lead to it. The natural approach needs a struct and its methods.
+
+    The state_machine! macro writes that code for us, from a single table of states and their transitions:
+
+        state_machine! {
+            machine post;
+            data: Article;
+            initial: Draft;
+            events: { request_review, approve, reject }
+            states: {
+                Draft { request_review if has_content => PendingReview }
+                PendingReview { approve => Published, reject => Draft }
+                Published {}
+            }
+        }
+
+    This generates a module "post" with two submodules:
+     - post::boxed, the traditional approach: a State trait with one method per event, a struct per state,
+       and a Machine that holds a Box<dyn State> and has a method per event returning Result<(), TransitionError>.
+     - post::typestate, the natural approach: a struct per state, with a method per transition that consumes it.
+       A transition with a guard returns Result<Target, Self>, as it can only be taken when the guard allows it.
+
+    The data (Article above) is shared by all states, and a guard is a function or closure taking &Article.
+    Unlike in the handwritten approaches, states can not hold data of their own, they are plain unit structs.
+
+    The macro checks the table while compiling:
+     - an event can only lead to one target from each state, so every method has a single return type,
+     - every state can be reached from the initial state, as an unreachable state is most likely a mistake.
+    Mistyped states and events are caught by the compiler anyway, as they name types and methods that do not exist.
+*/
+
+use std::{error::Error, fmt};
+
+#[derive(Debug, PartialEq)]
+pub enum TransitionError {
+    NotAllowed {
+        state: &'static str,
+        event: &'static str,
+    },
+    // The state has a transition for the event, but its guard did not allow it.
+    Guarded {
+        state: &'static str,
+        event: &'static str,
+    },
+}
+
+impl fmt::Display for TransitionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransitionError::NotAllowed { state, event } => {
+                write!(f, "{state} has no transition for {event}")
+            }
+            TransitionError::Guarded { state, event } => {
+                write!(
+                    f,
+                    "The guard of {event} in {state} does not allow the transition"
+                )
+            }
+        }
+    }
+}
+
+impl Error for TransitionError {}
+
+/*
+    The rows of the table, as the macro hands them to check().
+    A panic while evaluating a constant can only print a single &str, so the macro puts the error messages together
+    with concat! and passes them along.
+*/
+pub struct StateRow {
+    pub name: &'static str,
+    pub unreachable: &'static str,
+}
+
+pub struct TransitionRow {
+    pub from: &'static str,
+    pub event: &'static str,
+    pub to: &'static str,
+    pub duplicate: &'static str,
+}
+
+// Enough for any table someone would write by hand.
+const MAX_STATES: usize = 64;
+
+// Called by the macro in a constant, so a panic here is a compile error.
+pub const fn check(initial: &str, states: &[StateRow], transitions: &[TransitionRow]) {
+    assert!(
+        states.len() <= MAX_STATES,
+        "state_machine! supports at most 64 states"
+    );
+
+    let mut i = 0;
+    while i < transitions.len() {
+        let mut j = i + 1;
+        while j < transitions.len() {
+            if same(transitions[i].from, transitions[j].from)
+                && same(transitions[i].event, transitions[j].event)
+            {
+                panic!("{}", transitions[j].duplicate);
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+
+    // Marks the states reachable from the initial one, until a pass over all transitions finds no new state.
+    let mut reached = [false; MAX_STATES];
+    reached[index(states, initial)] = true;
+    let mut changed = true;
+    while changed {
+        changed = false;
+        let mut t = 0;
+        while t < transitions.len() {
+            let from = index(states, transitions[t].from);
+            let to = index(states, transitions[t].to);
+            if reached[from] && !reached[to] {
+                reached[to] = true;
+                changed = true;
+            }
+            t += 1;
+        }
+    }
+
+    let mut s = 0;
+    while s < states.len() {
+        if !reached[s] {
+            panic!("{}", states[s].unreachable);
+        }
+        s += 1;
+    }
+}
+
+// Names the compiler already knows to be states, so they are always found.
+const fn index(states: &[StateRow], name: &str) -> usize {
+    let mut i = 0;
+    while !same(states[i].name, name) {
+        i += 1;
+    }
+    i
+}
+
+// == on strings is not available in constants.
+const fn same(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+macro_rules! state_machine {
+    // The rules starting with @ are only used by the macro itself, for the parts that differ with and without a guard.
+    (@boxed $event:ident => $target:ident) => {
+        fn $event(&self, _data: &Data) -> Outcome {
+            Ok(Box::new($target))
+        }
+    };
+    (@boxed $event:ident if $guard:expr => $target:ident) => {
+        fn $event(&self, data: &Data) -> Outcome {
+            match ($guard)(data) {
+                true => Ok(Box::new($target)),
+                false => Err(TransitionError::Guarded {
+                    state: self.name(),
+                    event: stringify!($event),
+                }),
+            }
+        }
+    };
+    (@typestate $event:ident => $target:ident) => {
+        pub fn $event(self) -> $target {
+            $target { data: self.data }
+        }
+    };
+    (@typestate $event:ident if $guard:expr => $target:ident) => {
+        pub fn $event(self) -> Result<$target, Self> {
+            match ($guard)(&self.data) {
+                true => Ok($target { data: self.data }),
+                false => Err(self),
+            }
+        }
+    };
+    (
+        machine $machine:ident;
+        data: $data:ty;
+        initial: $initial:ident;
+        events: { $($event:ident),* $(,)? }
+        states: {
+            $(
+                $state:ident {
+                    $( $from_event:ident $(if $guard:expr)? => $target:ident ),* $(,)?
+                }
+            )*
+        }
+    ) => {
+        // Not every program uses every generated method, e.g. both forms of the machine.
+        #[allow(dead_code)]
+        pub mod $machine {
+            use super::*;
+
+            type Data = $data;
+
+            const _: () = $crate::state_machine::check(
+                stringify!($initial),
+                &[$(
+                    $crate::state_machine::StateRow {
+                        name: stringify!($state),
+                        unreachable: concat!(
+                            "state ", stringify!($state),
+                            " can not be reached from the initial state ", stringify!($initial)
+                        ),
+                    },
+                )*],
+                &[$($(
+                    $crate::state_machine::TransitionRow {
+                        from: stringify!($state),
+                        event: stringify!($from_event),
+                        to: stringify!($target),
+                        duplicate: concat!(
+                            "state ", stringify!($state), " has more than one transition for ",
+                            stringify!($from_event)
+                        ),
+                    },
+                )*)*],
+            );
+
+            pub mod boxed {
+                use super::*;
+                pub use $crate::state_machine::TransitionError;
+
+                type Outcome = Result<Box<dyn State>, TransitionError>;
+
+                // The states hold no data, so the events can borrow them instead of taking the Box.
+                pub trait State {
+                    fn name(&self) -> &'static str;
+
+                    $(
+                        fn $event(&self, _data: &Data) -> Outcome {
+                            Err(TransitionError::NotAllowed {
+                                state: self.name(),
+                                event: stringify!($event),
+                            })
+                        }
+                    )*
+                }
+
+                $(
+                    pub struct $state;
+
+                    impl State for $state {
+                        fn name(&self) -> &'static str {
+                            stringify!($state)
+                        }
+
+                        $( state_machine!(@boxed $from_event $(if $guard)? => $target); )*
+                    }
+                )*
+
+                pub struct Machine {
+                    state: Box<dyn State>,
+                    data: Data,
+                }
+
+                impl Machine {
+                    pub fn new(data: Data) -> Machine {
+                        Machine {
+                            state: Box::new($initial),
+                            data,
+                        }
+                    }
+
+                    pub fn state_name(&self) -> &'static str {
+                        self.state.name()
+                    }
+
+                    pub fn data(&self) -> &Data {
+                        &self.data
+                    }
+
+                    pub fn data_mut(&mut self) -> &mut Data {
+                        &mut self.data
+                    }
+
+                    $(
+                        pub fn $event(&mut self) -> Result<(), TransitionError> {
+                            self.state = self.state.$event(&self.data)?;
+                            Ok(())
+                        }
+                    )*
+                }
+            }
+
+            pub mod typestate {
+                use super::*;
+
+                pub fn new(data: Data) -> $initial {
+                    $initial { data }
+                }
+
+                $(
+                    pub struct $state {
+                        data: Data,
+                    }
+
+                    impl $state {
+                        pub fn data(&self) -> &Data {
+                            &self.data
+                        }
+
+                        pub fn data_mut(&mut self) -> &mut Data {
+                            &mut self.data
+                        }
+
+                        pub fn into_data(self) -> Data {
+                            self.data
+                        }
+
+                        $( state_machine!(@typestate $from_event $(if $guard)? => $target); )*
+                    }
+                )*
+            }
+        }
+    };
+}
+
+/*
+    The blog post from the other approaches once more, this time described by a table.
+    A post can only be sent to review with some content, and the data is just the text.
+*/
+pub struct Article {
+    pub content: String,
+}
+
+fn has_content(article: &Article) -> bool {
+    !article.content.is_empty()
+}
+
+state_machine! {
+    machine post;
+    data: Article;
+    initial: Draft;
+    events: { request_review, approve, reject }
+    states: {
+        Draft { request_review if has_content => PendingReview }
+        PendingReview { approve => Published, reject => Draft }
+        Published {}
+    }
+}
+
+pub fn run() {
+    println!("Creating a new post from the boxed machine");
+    let mut machine = post::boxed::Machine::new(Article {
+        content: String::new(),
+    });
+
+    println!("Requesting a review without content");
+    if let Err(error) = machine.request_review() {
+        println!("Error: {error}");
+    }
+
+    println!("Adding text and requesting a review");
+    machine
+        .data_mut()
+        .content
+        .push_str("I ate a salad for lunch today.");
+    let report = |event: &str, result: Result<(), TransitionError>, state: &str| match result {
+        Ok(()) => println!("{event}: now {state}"),
+        Err(error) => println!("{event}: {error}"),
+    };
+    let result = machine.request_review();
+    report("request_review", result, machine.state_name());
+    let result = machine.reject();
+    report("reject", result, machine.state_name());
+    let result = machine.request_review();
+    report("request_review", result, machine.state_name());
+    let result = machine.approve();
+    report("approve", result, machine.state_name());
+    let result = machine.approve();
+    report("approve", result, machine.state_name());
+
+    println!("Creating a new post from the typestate machine");
+    let post = post::typestate::new(Article {
+        content: String::new(),
+    });
+
+    // The guard hands the draft back when it does not allow the transition.
+    let mut post = match post.request_review() {
+        Ok(_) => unreachable!("a post without content can not be reviewed"),
+        Err(draft) => draft,
+    };
+    post.data_mut()
+        .content
+        .push_str("I ate a salad for lunch today.");
+    let post = match post.request_review() {
+        Ok(pending) => pending.approve(),
+        Err(_) => unreachable!("a post with content can be reviewed"),
+    };
+    println!("Post content: {}", post.data().content);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{post, Article, TransitionError};
+
+    fn article(content: &str) -> Article {
+        Article {
+            content: content.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_boxed_machine() {
+        let mut machine = post::boxed::Machine::new(article(""));
+        assert_eq!(machine.state_name(), "Draft");
+        assert_eq!(
+            machine.request_review(),
+            Err(TransitionError::Guarded {
+                state: "Draft",
+                event: "request_review"
+            })
+        );
+        assert_eq!(
+            machine.approve(),
+            Err(TransitionError::NotAllowed {
+                state: "Draft",
+                event: "approve"
+            })
+        );
+
+        machine.data_mut().content.push_str("Salad");
+        machine.request_review().unwrap();
+        machine.reject().unwrap();
+        assert_eq!(machine.state_name(), "Draft");
+        machine.request_review().unwrap();
+        machine.approve().unwrap();
+        assert_eq!(machine.state_name(), "Published");
+        assert!(machine.reject().is_err());
+        assert_eq!(machine.data().content, "Salad");
+    }
+
+    #[test]
+    fn test_typestate_machine() {
+        let draft = match post::typestate::new(article("")).request_review() {
+            Err(draft) => draft,
+            Ok(_) => panic!("the guard should refuse an empty post"),
+        };
+        assert!(draft.data().content.is_empty());
+
+        let pending = match post::typestate::new(article("Salad")).request_review() {
+            Ok(pending) => pending,
+            Err(_) => panic!("the guard should allow a post with content"),
+        };
+        let draft = pending.reject();
+        let published = draft.request_review().ok().unwrap().approve();
+        assert_eq!(published.into_data().content, "Salad");
+    }
+
+    // Tables the macro has to refuse, which would not compile, are checked by calling check() directly.
+    #[test]
+    #[should_panic(expected = "state Archived can not be reached from the initial state Draft")]
+    fn test_unreachable_states_are_refused() {
+        use super::{check, StateRow, TransitionRow};
+
+        let state = |name, unreachable| StateRow { name, unreachable };
+        check(
+            "Draft",
+            &[
+                state("Draft", ""),
+                state("Published", ""),
+                state(
+                    "Archived",
+                    "state Archived can not be reached from the initial state Draft",
+                ),
+            ],
+            &[TransitionRow {
+                from: "Draft",
+                event: "approve",
+                to: "Published",
+                duplicate: "",
+            }],
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "state Draft has more than one transition for approve")]
+    fn test_duplicate_transitions_are_refused() {
+        use super::{check, StateRow, TransitionRow};
+
+        let transition = |to| TransitionRow {
+            from: "Draft",
+            event: "approve",
+            to,
+            duplicate: "state Draft has more than one transition for approve",
+        };
+        check(
+            "Draft",
+            &[
+                StateRow {
+                    name: "Draft",
+                    unreachable: "",
+                },
+                StateRow {
+                    name: "Published",
+                    unreachable: "",
+                },
+            ],
+            &[transition("Published"), transition("Draft")],
+        );
+    }
+}
@@ -4,22 +4,37 @@
     We can also only transition to a next state that is available for the current state.
 */
 
+use std::collections::HashSet;
+
 pub struct Post {}
 
 impl Post {
+    // Post is only the entry point, the post itself is a DraftPost, so new() does not return Self on purpose.
+    #[allow(clippy::new_ret_no_self)]
     pub fn new() -> DraftPost {
         DraftPost::new()
     }
+
+    // At least one approval is always required, a post can not publish itself.
+    pub fn with_required_approvals(required_approvals: usize) -> DraftPost {
+        DraftPost {
+            required_approvals: required_approvals.max(1),
+            ..Post::new()
+        }
+    }
 }
 
 pub struct DraftPost {
     content: String,
+    required_approvals: usize,
 }
 
 impl DraftPost {
+    // A new draft needs a single approval before it gets published.
     pub fn new() -> DraftPost {
         DraftPost {
             content: String::new(),
+            required_approvals: 1,
         }
     }
 
@@ -30,20 +45,45 @@ impl DraftPost {
     pub fn request_review(self) -> PendingReviewPost {
         PendingReviewPost {
             content: self.content,
+            approvals: HashSet::new(),
+            required_approvals: self.required_approvals,
         }
     }
 }
 
 pub struct PendingReviewPost {
     content: String,
+    approvals: HashSet<String>,
+    required_approvals: usize,
+}
+
+/*
+    Whether an approval publishes the post is only known at runtime, so approve() can not return a single type.
+    Instead, it returns every possible outcome, and the compiler makes the caller handle each of them.
+*/
+pub enum Approval {
+    Pending(PendingReviewPost),
+    Duplicate(PendingReviewPost), // The reviewer had already approved, nothing changed
+    Published(PublishedPost),
 }
 
 impl PendingReviewPost {
-    pub fn approve(self) -> PublishedPost {
-        PublishedPost {
-            content: self.content,
+    pub fn approve(mut self, reviewer: &str) -> Approval {
+        if !self.approvals.insert(reviewer.to_string()) {
+            return Approval::Duplicate(self);
+        }
+
+        match self.approvals.len() >= self.required_approvals {
+            true => Approval::Published(PublishedPost {
+                content: self.content,
+            }),
+            false => Approval::Pending(self),
         }
     }
+
+    pub fn approvals(&self) -> usize {
+        self.approvals.len()
+    }
 }
 
 pub struct PublishedPost {
@@ -57,20 +97,34 @@ impl PublishedPost {
 }
 
 pub fn run() {
-    println!("Creating a new post");
-    let mut post = Post::new();
+    println!("Creating a new post that needs two approvals");
+    let mut post = Post::with_required_approvals(2);
 
     println!("Adding text to the post");
     post.add_text("I ate a salad for lunch today.");
 
     println!("Requesting a review");
-    let post = post.request_review();
-
-    println!("Approving the post");
-    let post = post.approve();
-
-    println!("Publishing the post");
-    println!("Post content: {}", post.content());
+    let mut post = post.request_review();
+
+    // Every reviewer approves until one of the approvals publishes the post.
+    for reviewer in ["alice", "alice", "bob"] {
+        println!("Approving the post as {reviewer}");
+        post = match post.approve(reviewer) {
+            Approval::Pending(post) => {
+                println!("Approvals so far: {}", post.approvals());
+                post
+            }
+            Approval::Duplicate(post) => {
+                println!("{reviewer} already approved the post");
+                post
+            }
+            Approval::Published(post) => {
+                println!("Publishing the post");
+                println!("Post content: {}", post.content());
+                return;
+            }
+        };
+    }
 }
 
 /*
@@ -80,3 +134,59 @@ pub fn run() {
     If we want to work with a finished and approved post, we have to use the PublishedPost type.
     Using the Post type results in having a Post that is neither finished nor approved.
 */
+
+#[cfg(test)]
+mod tests {
+    use super::{Approval, PendingReviewPost, Post};
+
+    fn pending_post(required_approvals: usize) -> PendingReviewPost {
+        let mut post = Post::with_required_approvals(required_approvals);
+        post.add_text("Salad");
+        post.request_review()
+    }
+
+    #[test]
+    fn test_publishes_at_the_threshold() {
+        let post = match pending_post(2).approve("alice") {
+            Approval::Pending(post) => post,
+            _ => panic!("one of two approvals should keep the post pending"),
+        };
+        assert_eq!(post.approvals(), 1);
+
+        match post.approve("bob") {
+            Approval::Published(post) => assert_eq!(post.content(), "Salad"),
+            _ => panic!("two of two approvals should publish the post"),
+        }
+    }
+
+    #[test]
+    fn test_duplicate_approvals_are_reported() {
+        let post = match pending_post(2).approve("alice") {
+            Approval::Pending(post) => post,
+            _ => panic!("one of two approvals should keep the post pending"),
+        };
+
+        match post.approve("alice") {
+            Approval::Duplicate(post) => assert_eq!(post.approvals(), 1),
+            _ => panic!("a second approval of alice should be a duplicate"),
+        }
+    }
+
+    #[test]
+    fn test_new_post_needs_one_approval() {
+        let mut post = Post::new();
+        post.add_text("Salad");
+        assert!(matches!(
+            post.request_review().approve("alice"),
+            Approval::Published(_)
+        ));
+    }
+
+    #[test]
+    fn test_at_least_one_approval_is_required() {
+        assert!(matches!(
+            pending_post(0).approve("alice"),
+            Approval::Published(_)
+        ));
+    }
+}
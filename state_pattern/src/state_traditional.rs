@@ -1,20 +1,98 @@
+use std::{
+    collections::HashSet,
+    env,
+    error::Error,
+    fmt, fs, process,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use storage::{FilePostRepository, MemoryPostRepository, PostRepository};
+
+pub mod storage;
+
 /*
     We want to have posts.
     A post can hold some State or None.
     A post also has some content.
     When initializing a post, we want it to be in draft state and contain an empty string.
     Because the state field is private, there is no way around than creating a new post as a draft.
+
+    A post needs a number of approvals from different reviewers before it gets published.
+    This number is data of the states, not of the post: the draft knows it, and hands it to the pending review.
+
+    The history is data of the post though, as it outlives every single state.
 */
 pub struct Post {
     state: Option<Box<dyn State>>,
     content: String,
+    history: Vec<Transition>,
+}
+
+/*
+    One entry of the history of a post, recorded whenever the post changes its state.
+    Calls that leave the state as it is, like an approval that is not the last one needed, are not transitions.
+*/
+pub struct Transition {
+    pub from: &'static str,
+    pub to: &'static str,
+    pub actor: String,
+    pub reason: Option<String>, // Only given when rejecting
+    pub timestamp: SystemTime,
+}
+
+/*
+    Calling a method that makes no sense in the current state is an error, so callers can tell that nothing happened.
+    The post keeps its state in that case.
+*/
+#[derive(Debug, PartialEq)]
+pub enum TransitionError {
+    NotAllowed { state: &'static str, action: Action },
+    // The action is fine, but this reviewer has already approved.
+    DuplicateApproval { reviewer: String },
+}
+
+impl fmt::Display for TransitionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransitionError::NotAllowed { state, action } => {
+                let action = match action {
+                    Action::RequestReview => "request a review for",
+                    Action::Approve => "approve",
+                    Action::Reject => "reject",
+                };
+                write!(f, "Cannot {action} a post in state {state}")
+            }
+            TransitionError::DuplicateApproval { reviewer } => {
+                write!(f, "{reviewer} has already approved the post")
+            }
+        }
+    }
+}
+
+impl Error for TransitionError {}
+
+// The methods of a post that change its state, so errors can tell which one was called.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    RequestReview,
+    Approve,
+    Reject,
 }
 
 impl Post {
+    // A new post needs a single approval before it gets published.
     pub fn new() -> Post {
         Post {
-            state: Some(Box::new(Draft::new())),
+            state: Some(Box::new(Draft::new(1))),
             content: String::new(),
+            history: Vec::new(),
+        }
+    }
+
+    // At least one approval is always required, a post can not publish itself.
+    pub fn with_required_approvals(required_approvals: usize) -> Post {
+        Post {
+            state: Some(Box::new(Draft::new(required_approvals.max(1)))),
+            ..Post::new()
         }
     }
 
@@ -30,16 +108,68 @@ impl Post {
     }
 
     // In addition to methods that do not depend on the state, we also implement methods that change the state on the Post struct.
-    pub fn request_review(&mut self) {
-        if let Some(state) = self.state.take() {
-            self.state = Some(state.request_review());
-        }
+    pub fn request_review(&mut self, author: &str) -> Result<(), TransitionError> {
+        self.transition(author, None, |state| state.request_review())
+    }
+
+    // Every reviewer can approve a post only once.
+    pub fn approve(&mut self, reviewer: &str) -> Result<(), TransitionError> {
+        self.transition(reviewer, None, |state| state.approve(reviewer))
+    }
+
+    // Sends a post under review back to its author, who can change it and request a review again.
+    pub fn reject(&mut self, reviewer: &str, reason: &str) -> Result<(), TransitionError> {
+        self.transition(reviewer, Some(reason), |state| state.reject())
+    }
+
+    pub fn state_name(&self) -> &'static str {
+        self.state.as_ref().map_or("", |state| state.name())
     }
 
-    pub fn approve(&mut self) {
-        if let Some(state) = self.state.take() {
-            self.state = Some(state.approve());
+    // The transitions of the post, oldest first.
+    pub fn history(&self) -> &[Transition] {
+        &self.history
+    }
+
+    /*
+        The post itself decides what goes into the history, so the states do not have to know about it.
+        A transition is recorded when the state has a different name afterwards.
+    */
+    fn transition(
+        &mut self,
+        actor: &str,
+        reason: Option<&str>,
+        change: impl FnOnce(Box<dyn State>) -> Outcome,
+    ) -> Result<(), TransitionError> {
+        let Some(state) = self.state.take() else {
+            return Ok(());
+        };
+        let from = state.name();
+        let state = match change(state) {
+            Ok(state) => state,
+            Err((state, error)) => {
+                self.state = Some(state);
+                return Err(error);
+            }
+        };
+        let to = state.name();
+        self.state = Some(state);
+
+        if from != to {
+            self.history.push(Transition {
+                from,
+                to,
+                actor: actor.to_string(),
+                reason: reason.map(str::to_string),
+                timestamp: SystemTime::now(),
+            });
         }
+
+        Ok(())
+    }
+
+    pub fn approvals(&self) -> usize {
+        self.state.as_ref().map_or(0, |state| state.approvals())
     }
 
     // And we specifically implement methods that depend on the state on the Post struct.
@@ -59,8 +189,32 @@ impl Post {
     Behaviour defined in the State trait will be shared by all available states.
 */
 trait State {
-    fn request_review(self: Box<Self>) -> Box<dyn State>;
-    fn approve(self: Box<Self>) -> Box<dyn State>;
+    fn request_review(self: Box<Self>) -> Outcome;
+    fn approve(self: Box<Self>, reviewer: &str) -> Outcome;
+    fn reject(self: Box<Self>) -> Outcome;
+    fn name(&self) -> &'static str;
+
+    // Only callable on the concrete states, as a Box<dyn State> can not be turned into a Box<dyn State> again.
+    fn refuse(self: Box<Self>, action: Action) -> Outcome
+    where
+        Self: Sized + 'static,
+    {
+        let error = TransitionError::NotAllowed {
+            state: self.name(),
+            action,
+        };
+        Err((self, error))
+    }
+
+    // Only a pending review collects approvals.
+    fn approvals(&self) -> usize {
+        0
+    }
+
+    // The data of the state, as written by the storage module, which also knows how to restore each state from it.
+    fn fields(&self) -> Vec<String> {
+        Vec::new()
+    }
 
     // As content() is the same for all states except Published, we can implement a default behaviour.
     fn content<'a>(&self, _post: &'a Post) -> &'a str {
@@ -68,6 +222,18 @@ trait State {
     }
 }
 
+/*
+    The next state, or the current one together with the reason why it refused to change.
+    The state has to be handed back, as the methods took ownership of it.
+*/
+type Outcome = Result<Box<dyn State>, (Box<dyn State>, TransitionError)>;
+
+// What name() returns for each state. The storage module writes these names and looks them up when loading.
+const DRAFT: &str = "Draft";
+const PENDING_REVIEW: &str = "PendingReview";
+const PUBLISHED: &str = "Published";
+const STATE_NAMES: [&str; 3] = [DRAFT, PENDING_REVIEW, PUBLISHED];
+
 /*
     Here we implement the different states and their behaviours to switch between states.
 
@@ -82,43 +248,98 @@ trait State {
     The rust compiler enforces this behaviour.
     Try changing the signature to just "self" and see what happens.
 */
-struct Draft {}
+struct Draft {
+    required_approvals: usize,
+}
 
 impl Draft {
-    fn new() -> Draft {
-        Draft {}
+    fn new(required_approvals: usize) -> Draft {
+        Draft { required_approvals }
     }
 }
 
 impl State for Draft {
     // Drafts can be requested for review.
-    fn request_review(self: Box<Self>) -> Box<dyn State> {
-        Box::new(PendingReview::new())
+    fn request_review(self: Box<Self>) -> Outcome {
+        Ok(Box::new(PendingReview::new(self.required_approvals)))
     }
 
     // Drafts cannot be approved directly.
-    fn approve(self: Box<Self>) -> Box<dyn State> {
-        self
+    fn approve(self: Box<Self>, _reviewer: &str) -> Outcome {
+        self.refuse(Action::Approve)
+    }
+
+    // Drafts have not been reviewed yet, so there is nothing to reject.
+    fn reject(self: Box<Self>) -> Outcome {
+        self.refuse(Action::Reject)
+    }
+
+    fn name(&self) -> &'static str {
+        DRAFT
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![self.required_approvals.to_string()]
     }
 }
 
-struct PendingReview {}
+/*
+    This is where every state holding its own data pays off.
+    Only a pending review needs to know who approved it, so neither the post nor the other states carry the set.
+*/
+struct PendingReview {
+    approvals: HashSet<String>,
+    required_approvals: usize,
+}
 
 impl PendingReview {
-    fn new() -> PendingReview {
-        PendingReview {}
+    fn new(required_approvals: usize) -> PendingReview {
+        PendingReview {
+            approvals: HashSet::new(),
+            required_approvals,
+        }
     }
 }
 
 impl State for PendingReview {
-    // Requesting a review on a pending review leads to no change.
-    fn request_review(self: Box<Self>) -> Box<dyn State> {
-        self
+    // A review has already been requested.
+    fn request_review(self: Box<Self>) -> Outcome {
+        self.refuse(Action::RequestReview)
     }
 
-    // Pending reviews can be approved.
-    fn approve(self: Box<Self>) -> Box<dyn State> {
-        Box::new(Published::new())
+    // Pending reviews are published once enough different reviewers approved them.
+    // insert() returns false if the reviewer is already in the set, so a second approval does not count.
+    fn approve(mut self: Box<Self>, reviewer: &str) -> Outcome {
+        if !self.approvals.insert(reviewer.to_string()) {
+            let reviewer = reviewer.to_string();
+            return Err((self, TransitionError::DuplicateApproval { reviewer }));
+        }
+
+        match self.approvals.len() >= self.required_approvals {
+            true => Ok(Box::new(Published::new())),
+            false => Ok(self),
+        }
+    }
+
+    // Rejected posts go back to being drafts, and the next review starts without approvals.
+    fn reject(self: Box<Self>) -> Outcome {
+        Ok(Box::new(Draft::new(self.required_approvals)))
+    }
+
+    fn approvals(&self) -> usize {
+        self.approvals.len()
+    }
+
+    fn name(&self) -> &'static str {
+        PENDING_REVIEW
+    }
+
+    // Sorted, so saving the same post twice writes the same file.
+    fn fields(&self) -> Vec<String> {
+        let mut reviewers: Vec<String> = self.approvals.iter().cloned().collect();
+        reviewers.sort();
+        reviewers.insert(0, self.required_approvals.to_string());
+        reviewers
     }
 }
 
@@ -132,13 +353,22 @@ impl Published {
 
 impl State for Published {
     // Published posts cannot be requested for review.
-    fn request_review(self: Box<Self>) -> Box<dyn State> {
-        self
+    fn request_review(self: Box<Self>) -> Outcome {
+        self.refuse(Action::RequestReview)
     }
 
     // Published posts cannot be approved.
-    fn approve(self: Box<Self>) -> Box<dyn State> {
-        self
+    fn approve(self: Box<Self>, _reviewer: &str) -> Outcome {
+        self.refuse(Action::Approve)
+    }
+
+    // Published posts cannot be rejected anymore.
+    fn reject(self: Box<Self>) -> Outcome {
+        self.refuse(Action::Reject)
+    }
+
+    fn name(&self) -> &'static str {
+        PUBLISHED
     }
 
     // Published posts have content.
@@ -148,8 +378,8 @@ impl State for Published {
 }
 
 pub fn run() {
-    println!("Creating a new post");
-    let mut post = Post::new();
+    println!("Creating a new post that needs two approvals");
+    let mut post = Post::with_required_approvals(2);
 
     println!("Adding text to the post");
     post.add_text("I ate a salad for lunch today.");
@@ -159,11 +389,244 @@ pub fn run() {
     post.add_text(" It was delicious.");
     println!("Content: {}", post.content());
 
+    // Every step prints the state afterwards, or why the post refused to change it.
+    let report = |result: Result<(), TransitionError>, post: &Post| match result {
+        Ok(()) => println!("State: {}, content: {}", post.state_name(), post.content()),
+        Err(error) => println!("Error: {error}"),
+    };
+
+    println!("Approving the draft as alice");
+    report(post.approve("alice"), &post);
+
     println!("Requesting a review");
-    post.request_review();
-    println!("Content: {}", post.content());
+    report(post.request_review("carol"), &post);
 
-    println!("Approving the post");
-    post.approve();
-    println!("Content: {}", post.content());
+    println!("Rejecting the post as alice");
+    report(post.reject("alice", "Which salad?"), &post);
+
+    println!("Adding some more text and requesting a review again");
+    post.add_text(" A caesar salad.");
+    report(post.request_review("carol"), &post);
+
+    println!("Approving the post as alice");
+    report(post.approve("alice"), &post);
+
+    println!("Approving the post as alice again");
+    report(post.approve("alice"), &post);
+    println!("Approvals: {}", post.approvals());
+
+    println!("Approving the post as bob");
+    report(post.approve("bob"), &post);
+
+    println!("History:");
+    for transition in post.history() {
+        let seconds = transition
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let reason = transition.reason.as_deref().unwrap_or("-");
+        println!(
+            "  {seconds}: {} -> {} by {} ({reason})",
+            transition.from, transition.to, transition.actor
+        );
+    }
+
+    // Both repositories take the same calls, the state is restored from its name either way.
+    println!("Saving and loading the post");
+    let directory = env::temp_dir().join(format!("state_pattern_posts_{}", process::id()));
+    let mut files = FilePostRepository::new(directory.clone());
+    let mut memory = MemoryPostRepository::new();
+    for repository in [&mut files as &mut dyn PostRepository, &mut memory] {
+        match repository
+            .save("salad", &post)
+            .and_then(|()| repository.load("salad"))
+        {
+            Ok(loaded) => println!(
+                "Loaded a post in state {} with {} transitions",
+                loaded.state_name(),
+                loaded.history().len()
+            ),
+            Err(error) => println!("Error: {error}"),
+        }
+    }
+    let _ = fs::remove_dir_all(directory);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Action, Post, TransitionError};
+
+    type Step = fn(&mut Post) -> Result<(), TransitionError>;
+
+    fn pending_post(required_approvals: usize) -> Post {
+        let mut post = Post::with_required_approvals(required_approvals);
+        post.add_text("Salad");
+        post.request_review("carol").unwrap();
+        post
+    }
+
+    fn published_post() -> Post {
+        let mut post = pending_post(1);
+        post.approve("alice").unwrap();
+        post
+    }
+
+    fn not_allowed(state: &'static str, action: Action) -> Result<(), TransitionError> {
+        Err(TransitionError::NotAllowed { state, action })
+    }
+
+    #[test]
+    fn test_transition_matrix() {
+        let states = [
+            ("Draft", Post::new as fn() -> Post),
+            ("PendingReview", || pending_post(1)),
+            ("Published", published_post),
+        ];
+        // For every state and action: the result, and the state afterwards.
+        let expected = [
+            [
+                (Ok(()), "PendingReview"),
+                (not_allowed("Draft", Action::Approve), "Draft"),
+                (not_allowed("Draft", Action::Reject), "Draft"),
+            ],
+            [
+                (
+                    not_allowed("PendingReview", Action::RequestReview),
+                    "PendingReview",
+                ),
+                (Ok(()), "Published"),
+                (Ok(()), "Draft"),
+            ],
+            [
+                (not_allowed("Published", Action::RequestReview), "Published"),
+                (not_allowed("Published", Action::Approve), "Published"),
+                (not_allowed("Published", Action::Reject), "Published"),
+            ],
+        ];
+
+        for ((name, create), expected) in states.iter().zip(expected) {
+            assert_eq!(create().state_name(), *name);
+
+            let steps: [Step; 3] = [
+                |post| post.request_review("carol"),
+                |post| post.approve("bob"),
+                |post| post.reject("bob", "Too short"),
+            ];
+            for (step, (result, state)) in steps.iter().zip(expected) {
+                let mut post = create();
+                let history = post.history().len();
+                assert_eq!(step(&mut post), result, "from {name}");
+                assert_eq!(post.state_name(), state, "from {name}");
+                // Only a change of the state is recorded.
+                let changed = usize::from(state != *name);
+                assert_eq!(post.history().len(), history + changed);
+            }
+        }
+    }
+
+    #[test]
+    fn test_publishes_at_the_threshold() {
+        let mut post = pending_post(3);
+        post.approve("alice").unwrap();
+        post.approve("bob").unwrap();
+        assert_eq!(post.approvals(), 2);
+        assert_eq!(post.content(), "");
+
+        post.approve("carol").unwrap();
+        assert_eq!(post.content(), "Salad");
+    }
+
+    #[test]
+    fn test_duplicate_approvals_do_not_count() {
+        let mut post = pending_post(2);
+        post.approve("alice").unwrap();
+        assert_eq!(
+            post.approve("alice"),
+            Err(TransitionError::DuplicateApproval {
+                reviewer: "alice".to_string()
+            })
+        );
+        assert_eq!(post.approvals(), 1);
+        assert_eq!(post.state_name(), "PendingReview");
+    }
+
+    #[test]
+    fn test_new_post_needs_one_approval() {
+        let mut post = Post::new();
+        post.add_text("Salad");
+        post.request_review("carol").unwrap();
+        post.approve("alice").unwrap();
+        assert_eq!(post.content(), "Salad");
+    }
+
+    #[test]
+    fn test_at_least_one_approval_is_required() {
+        let mut post = Post::with_required_approvals(0);
+        post.add_text("Salad");
+        post.request_review("carol").unwrap();
+        assert_eq!(post.content(), "");
+        post.approve("alice").unwrap();
+        assert_eq!(post.content(), "Salad");
+    }
+
+    #[test]
+    fn test_reject_sends_the_post_back() {
+        let mut post = pending_post(2);
+        post.approve("alice").unwrap();
+        post.reject("bob", "Too short").unwrap();
+        assert_eq!(post.approvals(), 0);
+
+        // A rejected post can only be approved after another review was requested.
+        assert!(post.approve("alice").is_err());
+        post.request_review("carol").unwrap();
+        post.approve("alice").unwrap();
+        assert_eq!(post.approvals(), 1);
+        post.approve("bob").unwrap();
+        assert_eq!(post.content(), "Salad");
+    }
+
+    #[test]
+    fn test_history() {
+        let mut post = pending_post(1);
+        post.reject("alice", "Too short").unwrap();
+        assert!(post.reject("alice", "Still too short").is_err());
+        post.request_review("carol").unwrap();
+        post.approve("bob").unwrap();
+        assert!(post.approve("bob").is_err());
+
+        let history: Vec<_> = post
+            .history()
+            .iter()
+            .map(|t| (t.from, t.to, t.actor.as_str(), t.reason.as_deref()))
+            .collect();
+        assert_eq!(
+            history,
+            vec![
+                ("Draft", "PendingReview", "carol", None),
+                ("PendingReview", "Draft", "alice", Some("Too short")),
+                ("Draft", "PendingReview", "carol", None),
+                ("PendingReview", "Published", "bob", None),
+            ]
+        );
+        assert!(post
+            .history()
+            .windows(2)
+            .all(|pair| pair[0].timestamp <= pair[1].timestamp));
+    }
+
+    #[test]
+    fn test_error_messages() {
+        let mut post = published_post();
+        assert_eq!(
+            post.request_review("carol").unwrap_err().to_string(),
+            "Cannot request a review for a post in state Published"
+        );
+        let mut post = pending_post(2);
+        post.approve("alice").unwrap();
+        assert_eq!(
+            post.approve("alice").unwrap_err().to_string(),
+            "alice has already approved the post"
+        );
+    }
 }
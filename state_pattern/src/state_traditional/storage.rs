@@ -0,0 +1,499 @@
+/*
+    Saving and loading posts.
+
+    A post holds a Box<dyn State>, and a trait object can not be written to a file as it is.
+    So every state tells us its name and its data (State::fields), and when loading,
+    restore_state() matches on the name to build the right state again.
+    Apart from STATE_NAMES, this module is the one place that has to know every state (restore_state),
+    like the match statements the state pattern otherwise avoids.
+    It is a child module of state_traditional, so it can reach the private states.
+
+    Posts are stored as text, one line per entry and tab separated fields:
+
+        content	I ate a salad for lunch today.\nIt was delicious.
+        state	PendingReview	2	alice
+        transition	Draft	PendingReview	1700000000.000000000	carol
+        transition	PendingReview	Draft	1700000100.000000000	alice	Which salad?
+
+    Tabs, line breaks and backslashes inside fields are escaped with a backslash.
+    A transition has a sixth field only if it has a reason, and timestamps are seconds and nanoseconds since 1970.
+*/
+
+use super::{
+    Draft, PendingReview, Post, Published, State, Transition, DRAFT, PENDING_REVIEW, PUBLISHED,
+    STATE_NAMES,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt,
+    fs::{self, File},
+    io::{self, Write},
+    path::PathBuf,
+    time::{Duration, UNIX_EPOCH},
+};
+
+#[derive(Debug)]
+pub enum StorageError {
+    NotFound(String),
+    // Ids become file names, so they are restricted to letters, digits, '-' and '_'.
+    InvalidId(String),
+    Io(io::Error),
+    Invalid { line: usize, message: String },
+    // The post as a whole lacks an entry, so there is no line to point to.
+    Missing(&'static str),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StorageError::NotFound(id) => write!(f, "There is no post {id}"),
+            StorageError::InvalidId(id) => write!(f, "\"{id}\" is not a valid post id"),
+            StorageError::Io(error) => write!(f, "Error while accessing the posts: {error}"),
+            StorageError::Invalid { line, message } => {
+                write!(f, "Invalid post in line {line}: {message}")
+            }
+            StorageError::Missing(entry) => write!(f, "Invalid post: it has no {entry}"),
+        }
+    }
+}
+
+impl Error for StorageError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            StorageError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for StorageError {
+    fn from(error: io::Error) -> StorageError {
+        StorageError::Io(error)
+    }
+}
+
+/*
+    Code that stores posts should not care where they end up.
+    The program uses files, while tests use memory and do not have to clean up after themselves.
+*/
+pub trait PostRepository {
+    // Replaces the post saved under the same id.
+    fn save(&mut self, id: &str, post: &Post) -> Result<(), StorageError>;
+    fn load(&self, id: &str) -> Result<Post, StorageError>;
+}
+
+// Keeps every post in a file named after its id.
+pub struct FilePostRepository {
+    directory: PathBuf,
+}
+
+impl FilePostRepository {
+    pub fn new(directory: PathBuf) -> FilePostRepository {
+        FilePostRepository { directory }
+    }
+
+    fn path(&self, id: &str) -> Result<PathBuf, StorageError> {
+        check_id(id)?;
+        Ok(self.directory.join(format!("{id}.post")))
+    }
+}
+
+impl PostRepository for FilePostRepository {
+    // Writes to a temporary file first, so a crash while saving does not leave half a post behind.
+    fn save(&mut self, id: &str, post: &Post) -> Result<(), StorageError> {
+        let path = self.path(id)?;
+        let temporary = path.with_extension("post.tmp");
+
+        fs::create_dir_all(&self.directory)?;
+        let written = File::create(&temporary).and_then(|mut file| {
+            file.write_all(to_text(post).as_bytes())?;
+            // The content has to reach the disk before the rename makes it visible.
+            file.sync_all()
+        });
+        let renamed = written.and_then(|_| fs::rename(&temporary, &path));
+        if renamed.is_err() {
+            let _ = fs::remove_file(&temporary);
+        }
+        Ok(renamed?)
+    }
+
+    fn load(&self, id: &str) -> Result<Post, StorageError> {
+        match fs::read_to_string(self.path(id)?) {
+            Ok(text) => from_text(&text),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                Err(StorageError::NotFound(id.to_string()))
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+}
+
+// Stores the same text as the files, so tests go through the whole format.
+#[derive(Default)]
+pub struct MemoryPostRepository {
+    posts: HashMap<String, String>,
+}
+
+impl MemoryPostRepository {
+    pub fn new() -> MemoryPostRepository {
+        MemoryPostRepository::default()
+    }
+}
+
+impl PostRepository for MemoryPostRepository {
+    fn save(&mut self, id: &str, post: &Post) -> Result<(), StorageError> {
+        check_id(id)?;
+        self.posts.insert(id.to_string(), to_text(post));
+        Ok(())
+    }
+
+    fn load(&self, id: &str) -> Result<Post, StorageError> {
+        check_id(id)?;
+        match self.posts.get(id) {
+            Some(text) => from_text(text),
+            None => Err(StorageError::NotFound(id.to_string())),
+        }
+    }
+}
+
+fn check_id(id: &str) -> Result<(), StorageError> {
+    let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    match !id.is_empty() && id.chars().all(valid) {
+        true => Ok(()),
+        false => Err(StorageError::InvalidId(id.to_string())),
+    }
+}
+
+pub fn to_text(post: &Post) -> String {
+    let mut lines = vec![line(&["content", &post.content])];
+
+    if let Some(state) = &post.state {
+        let mut fields = vec!["state".to_string(), state.name().to_string()];
+        fields.extend(state.fields());
+        lines.push(line(&fields));
+    }
+
+    for transition in &post.history {
+        let since_epoch = transition
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let timestamp = format!(
+            "{}.{:09}",
+            since_epoch.as_secs(),
+            since_epoch.subsec_nanos()
+        );
+        let mut fields = vec![
+            "transition",
+            transition.from,
+            transition.to,
+            &timestamp,
+            &transition.actor,
+        ];
+        fields.extend(transition.reason.as_deref());
+        lines.push(line(&fields));
+    }
+
+    lines.join("\n") + "\n"
+}
+
+pub fn from_text(text: &str) -> Result<Post, StorageError> {
+    let mut content = None;
+    let mut state = None;
+    let mut history = Vec::new();
+
+    for (index, text) in text.lines().enumerate() {
+        let invalid = |message: String| StorageError::Invalid {
+            line: index + 1,
+            message,
+        };
+        let fields: Vec<String> = text.split('\t').map(unescape).collect();
+
+        match fields[0].as_str() {
+            "content" if fields.len() == 2 && content.is_none() => {
+                content = Some(fields[1].clone());
+            }
+            "state" if fields.len() >= 2 && state.is_none() => {
+                state = Some(restore_state(&fields[1], &fields[2..]).map_err(invalid)?);
+            }
+            "transition" if fields.len() == 5 || fields.len() == 6 => {
+                history.push(restore_transition(&fields).map_err(invalid)?);
+            }
+            _ => return Err(invalid(format!("unexpected entry \"{}\"", fields[0]))),
+        }
+    }
+
+    Ok(Post {
+        state: Some(state.ok_or(StorageError::Missing("state"))?),
+        content: content.ok_or(StorageError::Missing("content"))?,
+        history,
+    })
+}
+
+fn restore_state(name: &str, fields: &[String]) -> Result<Box<dyn State>, String> {
+    match (name, fields) {
+        (DRAFT, [required]) => Ok(Box::new(Draft::new(required_approvals(required)?))),
+        (PENDING_REVIEW, [required, reviewers @ ..]) => {
+            let required_approvals = required_approvals(required)?;
+            // Enough approvals would have published the post.
+            if reviewers.len() >= required_approvals {
+                return Err(format!(
+                    "{} approvals of {required_approvals} required ones",
+                    reviewers.len()
+                ));
+            }
+            // Every reviewer approves only once, so a name that appears twice means the file was changed by hand.
+            let mut approvals = HashSet::new();
+            if let Some(reviewer) = reviewers
+                .iter()
+                .find(|reviewer| !approvals.insert(reviewer.to_string()))
+            {
+                return Err(format!("{reviewer} approved more than once"));
+            }
+            Ok(Box::new(PendingReview {
+                approvals,
+                required_approvals,
+            }))
+        }
+        (PUBLISHED, []) => Ok(Box::new(Published::new())),
+        _ => Err(format!("unknown state {name} with {} fields", fields.len())),
+    }
+}
+
+// The history refers to states by the &'static str their name() returns, so a loaded name is looked up among them.
+fn state_name(name: &str) -> Option<&'static str> {
+    STATE_NAMES.into_iter().find(|known| *known == name)
+}
+
+fn required_approvals(field: &str) -> Result<usize, String> {
+    match field.parse::<usize>() {
+        Ok(required) if required > 0 => Ok(required),
+        _ => Err(format!("\"{field}\" is not a number of approvals")),
+    }
+}
+
+fn restore_transition(fields: &[String]) -> Result<Transition, String> {
+    let state_name = |name: &str| state_name(name).ok_or(format!("unknown state {name}"));
+    let (seconds, nanoseconds) = fields[3]
+        .split_once('.')
+        .and_then(|(seconds, nanoseconds)| Some((seconds.parse().ok()?, nanoseconds.parse().ok()?)))
+        .filter(|(_, nanoseconds)| *nanoseconds < 1_000_000_000)
+        .ok_or(format!("\"{}\" is not a timestamp", fields[3]))?;
+
+    Ok(Transition {
+        from: state_name(&fields[1])?,
+        to: state_name(&fields[2])?,
+        actor: fields[4].clone(),
+        reason: fields.get(5).cloned(),
+        timestamp: UNIX_EPOCH + Duration::new(seconds, nanoseconds),
+    })
+}
+
+fn line<S: AsRef<str>>(fields: &[S]) -> String {
+    fields
+        .iter()
+        .map(|field| escape(field.as_ref()))
+        .collect::<Vec<String>>()
+        .join("\t")
+}
+
+fn escape(field: &str) -> String {
+    let mut escaped = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Unknown escapes keep their backslash, so nothing is lost.
+fn unescape(field: &str) -> String {
+    let mut unescaped = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some('\\') => unescaped.push('\\'),
+            Some(other) => {
+                unescaped.push('\\');
+                unescaped.push(other);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        from_text, restore_state, state_name, to_text, FilePostRepository, MemoryPostRepository,
+        PostRepository, StorageError,
+    };
+    use crate::state_traditional::{Post, STATE_NAMES};
+    use std::{env, fs, process};
+
+    fn reviewed_post() -> Post {
+        let mut post = Post::with_required_approvals(3);
+        post.add_text("Salad\twith\\dressing\nand bread");
+        post.request_review("carol").unwrap();
+        post.reject("alice", "Which\nsalad?").unwrap();
+        post.request_review("carol").unwrap();
+        post.approve("bob").unwrap();
+        post.approve("alice").unwrap();
+        post
+    }
+
+    fn history(post: &Post) -> Vec<String> {
+        post.history()
+            .iter()
+            .map(|t| {
+                format!(
+                    "{} {} {} {:?} {:?}",
+                    t.from, t.to, t.actor, t.reason, t.timestamp
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let post = reviewed_post();
+        let mut loaded = from_text(&to_text(&post)).unwrap();
+
+        assert_eq!(to_text(&loaded), to_text(&post));
+        assert_eq!(loaded.state_name(), "PendingReview");
+        assert_eq!(loaded.approvals(), 2);
+        assert_eq!(history(&loaded), history(&post));
+
+        // The restored state still behaves like one.
+        assert!(loaded.approve("bob").is_err());
+        loaded.approve("dave").unwrap();
+        assert_eq!(loaded.content(), "Salad\twith\\dressing\nand bread");
+    }
+
+    #[test]
+    fn test_format() {
+        let mut post = Post::new();
+        post.add_text("a\tb");
+        assert_eq!(to_text(&post), "content\ta\\tb\nstate\tDraft\t1\n");
+
+        post.request_review("carol").unwrap();
+        post.approve("bob").unwrap();
+        let text = to_text(&post);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[1], "state\tPublished");
+        assert!(lines[2].starts_with("transition\tDraft\tPendingReview\t"));
+        assert!(lines[3].ends_with("\tbob"));
+    }
+
+    #[test]
+    fn test_invalid_posts() {
+        let error = |text: &str| from_text(text).err().unwrap().to_string();
+
+        assert_eq!(
+            error("content\tx\nstate\tArchived"),
+            "Invalid post in line 2: unknown state Archived with 0 fields"
+        );
+        assert_eq!(
+            error("content\tx\nstate\tPendingReview\t1\talice"),
+            "Invalid post in line 2: 1 approvals of 1 required ones"
+        );
+        assert_eq!(
+            error("content\tx\nstate\tPendingReview\t3\talice\talice"),
+            "Invalid post in line 2: alice approved more than once"
+        );
+        assert_eq!(
+            error("content\tx\nstate\tDraft\t0"),
+            "Invalid post in line 2: \"0\" is not a number of approvals"
+        );
+        assert_eq!(
+            error("content\tx\nstate\tDraft\t1\ntransition\tDraft\tDone\t1.0\tbob"),
+            "Invalid post in line 3: unknown state Done"
+        );
+        assert_eq!(
+            error("content\tx\nstate\tDraft\t1\ntransition\tDraft\tPublished\tnow\tbob"),
+            "Invalid post in line 3: \"now\" is not a timestamp"
+        );
+        assert_eq!(error("content\tx"), "Invalid post: it has no state");
+        assert_eq!(error(""), "Invalid post: it has no state");
+        assert_eq!(error("state\tPublished"), "Invalid post: it has no content");
+        assert_eq!(
+            error("content\tx\ncontent\ty"),
+            "Invalid post in line 2: unexpected entry \"content\""
+        );
+    }
+
+    #[test]
+    fn test_state_names_round_trip() {
+        // Walks a post through every state, so a state missing from STATE_NAMES is noticed.
+        let mut post = Post::new();
+        let mut names = Vec::new();
+        let mut check = |post: &Post| {
+            let state = post.state.as_ref().unwrap();
+            let restored = restore_state(state.name(), &state.fields()).unwrap();
+            assert_eq!(restored.name(), state.name());
+            assert_eq!(state_name(state.name()), Some(state.name()));
+            names.push(state.name());
+        };
+
+        check(&post);
+        post.request_review("carol").unwrap();
+        check(&post);
+        post.approve("bob").unwrap();
+        check(&post);
+
+        assert_eq!(names, STATE_NAMES);
+        assert_eq!(state_name("Archived"), None);
+    }
+
+    #[test]
+    fn test_memory_repository() {
+        let mut repository = MemoryPostRepository::new();
+        repository.save("salad", &reviewed_post()).unwrap();
+
+        assert_eq!(repository.load("salad").unwrap().approvals(), 2);
+        assert!(matches!(
+            repository.load("soup"),
+            Err(StorageError::NotFound(_))
+        ));
+        assert!(matches!(
+            repository.save("../salad", &Post::new()),
+            Err(StorageError::InvalidId(_))
+        ));
+    }
+
+    #[test]
+    fn test_file_repository() {
+        let directory = env::temp_dir().join(format!("state_pattern_test_posts_{}", process::id()));
+        let mut repository = FilePostRepository::new(directory.clone());
+
+        let post = reviewed_post();
+        repository.save("salad", &post).unwrap();
+        let loaded = repository.load("salad").unwrap();
+        assert_eq!(to_text(&loaded), to_text(&post));
+        assert!(matches!(
+            repository.load("soup"),
+            Err(StorageError::NotFound(_))
+        ));
+
+        // Saving again replaces the post.
+        repository.save("salad", &Post::new()).unwrap();
+        assert_eq!(repository.load("salad").unwrap().state_name(), "Draft");
+        assert_eq!(fs::read_dir(&directory).unwrap().count(), 1);
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+}